@@ -0,0 +1,341 @@
+//! `ectt check`, validates the configuration and goes through connecting to its servers
+//! one step at a time, so it's clear which one fails.
+
+use std::{
+    io,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools;
+use serde_json::Value;
+
+use crate::{
+    config::{self, Config},
+    imap::{
+        self,
+        config::{ImapConfig, ReadBackend},
+        state::{capability_names, UnauthenticatedState},
+    },
+    smtp::{
+        self,
+        config::{SendBackend, SmtpConfig},
+    },
+    tls::{TlsConfig, TlsMode},
+};
+
+/// How long to wait for each connection.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configuration keys holding passwords and tokens, they're never printed.
+const SECRET_KEYS: [&str; 4] = ["raw", "access_token", "refresh_token", "client_secret"];
+
+/// Prints the outcome of every step, with the secrets from the configuration redacted.
+#[derive(Default)]
+struct Report {
+    secrets: Vec<String>,
+    failed: usize,
+}
+
+impl Report {
+    fn redact(&self, text: &str) -> String {
+        self.secrets.iter().fold(text.to_string(), |text, secret| {
+            text.replace(secret, "<redacted>")
+        })
+    }
+
+    fn section(&self, title: &str) {
+        println!("\n{}", self.redact(title));
+    }
+
+    fn print(&self, status: &str, line: &str) {
+        println!("  {status:<5} {}", self.redact(line));
+    }
+
+    /// Print how a step went, returning its value if it passed.
+    fn step<T>(
+        &mut self,
+        name: &str,
+        result: Result<T, crate::Error>,
+        details: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => {
+                let details = details(&value);
+                if details.is_empty() {
+                    self.print("ok", name);
+                } else {
+                    self.print("ok", &format!("{name}: {details}"));
+                }
+                Some(value)
+            }
+            Err(err) => {
+                self.failed += 1;
+                self.print("FAIL", &format!("{name}: {err}"));
+                None
+            }
+        }
+    }
+
+    fn skip(&self, name: &str, reason: &str) {
+        self.print("skip", &format!("{name}: {reason}"));
+    }
+}
+
+fn collect_secrets(value: &Value, secrets: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match value {
+                    Value::String(secret) if SECRET_KEYS.contains(&key.as_str()) => {
+                        if !secret.is_empty() {
+                            secrets.push(secret.clone());
+                        }
+                    }
+                    _ => collect_secrets(value, secrets),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_secrets(item, secrets);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Check the configuration at `path` and the servers it points to, printing a report.
+///
+/// Returns whether every step passed.
+pub fn run(path: &Path) -> bool {
+    let mut report = Report::default();
+    report.section(&format!("Configuration {}", path.display()));
+    let Some(value) = report.step("Parse", config::read_value(path), |_| String::new()) else {
+        return false;
+    };
+    check(&mut report, &value)
+}
+
+/// Same as [`run`], for a configuration which isn't saved yet.
+pub fn configuration(value: &Value) -> bool {
+    let mut report = Report::default();
+    report.section("Configuration");
+    check(&mut report, value)
+}
+
+fn check(report: &mut Report, value: &Value) -> bool {
+    collect_secrets(value, &mut report.secrets);
+    let Some(config) = report.step("Validate", Config::from_value(value), |_| String::new()) else {
+        return false;
+    };
+
+    match config.read {
+        ReadBackend::Imap(config) => check_imap(report, config),
+        ReadBackend::Maildir(_) => skip_section(report, "Read: Maildir"),
+        ReadBackend::Mbox(_) => skip_section(report, "Read: mbox"),
+        ReadBackend::Jmap(_) => skip_section(report, "Read: JMAP"),
+        ReadBackend::Pop3(_) => skip_section(report, "Read: POP3"),
+    }
+    match config.send {
+        SendBackend::Smtp(config) => check_smtp(report, config),
+        SendBackend::Jmap(_) => skip_section(report, "Send: JMAP"),
+        SendBackend::Sendmail(_) => skip_section(report, "Send: sendmail"),
+        SendBackend::File(_) => skip_section(report, "Send: file"),
+    }
+
+    if report.failed == 0 {
+        println!("\nEverything passed");
+    } else {
+        println!("\n{} step(s) failed", report.failed);
+    }
+    report.failed == 0
+}
+
+fn skip_section(report: &Report, title: &str) {
+    report.section(title);
+    report.skip("Connect", "only IMAP and SMTP servers are checked");
+}
+
+fn resolve(host: &str, port: u16) -> Result<Vec<SocketAddr>, crate::Error> {
+    let addresses = (host, port).to_socket_addrs()?.collect::<Vec<_>>();
+    if addresses.is_empty() {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{host} has no addresses"),
+        ))?
+    }
+    Ok(addresses)
+}
+
+/// Connect to the first address which accepts, like the backends do.
+fn connect(addresses: &[SocketAddr]) -> Result<(SocketAddr, TcpStream, Duration), crate::Error> {
+    let mut error = io::Error::from(io::ErrorKind::AddrNotAvailable);
+    for address in addresses {
+        let start = Instant::now();
+        match TcpStream::connect_timeout(address, TIMEOUT) {
+            Ok(tcp) => return Ok((*address, tcp, start.elapsed())),
+            Err(err) => error = err,
+        }
+    }
+    Err(error.into())
+}
+
+fn handshake(tls: &TlsConfig, host: &str, tcp: TcpStream) -> Result<String, crate::Error> {
+    let mut stream = tls.connect(host, tcp)?;
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+    let mut details = format!(
+        "{:?}, {:?}",
+        stream
+            .conn
+            .protocol_version()
+            .ok_or(rustls::Error::HandshakeNotComplete)?,
+        stream
+            .conn
+            .negotiated_cipher_suite()
+            .ok_or(rustls::Error::HandshakeNotComplete)?
+            .suite()
+    );
+    if tls.danger_accept_invalid_certs {
+        details.push_str(", the certificate was NOT verified (danger_accept_invalid_certs)");
+    }
+    Ok(details)
+}
+
+/// Resolve the host, connect and, with implicit TLS, handshake.
+fn reach(report: &mut Report, host: &str, port: u16, tls: &TlsConfig) -> bool {
+    let resolved = report.step(
+        &format!("Resolve {host}"),
+        resolve(host, port),
+        |addresses| addresses.iter().map(SocketAddr::ip).unique().join(", "),
+    );
+    let Some(addresses) = resolved else {
+        return false;
+    };
+    let connected = report.step("Connect", connect(&addresses), |(address, _, elapsed)| {
+        format!("{address} in {}ms", elapsed.as_millis())
+    });
+    let Some((_, tcp, _)) = connected else {
+        return false;
+    };
+    match tls.mode {
+        TlsMode::Implicit => report
+            .step("TLS handshake", handshake(tls, host, tcp), String::clone)
+            .is_some(),
+        TlsMode::StartTls => {
+            report.skip("TLS handshake", "done after STARTTLS, below");
+            true
+        }
+        TlsMode::None => {
+            report.skip(
+                "TLS handshake",
+                "tls is none, the connection isn't encrypted",
+            );
+            true
+        }
+    }
+}
+
+fn check_imap(report: &mut Report, config: ImapConfig) {
+    report.section(&format!(
+        "Read: IMAP {} at {}:{}",
+        config.login, config.host, config.port
+    ));
+    if !reach(report, &config.host, config.port, &config.tls) {
+        return;
+    }
+
+    let greeting = match config.tls.mode {
+        TlsMode::StartTls => "IMAP greeting and STARTTLS",
+        TlsMode::Implicit | TlsMode::None => "IMAP greeting",
+    };
+    let command = match config.auth {
+        imap::config::Auth::Password(_) => "IMAP LOGIN",
+        imap::config::Auth::OAuth(_) => "IMAP AUTHENTICATE XOAUTH2",
+    };
+    let Some(mut state) = report.step(greeting, UnauthenticatedState::new(config), |_| {
+        String::new()
+    }) else {
+        return;
+    };
+    let capabilities = state
+        .client
+        .capabilities()
+        .map(|capabilities| capability_names(&capabilities));
+    report.step(
+        "IMAP CAPABILITY",
+        capabilities.map_err(Into::into),
+        |names| names.join(" "),
+    );
+
+    let authenticated = state.authenticate().map_err(|(err, _)| err);
+    if let Some(mut session) = report.step(command, authenticated, |_| String::new()) {
+        report.step(
+            "IMAP CAPABILITY after login",
+            session.capabilities(),
+            |names| names.join(" "),
+        );
+    }
+}
+
+fn check_smtp(report: &mut Report, config: SmtpConfig) {
+    report.section(&format!(
+        "Send: SMTP {} at {}:{}",
+        config.login, config.host, config.port
+    ));
+    if !reach(report, &config.host, config.port, &config.tls) {
+        return;
+    }
+
+    let greeting = match config.tls.mode {
+        TlsMode::StartTls => "SMTP STARTTLS and EHLO",
+        TlsMode::Implicit | TlsMode::None => "SMTP EHLO",
+    };
+    let hello = smtp::Client::new(config).and_then(|client| Ok((client.extensions()?, client)));
+    let Some((_, mut client)) =
+        report.step(greeting, hello, |(extensions, _)| extensions.join(", "))
+    else {
+        return;
+    };
+    report.step("SMTP AUTH", client.login(), |mechanism| {
+        format!("authenticated with {}", mechanism.name())
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{collect_secrets, Report};
+
+    #[test]
+    fn secrets_are_redacted() {
+        let config = json!({
+            "read": {
+                "type": "imap",
+                "login": "jose@example.com",
+                "auth": { "type": "password", "raw": "hunter2" }
+            },
+            "send": {
+                "type": "smtp",
+                "login": "jose@example.com",
+                "auth": {
+                    "type": "oauth",
+                    "client_secret": "client-secret",
+                    "access_token": "access-token",
+                    "refresh_token": "",
+                }
+            }
+        });
+        let mut report = Report::default();
+        collect_secrets(&config, &mut report.secrets);
+        assert_eq!(report.secrets, ["hunter2", "access-token", "client-secret"]);
+        assert_eq!(
+            report.redact("jose@example.com rejected hunter2 and access-token"),
+            "jose@example.com rejected <redacted> and <redacted>"
+        );
+    }
+}
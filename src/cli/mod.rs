@@ -1,11 +1,23 @@
 use std::path::PathBuf;
 
+use lettre::Address;
+
+pub mod output;
+
 #[derive(Debug, Clone, clap::Parser)]
 pub struct App {
     #[command(subcommand)]
     pub command: Command,
 }
 
+/// The `--config` of the commands which read the configuration.
+#[derive(Debug, Clone, clap::Args)]
+pub struct ConfigArg {
+    /// Path to the configuration file (JSON, TOML or YAML, by its extension).
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone, clap::Subcommand)]
 pub enum Command {
     /// Utility to refresh OAuth tokens (only Gmail is currently supported).
@@ -13,12 +25,156 @@ pub enum Command {
     Login {
         #[arg(default_value_t = crate::oauth::Provider::Gmail)]
         provider: crate::oauth::Provider,
+
+        /// Port for the redirect server, it always binds to the loopback interface (0 picks a random port).
+        #[arg(long, default_value_t = 0)]
+        port: u16,
+
+        /// How long to wait for the authorization to complete, in seconds.
+        #[arg(long, default_value_t = crate::oauth::DEFAULT_REDIRECT_TIMEOUT.as_secs())]
+        timeout: u64,
     },
 
     /// Run the eCTT TUI.
     Run {
-        /// Path to the configuration file (only JSON is supported).
+        #[command(flatten)]
+        config: ConfigArg,
+
+        /// Write outgoing mail as `.eml` files to DIR instead of sending it
+        /// (defaults to a directory under the system's temporary directory).
+        #[arg(long, value_name = "DIR")]
+        dry_run: Option<Option<PathBuf>>,
+    },
+
+    /// Set eCTT up, looking the server settings up from the email address.
+    Init {
+        /// Where to write the configuration, defaults to `<OS configuration folder>/ectt/config.json`.
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+
+    /// Import an account from himalaya's TOML configuration.
+    ImportHimalaya {
+        /// himalaya's configuration, defaults to `<OS configuration folder>/himalaya/config.toml`.
+        #[arg(long)]
+        from: Option<PathBuf>,
+
+        /// The account to import, defaults to the one marked `default = true`.
+        #[arg(long)]
+        account: Option<String>,
+
+        /// Where to write the configuration, in the format of its extension, defaults to the
+        /// existing one or `<OS configuration folder>/ectt/config.toml`.
         #[arg(long)]
         config: Option<PathBuf>,
+
+        /// Overwrite an existing configuration.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Check the configuration and try connecting to the servers in it, step by step.
+    ///
+    /// Exits with 1 if any step fails.
+    Check {
+        #[command(flatten)]
+        config: ConfigArg,
+    },
+
+    /// Export an IMAP mailbox, or the messages matching a search, to an mbox file.
+    Export {
+        #[command(flatten)]
+        config: ConfigArg,
+
+        #[arg(long, default_value = crate::store::INBOX)]
+        mailbox: String,
+
+        /// IMAP SEARCH query, e.g. `FROM "jose@example.com" SINCE 1-Jan-2024`.
+        #[arg(long, default_value = "ALL")]
+        search: String,
+
+        /// Where to write the mbox file, it is overwritten if it exists.
+        output: PathBuf,
+    },
+
+    /// Send a message through the configured SMTP account, the body is read from stdin.
+    ///
+    /// Exits with 75 when sending might work later (e.g. the server can't be reached),
+    /// 69 when it won't (e.g. the message was rejected) and 1 for anything wrong before
+    /// sending (e.g. the configuration).
+    Send {
+        #[command(flatten)]
+        config: ConfigArg,
+
+        /// Can be repeated, as can `--cc` and `--bcc`.
+        #[arg(long)]
+        to: Vec<Address>,
+
+        #[arg(long)]
+        cc: Vec<Address>,
+
+        #[arg(long)]
+        bcc: Vec<Address>,
+
+        #[arg(long)]
+        subject: Option<String>,
+
+        /// File to attach, can be repeated.
+        #[arg(long, value_name = "FILE")]
+        attach: Vec<PathBuf>,
+
+        /// Read the body from FILE instead of stdin.
+        #[arg(long, value_name = "FILE")]
+        body_file: Option<PathBuf>,
+
+        /// Read a complete RFC 5322 message from stdin and send it as is,
+        /// to the recipients in its headers.
+        #[arg(long, conflicts_with_all = ["to", "cc", "bcc", "subject", "attach", "body_file"])]
+        raw: bool,
+    },
+
+    /// List the newest messages in an IMAP mailbox, one per line.
+    List {
+        #[command(flatten)]
+        config: ConfigArg,
+
+        #[arg(long, default_value = crate::store::INBOX)]
+        mailbox: String,
+
+        /// How many messages to list at most.
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+
+        /// Only list messages which haven't been read yet.
+        #[arg(long)]
+        unseen: bool,
+
+        /// Print a JSON object per message instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print a message from an IMAP mailbox, without marking it as read.
+    Read {
+        #[command(flatten)]
+        config: ConfigArg,
+
+        #[arg(long, default_value = crate::store::INBOX)]
+        mailbox: String,
+
+        /// UID of the message, as shown by `ectt list`.
+        uid: u32,
+
+        /// Print the message exactly as stored, headers and MIME parts included.
+        #[arg(long, conflicts_with_all = ["text", "json"])]
+        raw: bool,
+
+        /// Print the main headers and the text body (the default).
+        #[arg(long, conflicts_with = "json")]
+        text: bool,
+
+        /// Print the message as a JSON object.
+        #[arg(long)]
+        json: bool,
     },
 }
@@ -0,0 +1,76 @@
+//! How `ectt list` and `ectt read` print messages for people, scripts get JSON instead.
+
+use chrono::Local;
+
+use crate::store::ParsedEmail;
+
+const FROM_WIDTH: usize = 32;
+
+/// A line of `ectt list`, unread messages are marked with a `*`.
+pub fn row(email: &ParsedEmail) -> String {
+    let seen = if email.is_seen() { ' ' } else { '*' };
+    let date = email.date.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+    format!(
+        "{:>6} {seen} {date}  {:<FROM_WIDTH$.FROM_WIDTH$}  {}",
+        email.uid, email.from, email.subject
+    )
+}
+
+/// A message as printed by `ectt read`, its main headers followed by the body.
+pub fn text(email: &ParsedEmail) -> String {
+    let mut text = format!(
+        "From: {}\nDate: {}\n",
+        email.from,
+        email.date.with_timezone(&Local).to_rfc2822()
+    );
+    if !email.cc.is_empty() {
+        text.push_str(&format!("Cc: {}\n", email.cc.join(", ")));
+    }
+    text.push_str(&format!("Subject: {}\n\n{}", email.subject, email.body));
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::DateTime;
+
+    use crate::store::{Flag, ParsedEmail};
+
+    use super::{row, text};
+
+    fn email(flags: Vec<Flag>) -> ParsedEmail {
+        ParsedEmail {
+            uid: 42,
+            date: DateTime::UNIX_EPOCH,
+            from: "A very long sender name (someone@example.com)".to_string(),
+            cc: vec![
+                "duarte@example.com".to_string(),
+                "jose@example.com".to_string(),
+            ],
+            bcc: vec![],
+            subject: "Lunch".to_string(),
+            body: "Tomorrow?".to_string(),
+            flags,
+        }
+    }
+
+    #[test]
+    fn messages_are_printed() {
+        let unseen = row(&email(vec![]));
+        assert!(unseen.starts_with("    42 * "));
+        assert!(unseen.ends_with("  A very long sender name (someone  Lunch"));
+        assert!(row(&email(vec![Flag::Seen])).starts_with("    42   "));
+
+        let text = text(&email(vec![]));
+        assert!(text.starts_with("From: A very long sender name (someone@example.com)\nDate: "));
+        assert!(text
+            .ends_with("Cc: duarte@example.com, jose@example.com\nSubject: Lunch\n\nTomorrow?\n"));
+
+        let json = serde_json::to_value(email(vec![Flag::Seen])).unwrap();
+        assert_eq!(json["date"], "1970-01-01T00:00:00Z");
+        assert_eq!(json["flags"], serde_json::json!(["Seen"]));
+    }
+}
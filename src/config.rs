@@ -1,16 +1,57 @@
 use std::{
-    fs::OpenOptions,
+    fs::{self, OpenOptions},
+    io::Write,
     path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
 };
 
-use serde::Deserialize;
+#[cfg(feature = "refresher")]
+use oauth2::{AccessToken, ClientId, RefreshToken};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+use serde_path_to_error::Segment;
 
-use crate::{imap::config::ReadBackend, smtp::config::SendBackend};
+use crate::{
+    file::config::FileConfig,
+    imap::config::{ImapConfig, ReadBackend},
+    jmap::config::JmapConfig,
+    maildir::config::MaildirConfig,
+    mbox::config::MboxConfig,
+    pop3::config::Pop3Config,
+    sendmail::config::SendmailConfig,
+    smtp::config::{SendBackend, SmtpConfig},
+    tui::{
+        keymap::Keymap,
+        theme::{ColorDepth, Theme},
+    },
+};
 
 pub fn ectt_config_dir() -> Option<PathBuf> {
     dirs::config_dir().map(|dir| dir.join("ectt"))
 }
 
+/// Where what we change ourselves lives, apart from the configuration the user writes.
+pub fn ectt_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("ectt"))
+}
+
+/// Tokens obtained by logging in again, by client ID, they take precedence over the ones in
+/// the configuration.
+pub fn tokens_path() -> Option<PathBuf> {
+    ectt_data_dir().map(|dir| dir.join("tokens.json"))
+}
+
+/// File names the configuration is looked for under, in order.
+pub const CONFIG_FILES: [&str; 4] = ["config.json", "config.toml", "config.yaml", "config.yml"];
+
+/// The first of [`CONFIG_FILES`] which exists in `dir`.
+pub fn find_config(dir: &Path) -> Option<PathBuf> {
+    CONFIG_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+}
+
 pub fn get_config_path<P>(path: Option<P>) -> Result<PathBuf, crate::Error>
 where
     P: AsRef<Path>,
@@ -20,21 +61,20 @@ where
     };
 
     if let Some(dir) = ectt_config_dir() {
-        let config_file = dir.join("config.json");
-        if config_file.exists() {
+        if let Some(config_file) = find_config(&dir) {
             return Ok(config_file);
         }
-        tracing::warn!("Missing configuration file at: {}", config_file.display());
+        tracing::warn!("Missing configuration file in: {}", dir.display());
     }
 
-    let config_file = std::env::current_dir()?.join("config.json");
-    if config_file.exists() {
+    let dir = std::env::current_dir()?;
+    if let Some(config_file) = find_config(&dir) {
         return Ok(config_file);
     }
 
     tracing::error!(
-        "Failed to find a configuration file at the fallback path: {}",
-        config_file.display()
+        "Failed to find a configuration file in the fallback directory: {}",
+        dir.display()
     );
     Err(std::io::Error::new(
         std::io::ErrorKind::NotFound,
@@ -42,10 +82,159 @@ where
     ))?
 }
 
+/// How the configuration is written, picked by the file's extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// `.toml`, `.yaml` and `.yml` files, anything else is read as JSON.
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Format::Toml,
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => Format::Json,
+        }
+    }
+
+    pub fn parse(self, raw: &str) -> Result<Value, crate::Error> {
+        Ok(match self {
+            Format::Json => serde_json::from_str(raw)?,
+            Format::Toml => toml::from_str(raw)?,
+            Format::Yaml => serde_yaml_ng::from_str(raw)?,
+        })
+    }
+
+    pub fn write(self, value: &Value) -> Result<String, crate::Error> {
+        Ok(match self {
+            Format::Json => serde_json::to_string_pretty(value)? + "\n",
+            Format::Toml => toml::to_string_pretty(value)?,
+            Format::Yaml => serde_yaml_ng::to_string(value)?,
+        })
+    }
+}
+
+/// Read the configuration at `path` as it's written, without checking it, but with the tokens
+/// of a later login (see [`tokens_path`]) in place of the written ones.
+pub fn read_value(path: &Path) -> Result<Value, crate::Error> {
+    let mut value = Format::of(path).parse(&fs::read_to_string(path)?)?;
+    if let Some(tokens) = tokens_path().and_then(|path| fs::read_to_string(path).ok()) {
+        match serde_json::from_str(&tokens) {
+            Ok(tokens) => apply_tokens(&mut value, &tokens),
+            Err(err) => tracing::warn!("Ignoring the saved tokens, they don't parse: {err}"),
+        }
+    }
+    Ok(value)
+}
+
+/// Put the tokens saved for a client in every OAuth backend using it.
+fn apply_tokens(value: &mut Value, tokens: &Value) {
+    for backend in ["read", "send"] {
+        let Some(auth) = value
+            .get_mut(backend)
+            .and_then(|backend| backend.get_mut("auth"))
+            .and_then(Value::as_object_mut)
+        else {
+            continue;
+        };
+        if auth.get("type").and_then(Value::as_str) != Some("oauth") {
+            continue;
+        }
+        let Some(saved) = auth
+            .get("client_id")
+            .and_then(Value::as_str)
+            .and_then(|client_id| tokens.get(client_id))
+            .and_then(Value::as_object)
+        else {
+            continue;
+        };
+        for key in ["access_token", "refresh_token"] {
+            if let Some(token) = saved.get(key) {
+                auth.insert(key.to_string(), token.clone());
+            }
+        }
+    }
+}
+
+/// Write `value` to `path` in the format its extension asks for, readable only by us since it
+/// holds passwords or tokens.
+///
+/// It's written to a temporary file first so a failure doesn't leave us without a configuration.
+pub fn write_value(path: &Path, value: &Value) -> Result<(), crate::Error> {
+    let contents = Format::of(path).write(value)?;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&tmp_path)?.write_all(contents.as_bytes())?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+/// Notices when the configuration file changes, by checking its modification time and size
+/// every so often.
+pub struct Watcher {
+    path: PathBuf,
+    interval: Duration,
+    checked: Instant,
+    stamp: Option<(SystemTime, u64)>,
+}
+
+impl Watcher {
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        Self {
+            stamp: Self::stamp(&path),
+            path,
+            interval,
+            checked: Instant::now(),
+        }
+    }
+
+    fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+        let metadata = fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    /// The configuration as it is now, if it changed since it was last seen.
+    pub fn poll(&mut self) -> Option<Result<Value, crate::Error>> {
+        if self.checked.elapsed() < self.interval {
+            return None;
+        }
+        self.checked = Instant::now();
+        // Editors often replace the file, it's only gone for a moment
+        let stamp = Self::stamp(&self.path)?;
+        if self.stamp == Some(stamp) {
+            return None;
+        }
+        self.stamp = Some(stamp);
+        Some(read_value(&self.path))
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub read: ReadBackend,
     pub send: SendBackend,
+    /// Seconds a sent message can still be taken back, 0 sends right away.
+    #[serde(default)]
+    pub undo_send: u64,
+    /// Added below a `-- ` line to new messages.
+    #[serde(default)]
+    pub signature: Option<String>,
+    #[serde(default)]
+    pub keys: Keymap,
+    #[serde(default)]
+    pub theme: Theme,
+    /// Guessed from `COLORTERM` and `TERM` when not set.
+    #[serde(default)]
+    pub colors: Option<ColorDepth>,
 }
 
 impl Config {
@@ -53,7 +242,347 @@ impl Config {
     where
         P: AsRef<Path>,
     {
-        let file = OpenOptions::new().read(true).open(path)?;
-        Ok(serde_json::from_reader(file)?)
+        Self::from_value(&read_value(path.as_ref())?)
+    }
+
+    /// Deserialize the configuration, pointing at the offending value when it's wrong.
+    pub fn from_value(value: &Value) -> Result<Self, crate::Error> {
+        serde_path_to_error::deserialize(value).map_err(|err| locate(value, err).into())
+    }
+
+    /// The backends connecting without checking who they talk to, to be shown to the user
+    /// rather than just logged.
+    pub fn tls_dangers(&self) -> Vec<String> {
+        let read = match &self.read {
+            ReadBackend::Imap(ImapConfig { host, tls, .. })
+            | ReadBackend::Pop3(Pop3Config { host, tls, .. }) => tls.danger(host),
+            _ => None,
+        };
+        let send = match &self.send {
+            SendBackend::Smtp(SmtpConfig { host, tls, .. }) => tls.danger(host),
+            _ => None,
+        };
+        read.into_iter().chain(send).collect()
+    }
+}
+
+/// A mistake in the configuration, `path` is where in the JSON it was found, e.g. `read.auth.raw`.
+#[derive(Debug, thiserror::Error)]
+#[error("{}: {message}{}", if path.is_empty() { "." } else { path }, suggestion.as_ref().map(|s| format!(" ({s})")).unwrap_or_default())]
+pub struct ConfigError {
+    pub path: String,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+type PathError = serde_path_to_error::Error<serde_json::Error>;
+
+fn check<T: DeserializeOwned>(value: &Value) -> Result<(), PathError> {
+    serde_path_to_error::deserialize::<_, T>(value).map(drop)
+}
+
+fn get<'v>(root: &'v Value, path: &[String]) -> Option<&'v Value> {
+    path.iter().try_fold(root, |value, key| match value {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => value.get(key),
+    })
+}
+
+/// serde loses track of the path inside internally tagged enums, so the value at `path` is
+/// checked again as the variant its `type` names. `None` if it isn't one of them.
+fn check_variant(root: &Value, path: &[String]) -> Option<Result<(), PathError>> {
+    let variant = get(root, path)?.get("type")?.as_str()?;
+    let backend = root.get(path.first()?)?.get("type")?.as_str()?;
+    let value = get(root, path)?;
+    let path = path.iter().map(String::as_str).collect::<Vec<_>>();
+    Some(match (path.as_slice(), backend, variant) {
+        (["read"], _, "imap") => check::<ImapConfig>(value),
+        (["read"], _, "maildir") => check::<MaildirConfig>(value),
+        (["read"], _, "mbox") => check::<MboxConfig>(value),
+        (["read"], _, "pop3") => check::<Pop3Config>(value),
+        (["read" | "send"], _, "jmap") => check::<JmapConfig>(value),
+        (["send"], _, "smtp") => check::<SmtpConfig>(value),
+        (["send"], _, "sendmail") => check::<SendmailConfig>(value),
+        (["send"], _, "file") => check::<FileConfig>(value),
+        ([_, "auth"], "imap", "password") => check::<crate::imap::config::PasswordConfig>(value),
+        ([_, "auth"], "imap", "oauth") => check::<crate::imap::config::OAuthConfig>(value),
+        ([_, "auth"], "smtp", "password") => check::<crate::smtp::config::PasswordConfig>(value),
+        ([_, "auth"], "smtp", "oauth") => check::<crate::smtp::config::OAuthConfig>(value),
+        ([_, "auth"], "jmap", "password") => check::<crate::jmap::config::PasswordConfig>(value),
+        ([_, "auth"], "jmap", "token") => check::<crate::jmap::config::TokenConfig>(value),
+        ([_, "auth"], "pop3", "password") => check::<crate::pop3::config::PasswordConfig>(value),
+        _ => return None,
+    })
+}
+
+/// Turn a deserialization error into a precise path and, for likely typos, a suggestion.
+fn locate(root: &Value, err: PathError) -> ConfigError {
+    let segments = |err: &PathError| {
+        err.path()
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Seq { index } => Some(index.to_string()),
+                Segment::Map { key } => Some(key.clone()),
+                Segment::Enum { .. } | Segment::Unknown => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut path = segments(&err);
+    let mut error = err.into_inner();
+    while let Some(Err(err)) = check_variant(root, &path) {
+        let deeper = segments(&err);
+        error = err.into_inner();
+        if deeper.is_empty() {
+            break;
+        }
+        path.extend(deeper);
+    }
+
+    let message = error.to_string();
+    let quoted = |start: &str, end: char| {
+        let rest = &message[message.find(start)? + start.len()..];
+        Some(rest[..rest.find(end)?].to_string())
+    };
+    let keys = get(root, &path)
+        .and_then(Value::as_object)
+        .map(|object| object.keys().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let closest = |wanted: &str, candidates: &[String]| {
+        candidates
+            .iter()
+            .filter(|candidate| *candidate != wanted)
+            .map(|candidate| {
+                let distance = strsim::damerau_levenshtein(wanted, candidate);
+                (distance, candidate.clone())
+            })
+            // Short names are all close to each other
+            .filter(|(distance, _)| *distance <= (wanted.len() / 3).max(1))
+            .min()
+            .map(|(_, candidate)| candidate)
+    };
+
+    let mut suggestion = None;
+    if let Some(field) = quoted("missing field `", '`') {
+        if let Some(key) = closest(&field, &keys) {
+            suggestion = Some(format!("found `{key}`, did you mean `{field}`?"));
+        }
+        path.push(field);
+    } else if let Some(value) =
+        quoted("unknown variant `", '`').or_else(|| quoted("string \"", '"'))
+    {
+        // Flattened fields (e.g. `tls`) are reported on the object they're flattened into
+        let object = get(root, &path).and_then(Value::as_object);
+        if let Some((key, _)) = object.and_then(|object| {
+            object
+                .iter()
+                .find(|(key, field)| key.as_str() != "type" && field.as_str() == Some(&value))
+        }) {
+            path.push(key.clone());
+        }
+        let expected = message
+            .split_once("expected one of ")
+            .map(|(_, expected)| {
+                expected
+                    .split(", ")
+                    .map(|variant| variant.trim_matches('`').to_string())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if let Some(variant) = closest(&value, &expected) {
+            suggestion = Some(format!("did you mean `{variant}`?"));
+        }
+    }
+
+    ConfigError {
+        path: path.join("."),
+        message,
+        suggestion,
+    }
+}
+
+#[cfg(feature = "refresher")]
+impl Config {
+    /// Keep the tokens of a new login for the client `client_id` in the tokens file at `path`,
+    /// the configuration itself is left as the user wrote it.
+    pub fn save_oauth_tokens<P>(
+        path: P,
+        client_id: &ClientId,
+        access_token: &AccessToken,
+        refresh_token: &RefreshToken,
+    ) -> Result<(), crate::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut tokens = match fs::read_to_string(path) {
+            Ok(raw) => serde_json::from_str(&raw)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                Value::Object(Default::default())
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let Some(clients) = tokens.as_object_mut() else {
+            return Err(std::io::Error::other(format!(
+                "{} isn't a JSON object",
+                path.display()
+            )))?;
+        };
+        clients.insert(
+            client_id.to_string(),
+            serde_json::json!({
+                "access_token": access_token.secret(),
+                "refresh_token": refresh_token.secret(),
+            }),
+        );
+
+        write_value(path, &tokens)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use std::time::Duration;
+
+    use super::{apply_tokens, Config, Format, Watcher};
+
+    fn error(value: serde_json::Value) -> String {
+        match Config::from_value(&value) {
+            Err(crate::Error::Config(err)) => err.to_string(),
+            Err(err) => panic!("unexpected error: {err}"),
+            Ok(_) => panic!("the configuration should be invalid"),
+        }
+    }
+
+    #[test]
+    fn every_format_reads_the_same() {
+        let json = json!({
+            "read": {
+                "type": "imap",
+                "host": "imap.example.com",
+                "port": 993,
+                "login": "jose@example.com",
+                "auth": { "type": "password", "raw": "super-secret" }
+            },
+            "send": { "type": "sendmail", "from": "jose@example.com" },
+            "undo_send": 10
+        });
+        let toml = r#"
+undo_send = 10
+
+[read]
+type = "imap"
+host = "imap.example.com"
+port = 993
+login = "jose@example.com"
+auth = { type = "password", raw = "super-secret" }
+
+[send]
+type = "sendmail"
+from = "jose@example.com"
+"#;
+        let yaml = "
+read:
+  type: imap
+  host: imap.example.com
+  port: 993
+  login: jose@example.com
+  auth:
+    type: password
+    raw: super-secret
+send:
+  type: sendmail
+  from: jose@example.com
+undo_send: 10
+";
+        assert_eq!(Format::Toml.parse(toml).unwrap(), json);
+        assert_eq!(Format::Yaml.parse(yaml).unwrap(), json);
+        assert!(Config::from_value(&Format::Toml.parse(toml).unwrap()).is_ok());
+
+        for format in [Format::Json, Format::Toml, Format::Yaml] {
+            let written = format.write(&json).unwrap();
+            assert_eq!(format.parse(&written).unwrap(), json);
+        }
+        assert_eq!(Format::of("config.yml".as_ref()), Format::Yaml);
+        assert_eq!(Format::of("config".as_ref()), Format::Json);
+    }
+
+    #[test]
+    fn changes_are_noticed() {
+        let path = std::env::temp_dir().join(format!("ectt-watch-{}.toml", std::process::id()));
+        std::fs::write(&path, "undo_send = 10\n").unwrap();
+        let mut watcher = Watcher::new(path.clone(), Duration::ZERO);
+        assert!(watcher.poll().is_none());
+
+        std::fs::write(&path, "undo_send = 5\nsignature = \"Jose\"\n").unwrap();
+        let value = watcher.poll().unwrap().unwrap();
+        assert_eq!(value, json!({ "undo_send": 5, "signature": "Jose" }));
+        assert!(watcher.poll().is_none());
+
+        std::fs::write(&path, "undo_send = [").unwrap();
+        assert!(watcher.poll().unwrap().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn saved_tokens_replace_written_ones() {
+        let auth = |client_id: &str, token: &str| {
+            json!({
+                "type": "oauth",
+                "client_id": client_id,
+                "access_token": token,
+                "refresh_token": token,
+            })
+        };
+        let mut value = json!({
+            "read": { "type": "imap", "auth": auth("ectt", "old") },
+            "send": { "type": "smtp", "auth": auth("other", "old") },
+        });
+        let tokens = json!({ "ectt": { "access_token": "new", "refresh_token": "newer" } });
+        apply_tokens(&mut value, &tokens);
+
+        assert_eq!(value["read"]["auth"]["access_token"], "new");
+        assert_eq!(value["read"]["auth"]["refresh_token"], "newer");
+        assert_eq!(value["send"]["auth"], auth("other", "old"));
+    }
+
+    #[test]
+    fn mistakes_are_located() {
+        let imap = json!({
+            "type": "imap",
+            "host": "imap.example.com",
+            "port": 993,
+            "login": "jose@example.com",
+            "auth": { "type": "password", "raw": "super-secret" }
+        });
+        let send = json!({ "type": "file", "dir": "/tmp/ectt", "from": "jose@example.com" });
+        assert!(Config::from_value(&json!({ "read": imap, "send": send })).is_ok());
+
+        let mut typo = imap.clone();
+        typo["auth"] = json!({ "type": "password", "rwa": "super-secret" });
+        assert_eq!(
+            error(json!({ "read": typo, "send": send })),
+            "read.auth.raw: missing field `raw` (found `rwa`, did you mean `raw`?)"
+        );
+
+        let mut port = imap.clone();
+        port["port"] = json!("993");
+        assert_eq!(
+            error(json!({ "read": port, "send": send })),
+            "read.port: invalid type: string \"993\", expected u16"
+        );
+
+        let mut tls = imap.clone();
+        tls["tls"] = json!("startls");
+        assert!(error(json!({ "read": tls, "send": send }))
+            .starts_with("read.tls: unknown variant `startls`"));
+        assert!(error(json!({ "read": tls, "send": send })).ends_with("(did you mean `starttls`?)"));
+
+        let mut kind = imap.clone();
+        kind["type"] = json!("imaps");
+        assert!(error(json!({ "read": kind, "send": send })).ends_with("(did you mean `imap`?)"));
+
+        assert_eq!(error(json!({ "read": imap })), "send: missing field `send`");
     }
 }
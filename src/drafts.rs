@@ -0,0 +1,207 @@
+//! Messages being composed, saved locally so nothing typed is lost.
+
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, Local};
+
+use crate::config::ectt_data_dir;
+
+/// Header identifying our copy of a draft in the store's Drafts mailbox, so it can be replaced.
+pub const DRAFT_HEADER: &str = "X-Ectt-Draft";
+
+/// The fields of a message as typed, addresses are kept even if they don't parse yet.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Draft {
+    pub id: String,
+    pub to: String,
+    pub cc: String,
+    pub bcc: String,
+    pub subject: String,
+    pub body: String,
+    /// Files to attach, they're only read when the message is sent.
+    #[serde(default)]
+    pub attachments: Vec<PathBuf>,
+    /// Unix timestamp of the last save.
+    pub saved: i64,
+}
+
+pub fn new_id() -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("{timestamp}.{}", std::process::id())
+}
+
+impl Draft {
+    pub fn is_empty(&self) -> bool {
+        [&self.to, &self.cc, &self.bcc, &self.subject, &self.body]
+            .iter()
+            .all(|field| field.trim().is_empty())
+            && self.attachments.is_empty()
+    }
+
+    pub fn saved(&self) -> Option<DateTime<Local>> {
+        DateTime::from_timestamp(self.saved, 0).map(|date| date.with_timezone(&Local))
+    }
+
+    /// RFC 5322 version of the draft, for the store's Drafts mailbox.
+    pub fn formatted(&self) -> Vec<u8> {
+        let mut headers = vec![
+            format!("{DRAFT_HEADER}: {}", self.id),
+            format!("Date: {}", Local::now().to_rfc2822()),
+        ];
+        for (name, value) in [("To", &self.to), ("Cc", &self.cc), ("Bcc", &self.bcc)] {
+            if !value.trim().is_empty() {
+                headers.push(format!("{name}: {}", value.trim()));
+            }
+        }
+        headers.push(format!("Subject: {}", encode_header(&self.subject)));
+        headers.push("MIME-Version: 1.0".to_string());
+        headers.push("Content-Type: text/plain; charset=utf-8".to_string());
+        headers.push("Content-Transfer-Encoding: 8bit".to_string());
+
+        let body = self.body.lines().collect::<Vec<_>>().join("\r\n");
+        format!("{}\r\n\r\n{body}\r\n", headers.join("\r\n")).into_bytes()
+    }
+}
+
+/// RFC 2047 encoded word for values which aren't plain ASCII.
+fn encode_header(value: &str) -> String {
+    if value.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) {
+        return value.to_string();
+    }
+    format!("=?utf-8?B?{}?=", BASE64_STANDARD.encode(value))
+}
+
+/// Directory of drafts, one file each.
+pub struct Drafts {
+    dir: PathBuf,
+}
+
+impl Drafts {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn default_dir() -> PathBuf {
+        ectt_data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("drafts")
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    /// Saved drafts, most recent first.
+    pub fn list(&self) -> Result<Vec<Draft>, crate::Error> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let mut drafts = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            match serde_json::from_slice(&fs::read(&path)?) {
+                Ok(draft) => drafts.push(draft),
+                Err(err) => tracing::error!("Ignoring {} with error: {err}", path.display()),
+            }
+        }
+        drafts.sort_by(|a: &Draft, b| b.saved.cmp(&a.saved).then_with(|| b.id.cmp(&a.id)));
+        Ok(drafts)
+    }
+
+    pub fn save(&self, draft: &Draft) -> Result<(), crate::Error> {
+        fs::create_dir_all(&self.dir)?;
+        // Same as the outbox, a crash halfway through shouldn't lose the previous save
+        let path = self.path(&draft.id);
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_vec_pretty(draft)?)?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), crate::Error> {
+        match fs::remove_file(self.path(id)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Draft, Drafts};
+
+    fn draft(id: &str, saved: i64) -> Draft {
+        Draft {
+            id: id.to_string(),
+            to: "duarte@example.com".to_string(),
+            cc: String::new(),
+            bcc: "not an address yet".to_string(),
+            subject: "Olá".to_string(),
+            body: "Hello\nthere".to_string(),
+            attachments: vec![],
+            saved,
+        }
+    }
+
+    #[test]
+    fn drafts_are_kept() {
+        let dir = std::env::temp_dir().join(format!("ectt-drafts-{}", std::process::id()));
+        let drafts = Drafts::new(dir.clone());
+        assert!(drafts.list().unwrap().is_empty());
+
+        drafts.save(&draft("older", 1)).unwrap();
+        let mut newer = draft("newer", 2);
+        drafts.save(&newer).unwrap();
+        newer.subject = "Changed".to_string();
+        drafts.save(&newer).unwrap();
+        assert_eq!(drafts.list().unwrap(), [newer.clone(), draft("older", 1)]);
+
+        drafts.remove("older").unwrap();
+        drafts.remove("missing").unwrap();
+        assert_eq!(drafts.list().unwrap(), [newer]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn drafts_are_formatted() {
+        let raw = String::from_utf8(draft("1.2", 0).formatted()).unwrap();
+        assert!(raw.starts_with("X-Ectt-Draft: 1.2\r\n"));
+        assert!(raw.contains("To: duarte@example.com\r\n"));
+        assert!(!raw.contains("Cc:"));
+        assert!(raw.contains("Subject: =?utf-8?B?T2zDoQ==?=\r\n"));
+        assert!(raw.ends_with("\r\n\r\nHello\r\nthere\r\n"));
+
+        let parsed = mail_parser::MessageParser::default()
+            .parse(raw.as_bytes())
+            .unwrap();
+        assert_eq!(parsed.subject(), Some("Olá"));
+        assert!(!draft("1.2", 0).is_empty());
+
+        let blank = Draft {
+            to: String::new(),
+            bcc: String::new(),
+            subject: " ".to_string(),
+            body: "\n".to_string(),
+            ..draft("1.2", 0)
+        };
+        assert!(blank.is_empty());
+        let attached = Draft {
+            attachments: vec!["report.pdf".into()],
+            ..blank
+        };
+        assert!(!attached.is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct FileConfig {
+    /// Where the `.eml` files are written to, it's created if needed.
+    pub dir: PathBuf,
+    /// Address the mail would be sent from.
+    pub from: String,
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use serde_json::json;
+
+    use crate::smtp::config::SendBackend;
+
+    use super::FileConfig;
+
+    #[test]
+    fn ensure_file_format() {
+        let json = json!({
+            "type": "file",
+            "dir": "/tmp/outgoing",
+            "from": "jose@example.com"
+        });
+        let SendBackend::File(FileConfig { dir, from }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
+        assert_eq!(dir, PathBuf::from("/tmp/outgoing"));
+        assert_eq!(from, "jose@example.com");
+    }
+}
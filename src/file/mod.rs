@@ -0,0 +1,85 @@
+pub mod config;
+
+use std::{
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lettre::Address;
+
+use crate::{
+    file::config::FileConfig,
+    smtp::{MailTransport, PartialMessage},
+};
+
+/// Writes messages to disk instead of sending them, useful to check what would be sent.
+pub struct FileTransport {
+    config: FileConfig,
+}
+
+impl FileTransport {
+    pub fn new(config: FileConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl MailTransport for FileTransport {
+    fn send(&mut self, message: PartialMessage) -> Result<Option<Vec<u8>>, crate::Error> {
+        // Keep Bcc, the point is to see everything that would be sent
+        let message = message.into_message(self.config.from.parse::<Address>()?, true)?;
+
+        fs::create_dir_all(&self.config.dir)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = self
+            .config
+            .dir
+            .join(format!("{timestamp}.{}.eml", std::process::id()));
+        fs::write(&path, message.formatted())?;
+        tracing::info!("Wrote message to {}", path.display());
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        file::config::FileConfig,
+        smtp::{MailTransport, PartialMessage},
+    };
+
+    use super::FileTransport;
+
+    #[test]
+    fn messages_are_written() {
+        let dir = std::env::temp_dir().join(format!("ectt-file-{}", std::process::id()));
+        let mut transport = FileTransport::new(FileConfig {
+            dir: dir.clone(),
+            from: "jose@example.com".to_string(),
+        });
+        for subject in ["First", "Second"] {
+            transport
+                .send(PartialMessage {
+                    to: Some("friend@example.com".parse().unwrap()),
+                    cc: vec![],
+                    bcc: vec![],
+                    subject: Some(subject.to_string()),
+                    body: Some("Hi!".to_string()),
+                    attachments: vec![],
+                })
+                .unwrap();
+        }
+
+        let mut messages = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|message| message.contains("Subject: Second"));
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("Subject: First"));
+        assert!(messages[1].contains("From: jose@example.com"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,373 @@
+//! Importing an account from [himalaya](https://github.com/pimalaya/himalaya)'s TOML
+//! configuration, which ours is based on.
+
+use std::path::PathBuf;
+
+use itertools::Itertools;
+use serde_json::{json, Map, Value};
+
+use crate::config::ConfigError;
+
+/// An account mapped onto our configuration, with what couldn't be carried over.
+#[derive(Debug)]
+pub struct Import {
+    pub account: String,
+    pub config: Value,
+    /// Settings which need filling in by hand, e.g. passwords kept in a keyring.
+    pub warnings: Vec<String>,
+}
+
+/// `<OS configuration folder>/himalaya/config.toml`, where himalaya keeps its configuration.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("himalaya").join("config.toml"))
+}
+
+fn error(path: &str, message: impl Into<String>) -> crate::Error {
+    ConfigError {
+        path: path.to_string(),
+        message: message.into(),
+        suggestion: None,
+    }
+    .into()
+}
+
+/// Walks one account's settings, keeping track of where in the file they are for errors.
+struct Account<'a> {
+    name: &'a str,
+    settings: &'a Value,
+    warnings: Vec<String>,
+}
+
+impl Account<'_> {
+    fn path(&self, key: &str) -> String {
+        format!("accounts.{}.{key}", self.name)
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        key.split('.')
+            .try_fold(self.settings, |value, key| value.get(key))
+    }
+
+    fn string(&self, key: &str) -> Option<String> {
+        self.get(key).and_then(Value::as_str).map(str::to_string)
+    }
+
+    fn required(&self, key: &str) -> Result<String, crate::Error> {
+        self.string(key)
+            .ok_or_else(|| error(&self.path(key), "missing string"))
+    }
+
+    /// himalaya takes secrets as `{ raw = "..." }`, `{ cmd = "..." }` or `{ keyring = "..." }`,
+    /// only the raw ones can be carried over.
+    fn secret(&mut self, key: &str, target: &str) -> String {
+        let value = self.get(key);
+        if let Some(secret) = value
+            .and_then(|value| value.as_str().or_else(|| value.get("raw")?.as_str()))
+            .map(str::to_string)
+        {
+            return secret;
+        }
+        let how = match value {
+            Some(value) if value.get("cmd").is_some() => "is read from a command",
+            Some(value) if value.get("keyring").is_some() => "is kept in a keyring",
+            _ => "is missing",
+        };
+        self.warnings.push(format!(
+            "{} {how}, fill {target} in by hand",
+            self.path(key)
+        ));
+        String::new()
+    }
+
+    /// `encryption = "tls"` or `encryption.type = "tls"`, himalaya defaults to implicit TLS.
+    fn tls(&self, prefix: &str) -> Result<&'static str, crate::Error> {
+        let key = format!("{prefix}.encryption");
+        let encryption = self.get(&key).and_then(|value| {
+            value
+                .as_str()
+                .or_else(|| value.get("type").and_then(Value::as_str))
+        });
+        match encryption {
+            None | Some("tls") => Ok("implicit"),
+            Some("start-tls") => Ok("starttls"),
+            Some("none") => Ok("none"),
+            Some(other) => Err(error(
+                &self.path(&key),
+                format!("unknown encryption `{other}`"),
+            )),
+        }
+    }
+
+    fn auth(&mut self, prefix: &str, target: &str) -> Result<Value, crate::Error> {
+        let key = format!("{prefix}.auth");
+        match self.string(&format!("{key}.type")).as_deref() {
+            Some("password") => {
+                let raw = self.secret(&key, &format!("{target}.auth.raw"));
+                Ok(json!({ "type": "password", "raw": raw }))
+            }
+            Some("oauth2") => {
+                let mut auth = Map::new();
+                auth.insert("type".to_string(), "oauth".into());
+                auth.insert(
+                    "client_id".to_string(),
+                    self.required(&format!("{key}.client-id"))?.into(),
+                );
+                for (from, to) in [
+                    ("client-secret", "client_secret"),
+                    ("access-token", "access_token"),
+                    ("refresh-token", "refresh_token"),
+                ] {
+                    let secret =
+                        self.secret(&format!("{key}.{from}"), &format!("{target}.auth.{to}"));
+                    auth.insert(to.to_string(), secret.into());
+                }
+                auth.insert(
+                    "auth_url".to_string(),
+                    self.required(&format!("{key}.auth-url"))?.into(),
+                );
+                auth.insert(
+                    "token_url".to_string(),
+                    self.required(&format!("{key}.token-url"))?.into(),
+                );
+                let scopes = match (
+                    self.get(&format!("{key}.scopes")),
+                    self.get(&format!("{key}.scope")),
+                ) {
+                    (Some(Value::Array(scopes)), _) => Some(Value::Array(scopes.clone())),
+                    (_, Some(Value::String(scope))) => Some(json!([scope])),
+                    _ => None,
+                };
+                if let Some(scopes) = scopes {
+                    auth.insert("scopes".to_string(), scopes);
+                }
+                Ok(Value::Object(auth))
+            }
+            Some(other) => Err(error(
+                &self.path(&format!("{key}.type")),
+                format!("unknown authentication `{other}`"),
+            )),
+            None => Err(error(&self.path(&format!("{key}.type")), "missing string")),
+        }
+    }
+
+    /// The host, port, TLS, login and auth shared by IMAP and SMTP.
+    fn server(&mut self, prefix: &str, target: &str, kind: &str) -> Result<Value, crate::Error> {
+        let port = self
+            .get(&format!("{prefix}.port"))
+            .and_then(Value::as_u64)
+            .ok_or_else(|| error(&self.path(&format!("{prefix}.port")), "missing port"))?;
+        let login = match self.string(&format!("{prefix}.login")) {
+            Some(login) => login,
+            None => self.required("email")?,
+        };
+        Ok(json!({
+            "type": kind,
+            "host": self.required(&format!("{prefix}.host"))?,
+            "port": port,
+            "tls": self.tls(prefix)?,
+            "login": login,
+            "auth": self.auth(prefix, target)?,
+        }))
+    }
+
+    fn read(&mut self) -> Result<Value, crate::Error> {
+        match self.string("backend.type").as_deref() {
+            Some("imap") => self.server("backend", "read", "imap"),
+            Some("maildir") => Ok(json!({
+                "type": "maildir",
+                "path": self.required("backend.root-dir")?,
+            })),
+            Some(other) => Err(error(
+                &self.path("backend.type"),
+                format!("the {other} backend isn't supported, only imap and maildir are"),
+            )),
+            None => Err(error(&self.path("backend.type"), "missing string")),
+        }
+    }
+
+    fn send(&mut self) -> Result<Value, crate::Error> {
+        let prefix = "message.send.backend";
+        match self.string(&format!("{prefix}.type")).as_deref() {
+            Some("smtp") => {
+                let mut smtp = self.server(prefix, "send", "smtp")?;
+                if let Some(save_copy) = self.get("message.send.save-copy").and_then(Value::as_bool)
+                {
+                    smtp["save_sent"] = save_copy.into();
+                }
+                if let Some(method) = self.string(&format!("{prefix}.auth.method")) {
+                    smtp["mechanisms"] = json!([method]);
+                }
+                Ok(smtp)
+            }
+            Some("sendmail") => {
+                let mut sendmail = json!({ "type": "sendmail", "from": self.required("email")? });
+                if let Some(command) = self.string(&format!("{prefix}.cmd")) {
+                    sendmail["command"] = command.into();
+                }
+                Ok(sendmail)
+            }
+            Some(other) => Err(error(
+                &self.path(&format!("{prefix}.type")),
+                format!("the {other} backend isn't supported, only smtp and sendmail are"),
+            )),
+            None => Err(error(
+                &self.path(&format!("{prefix}.type")),
+                "missing string",
+            )),
+        }
+    }
+}
+
+/// Map the account `name` of a himalaya configuration onto ours, or its default account.
+pub fn import(raw: &str, name: Option<&str>) -> Result<Import, crate::Error> {
+    let himalaya: Value = toml::from_str(raw)?;
+    let accounts = himalaya
+        .get("accounts")
+        .and_then(Value::as_object)
+        .filter(|accounts| !accounts.is_empty())
+        .ok_or_else(|| error("accounts", "no accounts"))?;
+    let names = || accounts.keys().map(|name| format!("`{name}`")).join(", ");
+
+    let (name, settings) = match name {
+        Some(name) => accounts.get_key_value(name).ok_or_else(|| {
+            error(
+                &format!("accounts.{name}"),
+                format!("no such account, there's {}", names()),
+            )
+        })?,
+        None => accounts
+            .iter()
+            .find(|(_, settings)| settings.get("default").and_then(Value::as_bool) == Some(true))
+            .or_else(|| accounts.iter().exactly_one().ok())
+            .ok_or_else(|| {
+                error(
+                    "accounts",
+                    format!("no default account, pick one of {}", names()),
+                )
+            })?,
+    };
+
+    let mut account = Account {
+        name,
+        settings,
+        warnings: vec![],
+    };
+    let config = json!({ "read": account.read()?, "send": account.send()? });
+    Ok(Import {
+        account: name.clone(),
+        config,
+        warnings: account.warnings,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::config::Config;
+
+    use super::import;
+
+    const HIMALAYA: &str = r#"
+[accounts.personal]
+email = "jose@example.com"
+backend.type = "maildir"
+backend.root-dir = "~/Mail/personal"
+message.send.backend.type = "sendmail"
+message.send.backend.cmd = "/usr/bin/msmtp -t"
+
+[accounts.work]
+default = true
+email = "jose@work.example.com"
+display-name = "Jose"
+
+backend.type = "imap"
+backend.host = "imap.work.example.com"
+backend.port = 143
+backend.encryption.type = "start-tls"
+backend.auth.type = "password"
+backend.auth.cmd = "pass show work"
+
+message.send.save-copy = false
+message.send.backend.type = "smtp"
+message.send.backend.host = "smtp.work.example.com"
+message.send.backend.port = 465
+message.send.backend.login = "jose"
+message.send.backend.auth.type = "oauth2"
+message.send.backend.auth.method = "xoauth2"
+message.send.backend.auth.client-id = "client-id"
+message.send.backend.auth.client-secret.raw = "client-secret"
+message.send.backend.auth.access-token.raw = "access-token"
+message.send.backend.auth.refresh-token.keyring = "work-refresh-token"
+message.send.backend.auth.auth-url = "https://login.example.com/authorize"
+message.send.backend.auth.token-url = "https://login.example.com/token"
+message.send.backend.auth.scope = "https://mail.example.com/"
+"#;
+
+    #[test]
+    fn himalaya_accounts_are_imported() {
+        let work = import(HIMALAYA, None).unwrap();
+        assert_eq!(work.account, "work");
+        assert_eq!(
+            work.config,
+            json!({
+                "read": {
+                    "type": "imap",
+                    "host": "imap.work.example.com",
+                    "port": 143,
+                    "tls": "starttls",
+                    "login": "jose@work.example.com",
+                    "auth": { "type": "password", "raw": "" },
+                },
+                "send": {
+                    "type": "smtp",
+                    "host": "smtp.work.example.com",
+                    "port": 465,
+                    "tls": "implicit",
+                    "login": "jose",
+                    "auth": {
+                        "type": "oauth",
+                        "client_id": "client-id",
+                        "client_secret": "client-secret",
+                        "access_token": "access-token",
+                        "refresh_token": "",
+                        "auth_url": "https://login.example.com/authorize",
+                        "token_url": "https://login.example.com/token",
+                        "scopes": ["https://mail.example.com/"],
+                    },
+                    "save_sent": false,
+                    "mechanisms": ["xoauth2"],
+                },
+            })
+        );
+        assert_eq!(
+            work.warnings,
+            [
+                "accounts.work.backend.auth is read from a command, fill read.auth.raw in by hand",
+                "accounts.work.message.send.backend.auth.refresh-token is kept in a keyring, \
+                 fill send.auth.refresh_token in by hand",
+            ]
+        );
+        assert!(Config::from_value(&work.config).is_ok());
+
+        let personal = import(HIMALAYA, Some("personal")).unwrap();
+        assert_eq!(
+            personal.config,
+            json!({
+                "read": { "type": "maildir", "path": "~/Mail/personal" },
+                "send": {
+                    "type": "sendmail",
+                    "from": "jose@example.com",
+                    "command": "/usr/bin/msmtp -t",
+                },
+            })
+        );
+        assert!(personal.warnings.is_empty());
+
+        let missing = import(HIMALAYA, Some("home")).unwrap_err().to_string();
+        assert_eq!(
+            missing,
+            "Invalid configuration at accounts.home: no such account, there's `personal`, `work`"
+        );
+    }
+}
@@ -1,6 +1,10 @@
+use crate::{
+    jmap::config::JmapConfig, maildir::config::MaildirConfig, mbox::config::MboxConfig,
+    pop3::config::Pop3Config, tls::TlsConfig,
+};
 use oauth2::{
     basic::BasicClient, AccessToken, AuthUrl, ClientId, ClientSecret, EndpointNotSet, EndpointSet,
-    RefreshToken, TokenUrl,
+    RefreshToken, Scope, TokenUrl,
 };
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -8,6 +12,10 @@ use oauth2::{
 #[serde(rename_all = "lowercase")]
 pub enum ReadBackend {
     Imap(ImapConfig),
+    Maildir(MaildirConfig),
+    Mbox(MboxConfig),
+    Jmap(JmapConfig),
+    Pop3(Pop3Config),
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -16,6 +24,11 @@ pub struct ImapConfig {
     pub port: u16,
     pub login: String,
     pub auth: Auth,
+    #[serde(flatten)]
+    pub tls: TlsConfig,
+    /// Keep a copy of drafts in the `\Drafts` mailbox, so other clients can see them.
+    #[serde(default)]
+    pub sync_drafts: bool,
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -41,6 +54,14 @@ pub struct OAuthConfig {
     pub auth_url: AuthUrl,
     #[serde(alias = "token_uri")]
     pub token_url: TokenUrl,
+    /// Scopes to request when logging in again, defaults to Gmail's.
+    #[serde(default = "default_scopes")]
+    #[cfg_attr(not(feature = "refresher"), allow(dead_code))]
+    pub scopes: Vec<Scope>,
+}
+
+fn default_scopes() -> Vec<Scope> {
+    vec![Scope::new("https://mail.google.com/".to_string())]
 }
 
 impl OAuthConfig {
@@ -56,7 +77,7 @@ impl OAuthConfig {
 
 #[cfg(test)]
 mod test {
-    use oauth2::{AccessToken, AuthUrl, ClientId, ClientSecret, RefreshToken, TokenUrl};
+    use oauth2::{AccessToken, AuthUrl, ClientId, ClientSecret, RefreshToken, Scope, TokenUrl};
     use serde_json::json;
 
     use super::{Auth, ImapConfig, OAuthConfig, PasswordConfig, ReadBackend};
@@ -90,13 +111,19 @@ mod test {
             port,
             login,
             auth,
-        }) = serde_json::from_value(json).unwrap();
+            sync_drafts,
+            ..
+        }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
 
         assert_eq!(host, "imap.example.com".to_string());
         assert_eq!(port, 993);
         assert_eq!(login, "jose@example.com");
         // Defer the auth to the other tests
         assert!(matches!(auth, Auth::Password { .. }));
+        assert!(!sync_drafts);
     }
 
     #[test]
@@ -114,20 +141,27 @@ mod test {
                 "token_url": "https://localhost",
                 "access_token": "access-token",
                 "refresh_token": "refresh-token",
-            }
+            },
+            "sync_drafts": true
         });
         let ReadBackend::Imap(ImapConfig {
             host,
             port,
             login,
             auth,
-        }) = serde_json::from_value(json).unwrap();
+            sync_drafts,
+            ..
+        }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
 
         assert_eq!(host, "imap.example.com".to_string());
         assert_eq!(port, 993);
         assert_eq!(login, "jose@example.com");
         // Defer the auth to the other tests
         assert!(matches!(auth, Auth::OAuth { .. }));
+        assert!(sync_drafts);
     }
 
     #[test]
@@ -162,6 +196,7 @@ mod test {
             refresh_token,
             auth_url,
             token_url,
+            scopes,
         }) = serde_json::from_value::<Auth>(json).unwrap()
         else {
             panic!("wrong format");
@@ -188,5 +223,9 @@ mod test {
             refresh_token.into_secret(),
             RefreshToken::new("refresh-token".to_string()).into_secret()
         );
+        assert_eq!(
+            scopes,
+            vec![Scope::new("https://mail.google.com/".to_string())]
+        );
     }
 }
@@ -2,83 +2,89 @@ pub mod config;
 pub mod oauth;
 pub mod state;
 
-use crate::imap::state::UnauthenticatedState;
-use chrono::{DateTime, Utc};
+use crate::imap::state::{AuthenticatedState, UnauthenticatedState};
+use crate::store::{Command, Response};
+#[cfg(feature = "refresher")]
+use config::Auth;
 use config::ImapConfig;
+#[cfg(feature = "refresher")]
+use oauth2::{AccessToken, RefreshToken};
 use std::sync::mpsc::{Receiver, Sender};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ParsedEmail {
-    pub uid: u32,
-    pub date: DateTime<Utc>,
-    pub from: String,
-    pub cc: Vec<String>,
-    pub bcc: Vec<String>,
-    pub subject: String,
-    pub body: String,
-}
-
-pub enum Command {
-    ReadInbox { count: u32, offset: u32 },
-}
-
-pub enum Response {
-    Inbox(Vec<ParsedEmail>),
-    Error(crate::Error),
-}
-
 #[tracing::instrument(skip_all)]
 pub fn imap_thread(
     config: ImapConfig,
     rx: Receiver<Command>,
     tx: Sender<Response>,
 ) -> Result<(), crate::Error> {
-    let state = UnauthenticatedState::new(config)?;
-    let mut state = match state.authenticate() {
-        Ok(state) => state,
-        Err((err, _)) => {
-            if let Err(err) = tx.send(Response::Error(err)) {
-                tracing::error!("Failed to send error message to main thread with error: {err}");
-            };
-            return Ok(()); // Nothing left to do since authenticate already tries to refresh the token
-        }
+    let Some(state) = authenticate(config, &rx, &tx)? else {
+        return Ok(()); // Nothing left to do since authenticate already tries to refresh the token
     };
 
+    crate::store::run(state, rx, tx)
+}
+
+/// Authenticate against the server, asking the main thread for a new login if the refresh token was revoked.
+///
+/// Returns `None` if authentication failed and the error was already reported to the main thread.
+#[cfg_attr(
+    not(feature = "refresher"),
+    allow(unused_mut, unused_variables, clippy::never_loop)
+)]
+fn authenticate(
+    mut config: ImapConfig,
+    rx: &Receiver<Command>,
+    tx: &Sender<Response>,
+) -> Result<Option<AuthenticatedState>, crate::Error> {
     loop {
-        let message = match rx.recv() {
-            Ok(message) => message,
-            Err(err) => {
-                tracing::error!("Error while receiving a message from main thread: {err}");
-                // It's ok to just break and return here because it means the main thread has closed the channel
-                break;
-            }
+        let state = UnauthenticatedState::new(config.clone())?;
+        let (err, state) = match state.authenticate() {
+            Ok(state) => return Ok(Some(state)),
+            Err(err) => err,
         };
 
-        match message {
-            Command::ReadInbox { count, offset } => {
-                let emails = match state.read_inbox(count, offset) {
-                    Ok(emails) => emails,
-                    Err(err) => {
-                        if let Err(err) = tx.send(Response::Error(err)) {
-                            tracing::error!(
-                                "Failed to send inbox response to main thread with error: {err}"
-                            );
-                            // It's ok to just break and return here because it means the main thread has closed the channel
-                            break;
-                        }
-                        continue;
-                    }
-                };
-                if let Err(err) = tx.send(Response::Inbox(emails)) {
-                    tracing::error!(
-                        "Failed to send inbox response to main thread with error: {err}"
-                    );
-                    // It's ok to just break and return here because it means the main thread has closed the channel
-                    break;
-                }
+        #[cfg(feature = "refresher")]
+        if let (true, Auth::OAuth(oauth_config)) = (err.requires_login(), state.config.auth) {
+            tracing::warn!("Refresh token was rejected, requesting a new login");
+            if let Err(err) = tx.send(Response::LoginRequired(oauth_config)) {
+                tracing::error!("Failed to send login request to main thread with error: {err}");
+                return Ok(None);
             }
+
+            let Some((access_token, refresh_token)) = wait_for_login(rx) else {
+                // It's ok to just return here because it means the main thread has closed the channel
+                return Ok(None);
+            };
+            if let Auth::OAuth(ref mut oauth_config) = config.auth {
+                oauth_config.access_token = access_token;
+                oauth_config.refresh_token = refresh_token;
+            }
+            continue;
         }
+
+        if let Err(err) = tx.send(Response::Error(err)) {
+            tracing::error!("Failed to send error message to main thread with error: {err}");
+        };
+        return Ok(None);
     }
+}
 
-    Ok(())
+/// Block until the main thread sends us new tokens, everything else is dropped in the meantime.
+#[cfg(feature = "refresher")]
+fn wait_for_login(rx: &Receiver<Command>) -> Option<(AccessToken, RefreshToken)> {
+    loop {
+        match rx.recv() {
+            Ok(Command::Login {
+                access_token,
+                refresh_token,
+            }) => return Some((access_token, refresh_token)),
+            Ok(_) => {
+                tracing::debug!("Ignoring command while waiting for a new login");
+            }
+            Err(err) => {
+                tracing::error!("Error while receiving a message from main thread: {err}");
+                return None;
+            }
+        }
+    }
 }
@@ -1,21 +1,114 @@
-use std::cmp;
+use std::{
+    cmp,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
 
 use chrono::{DateTime, Utc};
-use imap::Connection;
+use imap::{extensions::idle::SetReadTimeout, Connection, ConnectionMode};
+use imap_proto::{Capability, NameAttribute};
 use itertools::Itertools;
-use mail_parser::MessageParser;
 use oauth2::{
     basic::BasicRequestTokenError,
     reqwest::{self, Error},
     HttpClientError, TokenResponse,
 };
+use rustls::{ClientConnection, StreamOwned};
 
-use crate::imap::{
-    config::{Auth, ImapConfig},
-    oauth::OAuthConfigWithUser,
-    ParsedEmail,
+use crate::{
+    drafts::DRAFT_HEADER,
+    imap::{
+        config::{Auth, ImapConfig},
+        oauth::OAuthConfigWithUser,
+    },
+    store::{special_use_mailbox, Flag, MailStore, Mailbox, ParsedEmail, SpecialUse},
+    tls::{TlsConfig, TlsMode},
 };
 
+/// TLS stream built from our own settings, `imap` only knows about the system's certificates.
+struct TlsStream(StreamOwned<ClientConnection, TcpStream>);
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl SetReadTimeout for TlsStream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> imap::Result<()> {
+        Ok(self.0.sock.set_read_timeout(timeout)?)
+    }
+}
+
+/// Connect using a custom CA bundle or client certificate.
+fn connect_with_certs(
+    host: &str,
+    port: u16,
+    tls: &TlsConfig,
+) -> Result<imap::Client<Connection>, crate::Error> {
+    let tcp = TcpStream::connect((host, port))?;
+    if tls.mode == TlsMode::StartTls {
+        starttls(&tcp)?;
+    }
+    let stream: Connection = Box::new(TlsStream(tls.connect(host, tcp)?));
+    let mut client = imap::Client::new(stream);
+    match tls.mode {
+        // The greeting was read before upgrading the connection
+        TlsMode::StartTls => client.greeting_read = true,
+        TlsMode::Implicit | TlsMode::None => {
+            client.read_greeting()?;
+        }
+    }
+    Ok(client)
+}
+
+/// Read the greeting and ask for `STARTTLS`, the `imap` client doesn't let us run raw commands.
+fn starttls(tcp: &TcpStream) -> Result<(), crate::Error> {
+    let mut reader = BufReader::new(tcp);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    (&*tcp).write_all(b"a0 STARTTLS\r\n")?;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        if let Some(status) = line.strip_prefix("a0 ") {
+            if status.starts_with("OK") {
+                return Ok(());
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("STARTTLS failed: {}", status.trim_end()),
+            )
+            .into());
+        }
+    }
+}
+
+/// Capabilities as the server names them, e.g. `IMAP4rev1` or `AUTH=XOAUTH2`.
+pub fn capability_names(capabilities: &imap::types::Capabilities) -> Vec<String> {
+    capabilities
+        .iter()
+        .map(|capability| match capability {
+            Capability::Imap4rev1 => "IMAP4rev1".to_string(),
+            Capability::Auth(mechanism) => format!("AUTH={mechanism}"),
+            Capability::Atom(atom) => atom.to_string(),
+        })
+        .collect()
+}
+
 pub struct UnauthenticatedState {
     pub config: ImapConfig,
     pub client: imap::Client<Connection>,
@@ -23,8 +116,26 @@ pub struct UnauthenticatedState {
 
 impl UnauthenticatedState {
     pub fn new(config: ImapConfig) -> Result<Self, crate::Error> {
-        let ImapConfig { ref host, port, .. } = config;
-        let client = imap::ClientBuilder::new(host.clone(), port).connect()?;
+        let ImapConfig {
+            ref host,
+            port,
+            ref tls,
+            ..
+        } = config;
+        tls.warn_if_dangerous(host);
+        let client = if tls.has_custom_certs() && tls.mode != TlsMode::None {
+            connect_with_certs(host, port, tls)?
+        } else {
+            let mode = match tls.mode {
+                TlsMode::Implicit => ConnectionMode::Tls,
+                TlsMode::StartTls => ConnectionMode::StartTls,
+                TlsMode::None => ConnectionMode::Plaintext,
+            };
+            imap::ClientBuilder::new(host.clone(), port)
+                .mode(mode)
+                .danger_skip_tls_verify(tls.danger_accept_invalid_certs)
+                .connect()?
+        };
         Ok(UnauthenticatedState { config, client })
     }
 
@@ -62,7 +173,7 @@ impl UnauthenticatedState {
                     .client
                     .login(self.config.login.as_str(), &password_config.raw)
                 {
-                    Ok(session) => Ok(AuthenticatedState::new(session)),
+                    Ok(session) => Ok(AuthenticatedState::new(session, self.config.sync_drafts)),
                     Err((err, client)) => Err((
                         err,
                         Self {
@@ -75,7 +186,7 @@ impl UnauthenticatedState {
             Auth::OAuth(oauth_config) => {
                 let authenticator = OAuthConfigWithUser::new(&self.config.login, oauth_config);
                 match self.client.authenticate("XOAUTH2", &authenticator) {
-                    Ok(session) => Ok(AuthenticatedState::new(session)),
+                    Ok(session) => Ok(AuthenticatedState::new(session, self.config.sync_drafts)),
                     Err((err, client)) => Err((
                         err,
                         Self {
@@ -116,21 +227,48 @@ impl UnauthenticatedState {
     }
 }
 
+/// A full message, as fetched for exporting.
+pub struct RawMessage {
+    pub raw: Vec<u8>,
+    pub internal_date: Option<DateTime<Utc>>,
+    pub flags: Vec<Flag>,
+}
+
 pub struct AuthenticatedState {
     session: imap::Session<Connection>,
+    /// Mailbox the cached UIDs belong to.
+    selected: Option<String>,
     uids: Vec<u32>,
+    /// Whether drafts are kept in the Drafts mailbox, see [`ImapConfig::sync_drafts`].
+    sync_drafts: bool,
+    /// The last listed mailboxes, so finding e.g. the Sent one doesn't cost a LIST every time.
+    mailboxes: Option<Vec<Mailbox>>,
 }
 
 impl AuthenticatedState {
-    fn new(session: imap::Session<Connection>) -> Self {
+    fn new(session: imap::Session<Connection>, sync_drafts: bool) -> Self {
         Self {
             session,
+            selected: None,
             uids: vec![],
+            sync_drafts,
+            mailboxes: None,
         }
     }
 
-    fn prepare_uids(&mut self) -> Result<(), crate::Error> {
-        self.session.select("INBOX")?;
+    /// Name of the mailbox with the given special use, only listed again when not known yet.
+    fn special_use_name(&mut self, special_use: SpecialUse) -> Result<String, crate::Error> {
+        let mailboxes = match self.mailboxes.take() {
+            Some(mailboxes) => mailboxes,
+            None => self.list_mailboxes()?,
+        };
+        let name = special_use_mailbox(&mailboxes, special_use).map(str::to_string);
+        self.mailboxes = Some(mailboxes);
+        name
+    }
+
+    fn prepare_uids(&mut self, mailbox: &str) -> Result<(), crate::Error> {
+        self.session.select(mailbox)?;
 
         // This is really slow but at least we're caching them
         self.uids = self
@@ -139,134 +277,296 @@ impl AuthenticatedState {
             .into_iter()
             .sorted()
             .collect::<Vec<_>>();
+        self.selected = Some(mailbox.to_string());
 
         Ok(())
     }
 
-    // This could be made faster, or at least more interactive if we sent the emails one bby one to the main thread for display
-    pub fn read_inbox(
-        &mut self,
-        count: u32,
-        offset: u32,
-    ) -> Result<Vec<ParsedEmail>, crate::Error> {
-        self.session.select("INBOX")?;
-
-        if self.uids.is_empty() {
-            self.prepare_uids()?;
+    fn select(&mut self, mailbox: &str) -> Result<(), crate::Error> {
+        if self.selected.as_deref() != Some(mailbox) {
+            self.prepare_uids(mailbox)?;
         }
-        if self.uids.is_empty() {
+        Ok(())
+    }
+
+    pub fn capabilities(&mut self) -> Result<Vec<String>, crate::Error> {
+        Ok(capability_names(&self.session.capabilities()?))
+    }
+
+    /// UIDs matching the IMAP `SEARCH` query, oldest first.
+    pub fn search(&mut self, mailbox: &str, query: &str) -> Result<Vec<u32>, crate::Error> {
+        self.select(mailbox)?;
+        Ok(self
+            .session
+            .uid_search(query)?
+            .into_iter()
+            .sorted()
+            .collect())
+    }
+
+    /// Full messages along with their internal date, in the same order as `uids`.
+    pub fn fetch_raw(&mut self, uids: &[u32]) -> Result<Vec<RawMessage>, crate::Error> {
+        if uids.is_empty() {
             return Ok(vec![]);
         }
+        let messages = self.session.uid_fetch(
+            uids.iter().join(","),
+            "(UID FLAGS INTERNALDATE BODY.PEEK[])",
+        )?;
+        Ok(messages
+            .iter()
+            .filter_map(|message| Some((message.uid?, message)))
+            .sorted_by_key(|(uid, _)| uids.iter().position(|u| u == uid))
+            .filter_map(|(_, message)| {
+                Some(RawMessage {
+                    raw: message.body()?.to_vec(),
+                    internal_date: message.internal_date().map(|date| date.to_utc()),
+                    flags: message
+                        .flags()
+                        .iter()
+                        .filter_map(Self::flag_from_imap)
+                        .collect(),
+                })
+            })
+            .collect())
+    }
 
-        let last_idx = self.uids.len() - 1; // Worst case this is 0 and it always exists
-        let top = self.uids[last_idx.saturating_sub(offset as usize)];
-        let bot = self.uids[last_idx.saturating_sub((offset + count - 1) as usize)]; // -1 because IMAP range is inclusive
+    /// A full message from `mailbox`, without marking it as seen.
+    pub fn fetch_one(&mut self, mailbox: &str, uid: u32) -> Result<RawMessage, crate::Error> {
+        self.select(mailbox)?;
+        let Some(message) = self.fetch_raw(&[uid])?.pop() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Message {uid} not found in {mailbox}"),
+            ))?;
+        };
+        Ok(message)
+    }
+
+    /// Headers of the newest `count` messages matching the IMAP `SEARCH` query, newest first.
+    pub fn search_messages(
+        &mut self,
+        mailbox: &str,
+        query: &str,
+        count: usize,
+    ) -> Result<Vec<ParsedEmail>, crate::Error> {
+        let uids = self.search(mailbox, query)?;
+        let newest = &uids[uids.len().saturating_sub(count)..];
+        if newest.is_empty() {
+            return Ok(vec![]);
+        }
+        self.fetch_headers(newest.iter().join(","))
+    }
 
+    /// Headers of the messages in the UID `set` of the selected mailbox, newest first.
+    fn fetch_headers(&mut self, set: String) -> Result<Vec<ParsedEmail>, crate::Error> {
+        // PEEK so we don't mark everything as read just by listing it
         let messages = self
             .session
-            .uid_fetch(format!("{bot}:{top}"), "(UID INTERNALDATE RFC822)")?;
+            .uid_fetch(set, "(UID FLAGS INTERNALDATE BODY.PEEK[HEADER])")?;
 
         let mut parsed_emails = Vec::with_capacity(messages.len());
-        let parser = MessageParser::new();
         for message in messages.iter() {
-            let Some(body) = message.body() else {
-                tracing::warn!("Email does not contain a body, ignoring");
+            let Some(header) = message.header() else {
+                tracing::warn!("Email does not contain a header, ignoring");
                 continue;
             };
 
-            let parsed = match parser.parse(body) {
-                Some(parsed) => parsed,
-                None => {
-                    tracing::error!("Failed to parse email message, ignoring...");
-                    continue;
-                }
-            };
-
-            let date = match message.internal_date() {
-                Some(date) => date.to_utc(),
-                None => match parsed.date() {
-                    Some(parsed_date) => {
-                        DateTime::parse_from_rfc3339(parsed_date.to_rfc3339().as_str())
-                            .expect("one of the libraries messed up RFC3339")
-                            .to_utc()
-                    }
-                    None => {
-                        tracing::warn!("No date was found, defaulting to UNIX_EPOCH");
-                        DateTime::<Utc>::UNIX_EPOCH
-                    }
-                },
+            let flags = message
+                .flags()
+                .iter()
+                .filter_map(Self::flag_from_imap)
+                .collect();
+            let Some(parsed) = ParsedEmail::parse(
+                message.uid.unwrap_or_default(),
+                header,
+                message.internal_date().map(|date| date.to_utc()),
+                flags,
+            ) else {
+                tracing::error!("Failed to parse email message, ignoring...");
+                continue;
             };
-
-            parsed_emails.push(ParsedEmail {
-                uid: message.uid.unwrap_or_default(),
-                date,
-                from: Self::get_from(&parsed),
-                cc: Self::get_cc(&parsed),
-                bcc: Self::get_bcc(&parsed),
-                subject: parsed.subject().unwrap_or("No subject").to_string(),
-                body: (0..parsed.text_body_count())
-                    .map(|idx| parsed.body_text(idx).unwrap_or_default().to_string())
-                    .join(""),
-            });
+            parsed_emails.push(parsed);
         }
         parsed_emails.sort_by_cached_key(|parsed| cmp::Reverse(parsed.uid));
         Ok(parsed_emails)
     }
 
-    pub fn get_from(parsed: &mail_parser::Message) -> String {
-        let from = match parsed.from() {
-            Some(from) => from,
-            None => return "No sender".to_string(),
-        };
-
-        let sender = match from.first() {
-            Some(sender) => sender,
-            None => return "No sender".to_string(),
-        };
+    fn flag_to_imap(flag: &Flag) -> imap::types::Flag<'static> {
+        match flag {
+            Flag::Seen => imap::types::Flag::Seen,
+            Flag::Answered => imap::types::Flag::Answered,
+            Flag::Flagged => imap::types::Flag::Flagged,
+            Flag::Deleted => imap::types::Flag::Deleted,
+            Flag::Draft => imap::types::Flag::Draft,
+        }
+    }
 
-        match (&sender.name, &sender.address) {
-            (None, None) => "Unknown sender".to_string(),
-            (None, Some(address)) => address.to_string(),
-            (Some(name), None) => name.to_string(),
-            (Some(name), Some(address)) => format!("{name} ({address})"),
+    fn flag_from_imap(flag: &imap::types::Flag) -> Option<Flag> {
+        match flag {
+            imap::types::Flag::Seen => Some(Flag::Seen),
+            imap::types::Flag::Answered => Some(Flag::Answered),
+            imap::types::Flag::Flagged => Some(Flag::Flagged),
+            imap::types::Flag::Deleted => Some(Flag::Deleted),
+            imap::types::Flag::Draft => Some(Flag::Draft),
+            _ => None,
         }
     }
 
-    pub fn get_cc(parsed: &mail_parser::Message) -> Vec<String> {
-        let cc = match parsed.cc() {
-            Some(cc) => cc,
-            None => return vec![],
-        };
+    fn special_use(attributes: &[NameAttribute]) -> Option<SpecialUse> {
+        attributes.iter().find_map(|attribute| match attribute {
+            NameAttribute::All => Some(SpecialUse::All),
+            NameAttribute::Archive => Some(SpecialUse::Archive),
+            NameAttribute::Drafts => Some(SpecialUse::Drafts),
+            NameAttribute::Flagged => Some(SpecialUse::Flagged),
+            NameAttribute::Junk => Some(SpecialUse::Junk),
+            NameAttribute::Sent => Some(SpecialUse::Sent),
+            NameAttribute::Trash => Some(SpecialUse::Trash),
+            _ => None,
+        })
+    }
+}
 
-        // I could parse the groups manually and probably get slightly better perf here but this works
-        cc.clone()
-            .into_list()
+impl MailStore for AuthenticatedState {
+    fn list_mailboxes(&mut self) -> Result<Vec<Mailbox>, crate::Error> {
+        let names = self.session.list(None, Some("*"))?;
+        let mailboxes = names
             .iter()
-            .map(|addr| match (&addr.name, &addr.address) {
-                (None, None) => "Unknown CC".to_string(),
-                (None, Some(address)) => address.to_string(),
-                (Some(name), None) => name.to_string(),
-                (Some(name), Some(address)) => format!("{name} ({address})"),
+            .filter(|name| !name.attributes().contains(&NameAttribute::NoSelect))
+            .map(|name| Mailbox {
+                name: name.name().to_string(),
+                special_use: Self::special_use(name.attributes()),
             })
-            .collect::<Vec<_>>()
+            .collect::<Vec<_>>();
+        self.mailboxes = Some(mailboxes.clone());
+        Ok(mailboxes)
+    }
+
+    // This could be made faster, or at least more interactive if we sent the emails one bby one to the main thread for display
+    fn list_messages(
+        &mut self,
+        mailbox: &str,
+        count: u32,
+        offset: u32,
+    ) -> Result<Vec<ParsedEmail>, crate::Error> {
+        // Starting from the top means the user wants to see what's new
+        if offset == 0 {
+            self.prepare_uids(mailbox)?;
+        } else {
+            self.select(mailbox)?;
+        }
+        if self.uids.is_empty() || offset as usize >= self.uids.len() || count == 0 {
+            return Ok(vec![]);
+        }
+
+        let last_idx = self.uids.len() - 1; // Worst case this is 0 and it always exists
+        let top = self.uids[last_idx.saturating_sub(offset as usize)];
+        let bot = self.uids[last_idx.saturating_sub((offset + count - 1) as usize)]; // -1 because IMAP range is inclusive
+
+        self.fetch_headers(format!("{bot}:{top}"))
     }
 
-    pub fn get_bcc(parsed: &mail_parser::Message) -> Vec<String> {
-        let bcc = match parsed.bcc() {
-            Some(bcc) => bcc,
-            None => return vec![],
+    fn fetch_message(&mut self, mailbox: &str, uid: u32) -> Result<Vec<u8>, crate::Error> {
+        self.select(mailbox)?;
+        let messages = self
+            .session
+            .uid_fetch(uid.to_string(), "(UID BODY.PEEK[])")?;
+        let Some(body) = messages.iter().find_map(|message| message.body()) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Message {uid} not found in {mailbox}"),
+            ))?;
         };
+        Ok(body.to_vec())
+    }
 
-        // I could parse the groups manually and probably get slightly better perf here but this works
-        bcc.clone()
-            .into_list()
+    fn set_flags(
+        &mut self,
+        mailbox: &str,
+        uid: u32,
+        flags: &[Flag],
+        enable: bool,
+    ) -> Result<(), crate::Error> {
+        self.select(mailbox)?;
+        let flags = flags
             .iter()
-            .map(|addr| match (&addr.name, &addr.address) {
-                (None, None) => "Unknown BCC".to_string(),
-                (None, Some(address)) => address.to_string(),
-                (Some(name), None) => name.to_string(),
-                (Some(name), Some(address)) => format!("{name} ({address})"),
-            })
-            .collect::<Vec<_>>()
+            .map(|flag| Self::flag_to_imap(flag).to_string())
+            .join(" ");
+        let operation = if enable {
+            "+FLAGS.SILENT"
+        } else {
+            "-FLAGS.SILENT"
+        };
+        self.session
+            .uid_store(uid.to_string(), format!("{operation} ({flags})"))?;
+        Ok(())
+    }
+
+    fn move_message(
+        &mut self,
+        mailbox: &str,
+        uid: u32,
+        destination: &str,
+    ) -> Result<(), crate::Error> {
+        self.select(mailbox)?;
+        if self.session.capabilities()?.has_str("MOVE") {
+            self.session.uid_mv(uid.to_string(), destination)?;
+        } else {
+            // RFC 6851 describes this as the way to go about it without MOVE
+            self.session.uid_copy(uid.to_string(), destination)?;
+            self.session
+                .uid_store(uid.to_string(), "+FLAGS.SILENT (\\Deleted)")?;
+            self.session.uid_expunge(uid.to_string())?;
+        }
+        self.uids.retain(|cached| *cached != uid);
+        Ok(())
+    }
+
+    fn append(
+        &mut self,
+        special_use: SpecialUse,
+        raw: &[u8],
+        flags: &[Flag],
+    ) -> Result<(), crate::Error> {
+        let mailbox = self.special_use_name(special_use)?;
+        let appended = self
+            .session
+            .append(&mailbox, raw)
+            .flags(flags.iter().map(|flag| Self::flag_to_imap(flag)))
+            .finish();
+        if appended.is_err() {
+            // It may have been renamed or deleted since it was listed
+            self.mailboxes = None;
+        }
+        appended?;
+        if self.selected.as_deref() == Some(mailbox.as_str()) {
+            // The cached UIDs are missing the new message
+            self.selected = None;
+        }
+        Ok(())
+    }
+
+    fn save_draft(&mut self, id: &str, raw: &[u8]) -> Result<(), crate::Error> {
+        if !self.sync_drafts {
+            return Ok(());
+        }
+        self.remove_draft(id)?;
+        self.append(SpecialUse::Drafts, raw, &[Flag::Draft, Flag::Seen])
+    }
+
+    fn remove_draft(&mut self, id: &str) -> Result<(), crate::Error> {
+        if !self.sync_drafts {
+            return Ok(());
+        }
+        let mailbox = self.special_use_name(SpecialUse::Drafts)?;
+        let uids = self.search(&mailbox, &format!("HEADER {DRAFT_HEADER} \"{id}\""))?;
+        if uids.is_empty() {
+            return Ok(());
+        }
+        let set = uids.iter().join(",");
+        self.session.uid_store(&set, "+FLAGS.SILENT (\\Deleted)")?;
+        self.session.uid_expunge(&set)?;
+        self.uids.retain(|cached| !uids.contains(cached));
+        Ok(())
     }
 }
@@ -0,0 +1,352 @@
+//! Finding the IMAP and SMTP settings for an address, the way Thunderbird does: known providers,
+//! [autoconfig](https://wiki.mozilla.org/Thunderbird:Autoconfiguration) files and
+//! [RFC 6186](https://www.rfc-editor.org/rfc/rfc6186) SRV records.
+
+use std::{fmt, fs, path::PathBuf, time::Duration};
+
+use oauth2::reqwest;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use crate::{init::srv, tls::TlsMode};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Server {
+    pub host: String,
+    pub port: u16,
+    pub tls: TlsMode,
+    /// What to log in with, usually the address itself.
+    pub login: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OAuthEndpoints {
+    pub auth_url: String,
+    pub token_url: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub imap: Option<Server>,
+    pub smtp: Option<Server>,
+    /// Set when the provider supports (or requires) OAuth.
+    pub oauth: Option<OAuthEndpoints>,
+    /// Whether sent messages need to be appended to the Sent mailbox by us.
+    pub save_sent: bool,
+}
+
+/// Where the settings were found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Preset,
+    Cache(PathBuf),
+    WellKnown(String),
+    Srv,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Preset => write!(f, "known provider"),
+            Source::Cache(path) => write!(f, "autoconfig file {}", path.display()),
+            Source::WellKnown(url) => write!(f, "autoconfig from {url}"),
+            Source::Srv => write!(f, "SRV records"),
+        }
+    }
+}
+
+fn server(host: &str, port: u16, tls: TlsMode, login: &str) -> Option<Server> {
+    Some(Server {
+        host: host.to_string(),
+        port,
+        tls,
+        login: login.to_string(),
+    })
+}
+
+/// Settings for the big providers, which don't always publish autoconfig files themselves.
+pub fn preset(address: &str, domain: &str) -> Option<Settings> {
+    let settings = match domain {
+        "gmail.com" | "googlemail.com" => Settings {
+            imap: server("imap.gmail.com", 993, TlsMode::Implicit, address),
+            smtp: server("smtp.gmail.com", 465, TlsMode::Implicit, address),
+            oauth: Some(OAuthEndpoints {
+                auth_url: "https://accounts.google.com/o/oauth2/auth".to_string(),
+                token_url: "https://oauth2.googleapis.com/token".to_string(),
+            }),
+            // Gmail keeps a copy of everything sent through its SMTP server
+            save_sent: false,
+        },
+        "outlook.com" | "hotmail.com" | "live.com" | "msn.com" => Settings {
+            imap: server("outlook.office365.com", 993, TlsMode::Implicit, address),
+            smtp: server("smtp-mail.outlook.com", 587, TlsMode::StartTls, address),
+            oauth: Some(OAuthEndpoints {
+                auth_url: "https://login.microsoftonline.com/common/oauth2/v2.0/authorize"
+                    .to_string(),
+                token_url: "https://login.microsoftonline.com/common/oauth2/v2.0/token".to_string(),
+            }),
+            save_sent: false,
+        },
+        "yahoo.com" | "ymail.com" => Settings {
+            imap: server("imap.mail.yahoo.com", 993, TlsMode::Implicit, address),
+            smtp: server("smtp.mail.yahoo.com", 465, TlsMode::Implicit, address),
+            oauth: None,
+            save_sent: true,
+        },
+        "icloud.com" | "me.com" | "mac.com" => Settings {
+            imap: server("imap.mail.me.com", 993, TlsMode::Implicit, address),
+            smtp: server("smtp.mail.me.com", 587, TlsMode::StartTls, address),
+            oauth: None,
+            save_sent: true,
+        },
+        "fastmail.com" | "fastmail.fm" => Settings {
+            imap: server("imap.fastmail.com", 993, TlsMode::Implicit, address),
+            smtp: server("smtp.fastmail.com", 465, TlsMode::Implicit, address),
+            oauth: None,
+            save_sent: true,
+        },
+        _ => return None,
+    };
+    Some(settings)
+}
+
+/// Read an autoconfig (`config-v1.1.xml`) file, `None` if it has neither an IMAP nor
+/// an SMTP server.
+pub fn parse_autoconfig(xml: &str, address: &str) -> Option<Settings> {
+    let document = roxmltree::Document::parse(xml).ok()?;
+    let provider = document
+        .descendants()
+        .find(|node| node.has_tag_name("emailProvider"))?;
+    let local_part = address.split('@').next().unwrap_or(address);
+    let domain = address.rsplit('@').next().unwrap_or_default();
+
+    let child = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|child| child.has_tag_name(name))
+            .and_then(|child| child.text())
+            .map(str::trim)
+            .map(str::to_string)
+    };
+    let find_server = |tag: &str, kind: &str| {
+        let node = provider
+            .children()
+            .find(|node| node.has_tag_name(tag) && node.attribute("type") == Some(kind))?;
+        let tls = match child(node, "socketType")?.to_ascii_uppercase().as_str() {
+            "SSL" => TlsMode::Implicit,
+            "STARTTLS" => TlsMode::StartTls,
+            _ => TlsMode::None,
+        };
+        let login = child(node, "username")
+            .unwrap_or_else(|| "%EMAILADDRESS%".to_string())
+            .replace("%EMAILADDRESS%", address)
+            .replace("%EMAILLOCALPART%", local_part)
+            .replace("%EMAILDOMAIN%", domain);
+        Some(Server {
+            host: child(node, "hostname")?,
+            port: child(node, "port")?.parse().ok()?,
+            tls,
+            login,
+        })
+    };
+
+    let imap = find_server("incomingServer", "imap");
+    let smtp = find_server("outgoingServer", "smtp");
+    if imap.is_none() && smtp.is_none() {
+        return None;
+    }
+    let oauth = document
+        .descendants()
+        .find(|node| node.has_tag_name("oAuth2"))
+        .and_then(|node| {
+            Some(OAuthEndpoints {
+                auth_url: child(node, "authURL")?,
+                token_url: child(node, "tokenURL")?,
+            })
+        });
+    Some(Settings {
+        imap,
+        smtp,
+        oauth,
+        save_sent: true,
+    })
+}
+
+/// Autoconfig files are kept under `<OS cache folder>/ectt/autoconfig/<domain>.xml`, one can be
+/// put there by hand for domains which don't publish theirs.
+pub fn cache_path(domain: &str) -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| {
+        dir.join("ectt")
+            .join("autoconfig")
+            .join(format!("{domain}.xml"))
+    })
+}
+
+fn fetch(url: &str) -> Result<String, crate::Error> {
+    let client = reqwest::blocking::ClientBuilder::new()
+        .timeout(HTTP_TIMEOUT)
+        .build()?;
+    Ok(client.get(url).send()?.error_for_status()?.text()?)
+}
+
+fn srv_settings(address: &str, domain: &str) -> Option<Settings> {
+    let resolver = srv::resolver()
+        .inspect_err(|err| tracing::warn!("No DNS resolver for SRV lookups: {err}"))
+        .ok()?;
+    let lookup = |services: [(&str, TlsMode); 2]| {
+        services.into_iter().find_map(|(service, tls)| {
+            let records = srv::lookup(&resolver, &format!("{service}._tcp.{domain}"))
+                .inspect_err(|err| tracing::warn!("SRV lookup for {domain} failed: {err}"))
+                .ok()?;
+            let record = srv::pick(records)?;
+            server(&record.target, record.port, tls, address)
+        })
+    };
+    // RFC 8314 prefers implicit TLS over STARTTLS
+    let imap = lookup([("_imaps", TlsMode::Implicit), ("_imap", TlsMode::StartTls)]);
+    let smtp = lookup([
+        ("_submissions", TlsMode::Implicit),
+        ("_submission", TlsMode::StartTls),
+    ]);
+    if imap.is_none() && smtp.is_none() {
+        return None;
+    }
+    Some(Settings {
+        imap,
+        smtp,
+        oauth: None,
+        save_sent: true,
+    })
+}
+
+/// Look the settings for `address` up, trying every source in turn.
+pub fn discover(address: &str) -> Option<(Settings, Source)> {
+    let domain = address.rsplit('@').next()?.to_ascii_lowercase();
+    if let Some(settings) = preset(address, &domain) {
+        return Some((settings, Source::Preset));
+    }
+
+    let cache = cache_path(&domain);
+    if let Some(path) = &cache {
+        if let Ok(xml) = fs::read_to_string(path) {
+            if let Some(settings) = parse_autoconfig(&xml, address) {
+                return Some((settings, Source::Cache(path.clone())));
+            }
+        }
+    }
+
+    // `+` and the like would otherwise change the address or the query
+    let encoded = utf8_percent_encode(address, NON_ALPHANUMERIC);
+    let urls = [
+        format!("https://autoconfig.{domain}/mail/config-v1.1.xml?emailaddress={encoded}"),
+        format!("https://{domain}/.well-known/autoconfig/mail/config-v1.1.xml"),
+    ];
+    for url in urls {
+        let xml = match fetch(&url) {
+            Ok(xml) => xml,
+            Err(err) => {
+                tracing::info!("No autoconfig at {url}: {err}");
+                continue;
+            }
+        };
+        let Some(settings) = parse_autoconfig(&xml, address) else {
+            continue;
+        };
+        if let Some(path) = &cache {
+            let saved = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(path, &xml));
+            if let Err(err) = saved {
+                tracing::warn!("Failed to cache {url} at {}: {err}", path.display());
+            }
+        }
+        return Some((settings, Source::WellKnown(url)));
+    }
+
+    srv_settings(address, &domain).map(|settings| (settings, Source::Srv))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tls::TlsMode;
+
+    use super::{parse_autoconfig, preset, OAuthEndpoints, Server};
+
+    const AUTOCONFIG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<clientConfig version="1.1">
+  <emailProvider id="example.com">
+    <domain>example.com</domain>
+    <incomingServer type="pop3">
+      <hostname>pop.example.com</hostname>
+      <port>995</port>
+      <socketType>SSL</socketType>
+      <username>%EMAILADDRESS%</username>
+    </incomingServer>
+    <incomingServer type="imap">
+      <hostname>imap.example.com</hostname>
+      <port>143</port>
+      <socketType>STARTTLS</socketType>
+      <username>%EMAILLOCALPART%</username>
+      <authentication>password-cleartext</authentication>
+    </incomingServer>
+    <outgoingServer type="smtp">
+      <hostname> smtp.example.com </hostname>
+      <port>465</port>
+      <socketType>SSL</socketType>
+      <username>%EMAILADDRESS%</username>
+      <authentication>OAuth2</authentication>
+    </outgoingServer>
+  </emailProvider>
+  <oAuth2>
+    <issuer>login.example.com</issuer>
+    <authURL>https://login.example.com/authorize</authURL>
+    <tokenURL>https://login.example.com/token</tokenURL>
+  </oAuth2>
+</clientConfig>"#;
+
+    #[test]
+    fn autoconfig_files_are_understood() {
+        let settings = parse_autoconfig(AUTOCONFIG, "jose@example.com").unwrap();
+        assert_eq!(
+            settings.imap,
+            Some(Server {
+                host: "imap.example.com".to_string(),
+                port: 143,
+                tls: TlsMode::StartTls,
+                login: "jose".to_string(),
+            })
+        );
+        assert_eq!(
+            settings.smtp,
+            Some(Server {
+                host: "smtp.example.com".to_string(),
+                port: 465,
+                tls: TlsMode::Implicit,
+                login: "jose@example.com".to_string(),
+            })
+        );
+        assert_eq!(
+            settings.oauth,
+            Some(OAuthEndpoints {
+                auth_url: "https://login.example.com/authorize".to_string(),
+                token_url: "https://login.example.com/token".to_string(),
+            })
+        );
+
+        assert_eq!(
+            parse_autoconfig("<clientConfig/>", "jose@example.com"),
+            None
+        );
+        assert_eq!(parse_autoconfig("not xml", "jose@example.com"), None);
+    }
+
+    #[test]
+    fn known_providers_have_presets() {
+        let gmail = preset("jose@gmail.com", "gmail.com").unwrap();
+        assert_eq!(gmail.imap.unwrap().host, "imap.gmail.com");
+        assert!(gmail.oauth.is_some());
+        assert!(!gmail.save_sent);
+        assert_eq!(preset("jose@example.com", "example.com"), None);
+    }
+}
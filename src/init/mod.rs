@@ -0,0 +1,314 @@
+//! `ectt init`, asks a few questions and writes a working configuration.
+
+use std::{
+    io::{self, IsTerminal, Write},
+    path::Path,
+};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use lettre::Address;
+use serde_json::{json, Value};
+
+use crate::{
+    check, config,
+    init::autoconfig::{OAuthEndpoints, Server, Settings},
+    tls::TlsMode,
+};
+
+pub mod autoconfig;
+mod srv;
+
+fn read_answer(prompt: &str) -> Result<String, crate::Error> {
+    print!("{prompt}: ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer)? == 0 {
+        Err(io::Error::from(io::ErrorKind::UnexpectedEof))?
+    }
+    Ok(answer.trim().to_string())
+}
+
+/// Ask until there's an answer, an empty one picks `default`.
+fn ask(question: &str, default: Option<&str>) -> Result<String, crate::Error> {
+    let prompt = match default {
+        Some(default) => format!("{question} [{default}]"),
+        None => question.to_string(),
+    };
+    loop {
+        match (read_answer(&prompt)?, default) {
+            (answer, Some(default)) if answer.is_empty() => return Ok(default.to_string()),
+            (answer, None) if answer.is_empty() => continue,
+            (answer, _) => return Ok(answer),
+        }
+    }
+}
+
+fn confirm(question: &str, default: bool) -> Result<bool, crate::Error> {
+    let prompt = format!("{question} [{}]", if default { "Y/n" } else { "y/N" });
+    loop {
+        match read_answer(&prompt)?.to_ascii_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer yes or no"),
+        }
+    }
+}
+
+/// Ask without echoing the answer, when there's a terminal to do it on.
+fn ask_secret(question: &str) -> Result<String, crate::Error> {
+    if !io::stdin().is_terminal() {
+        return ask(question, None);
+    }
+    print!("{question}: ");
+    io::stdout().flush()?;
+    crossterm::terminal::enable_raw_mode()?;
+    let secret = read_secret();
+    crossterm::terminal::disable_raw_mode()?;
+    println!();
+    secret
+}
+
+fn read_secret() -> Result<String, crate::Error> {
+    let mut secret = String::new();
+    loop {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+        match code {
+            KeyCode::Enter if !secret.is_empty() => return Ok(secret),
+            KeyCode::Backspace => {
+                secret.pop();
+            }
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                Err(io::Error::from(io::ErrorKind::Interrupted))?
+            }
+            KeyCode::Char(c) => secret.push(c),
+            _ => {}
+        }
+    }
+}
+
+fn describe(server: &Server) -> String {
+    let tls = match server.tls {
+        TlsMode::Implicit => "TLS",
+        TlsMode::StartTls => "STARTTLS",
+        TlsMode::None => "no encryption",
+    };
+    format!(
+        "{}:{} ({tls}), logging in as {}",
+        server.host, server.port, server.login
+    )
+}
+
+/// Ask for every setting of a server, starting from what was found.
+fn edit_server(kind: &str, server: Server) -> Result<Server, crate::Error> {
+    let host = ask(&format!("{kind} server"), Some(&server.host))?;
+    let tls = loop {
+        let current = serde_json::to_value(server.tls)?;
+        let answer = ask("Encryption, implicit, starttls or none", current.as_str())?;
+        match serde_json::from_value::<TlsMode>(Value::String(answer.to_ascii_lowercase())) {
+            Ok(tls) => break tls,
+            Err(_) => println!("Please answer implicit, starttls or none"),
+        }
+    };
+    let port = loop {
+        match ask("Port", Some(&server.port.to_string()))?.parse::<u16>() {
+            Ok(port) => break port,
+            Err(err) => println!("{err}"),
+        }
+    };
+    let login = ask("Login", Some(&server.login))?;
+    Ok(Server {
+        host,
+        port,
+        tls,
+        login,
+    })
+}
+
+/// The `auth` object for the configuration, shared by both servers.
+fn ask_auth(settings: &Settings) -> Result<Value, crate::Error> {
+    let default = if settings.oauth.is_some() {
+        "oauth"
+    } else {
+        "password"
+    };
+    loop {
+        match ask("Authentication, password or oauth", Some(default))?.as_str() {
+            "password" => {
+                let password = ask_secret("Password (or app password)")?;
+                return Ok(json!({ "type": "password", "raw": password }));
+            }
+            "oauth" => return ask_oauth(settings.oauth.as_ref()),
+            _ => println!("Please answer password or oauth"),
+        }
+    }
+}
+
+fn ask_oauth(endpoints: Option<&OAuthEndpoints>) -> Result<Value, crate::Error> {
+    #[cfg(feature = "refresher")]
+    if endpoints.is_some_and(|endpoints| endpoints.auth_url.contains("accounts.google.com"))
+        && confirm(
+            "Log in through the browser with eCTT's own Gmail client?",
+            true,
+        )?
+    {
+        return gmail_login();
+    }
+
+    println!(
+        "You'll need an OAuth client registered with your provider, and a refresh token for it"
+    );
+    let client_id = ask("Client ID", None)?;
+    let client_secret = ask_secret("Client secret")?;
+    let auth_url = ask(
+        "Authorization URL",
+        endpoints.map(|endpoints| endpoints.auth_url.as_str()),
+    )?;
+    let token_url = ask(
+        "Token URL",
+        endpoints.map(|endpoints| endpoints.token_url.as_str()),
+    )?;
+    let refresh_token = ask_secret("Refresh token")?;
+    // The access token is refreshed the first time it's rejected
+    Ok(json!({
+        "type": "oauth",
+        "client_id": client_id,
+        "client_secret": client_secret,
+        "auth_url": auth_url,
+        "token_url": token_url,
+        "access_token": "",
+        "refresh_token": refresh_token,
+    }))
+}
+
+#[cfg(feature = "refresher")]
+fn gmail_login() -> Result<Value, crate::Error> {
+    use crate::oauth::{spawn_login, LoginEvent, Provider};
+
+    let (client, scopes) = Provider::Gmail.into();
+    let auth = json!({
+        "type": "oauth",
+        "client_id": client.client_id().as_str(),
+        "client_secret": Provider::Gmail.client_secret().secret(),
+        "auth_url": client.auth_uri().as_str(),
+        "token_url": client.token_uri().as_str(),
+    });
+    let events = spawn_login(client, scopes);
+    loop {
+        let event = events
+            .recv()
+            .map_err(|_| io::Error::other("the login stopped unexpectedly"))?;
+        match event {
+            LoginEvent::Url(url) => println!("Open this URL to log in: {url}"),
+            LoginEvent::Success {
+                access_token,
+                refresh_token,
+            } => {
+                let Some(refresh_token) = refresh_token else {
+                    Err(io::Error::other("Google didn't send a refresh token"))?
+                };
+                let mut auth = auth;
+                auth["access_token"] = access_token.secret().clone().into();
+                auth["refresh_token"] = refresh_token.secret().clone().into();
+                return Ok(auth);
+            }
+            LoginEvent::Failed(err) => return Err(err),
+        }
+    }
+}
+
+/// Run the wizard, writing the configuration to `path`.
+pub fn run(path: &Path) -> Result<(), crate::Error> {
+    if path.exists()
+        && !confirm(
+            &format!("{} already exists, overwrite it?", path.display()),
+            false,
+        )?
+    {
+        return Ok(());
+    }
+
+    let address = loop {
+        match ask("Email address", None)?.parse::<Address>() {
+            Ok(address) => break address,
+            Err(err) => println!("That's not a valid address: {err}"),
+        }
+    };
+    let domain = address.domain().to_ascii_lowercase();
+
+    println!("Looking up the settings for {domain}...");
+    let mut settings = match autoconfig::discover(address.as_ref()) {
+        Some((settings, source)) => {
+            println!("Found them through the {source}");
+            settings
+        }
+        None => {
+            println!("Couldn't find them, please enter them by hand");
+            Settings {
+                save_sent: true,
+                ..Default::default()
+            }
+        }
+    };
+
+    let found = match (&settings.imap, &settings.smtp) {
+        (Some(imap), Some(smtp)) => {
+            println!("  IMAP: {}", describe(imap));
+            println!("  SMTP: {}", describe(smtp));
+            confirm("Use these settings?", true)?
+        }
+        _ => false,
+    };
+    let server = |kind: &str, found_server: Option<Server>, host: &str, port| {
+        let server = found_server.unwrap_or_else(|| Server {
+            host: format!("{host}.{domain}"),
+            port,
+            tls: TlsMode::Implicit,
+            login: address.to_string(),
+        });
+        if found {
+            Ok(server)
+        } else {
+            edit_server(kind, server)
+        }
+    };
+    let imap = server("IMAP", settings.imap.take(), "imap", 993)?;
+    let smtp = server("SMTP", settings.smtp.take(), "smtp", 465)?;
+
+    let auth = ask_auth(&settings)?;
+    let config = json!({
+        "read": {
+            "type": "imap",
+            "host": imap.host,
+            "port": imap.port,
+            "tls": imap.tls,
+            "login": imap.login,
+            "auth": auth,
+        },
+        "send": {
+            "type": "smtp",
+            "host": smtp.host,
+            "port": smtp.port,
+            "tls": smtp.tls,
+            "login": smtp.login,
+            "auth": auth,
+            "save_sent": settings.save_sent,
+        },
+    });
+
+    println!("Trying the settings out...");
+    if !check::configuration(&config) && !confirm("Save the configuration anyway?", false)? {
+        return Ok(());
+    }
+    config::write_value(path, &config)?;
+    println!("Saved the configuration to {}", path.display());
+    Ok(())
+}
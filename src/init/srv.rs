@@ -0,0 +1,71 @@
+//! SRV records, looked up through the system's resolver.
+
+use hickory_resolver::{error::ResolveErrorKind, Resolver};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// The record to use, the lowest priority and then the highest weight.
+///
+/// A target of `.` means the service isn't offered at all.
+pub fn pick(records: Vec<SrvRecord>) -> Option<SrvRecord> {
+    records
+        .into_iter()
+        .min_by_key(|record| (record.priority, std::cmp::Reverse(record.weight)))
+        .filter(|record| !record.target.is_empty())
+}
+
+/// A resolver configured like the system's, e.g. from `/etc/resolv.conf`.
+pub fn resolver() -> Result<Resolver, crate::Error> {
+    Ok(Resolver::from_system_conf()?)
+}
+
+/// SRV records for `name`, e.g. `_imaps._tcp.example.com`.
+pub fn lookup(resolver: &Resolver, name: &str) -> Result<Vec<SrvRecord>, crate::Error> {
+    let lookup = match resolver.srv_lookup(name) {
+        Ok(lookup) => lookup,
+        // NXDOMAIN or no SRV record, nothing to find
+        Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+            return Ok(vec![])
+        }
+        Err(err) => Err(std::io::Error::from(err))?,
+    };
+    Ok(lookup
+        .iter()
+        .map(|record| SrvRecord {
+            priority: record.priority(),
+            weight: record.weight(),
+            port: record.port(),
+            target: record.target().to_utf8().trim_end_matches('.').to_string(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pick, SrvRecord};
+
+    #[test]
+    fn preferred_records_are_picked() {
+        let record = |priority, weight, target: &str| SrvRecord {
+            priority,
+            weight,
+            port: 993,
+            target: target.to_string(),
+        };
+        let records = vec![
+            record(10, 5, "imap.example.com"),
+            record(0, 0, "backup.example.com"),
+            record(0, 10, "primary.example.com"),
+        ];
+        assert_eq!(pick(records).unwrap().target, "primary.example.com");
+
+        let unavailable = record(0, 0, "");
+        assert_eq!(pick(vec![unavailable]), None);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use oauth2::reqwest::{self, blocking::RequestBuilder, header::CONTENT_TYPE};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::jmap::config::{Auth, JmapConfig};
+
+pub const CORE: &str = "urn:ietf:params:jmap:core";
+pub const MAIL: &str = "urn:ietf:params:jmap:mail";
+pub const SUBMISSION: &str = "urn:ietf:params:jmap:submission";
+
+/// The parts of the session resource ([RFC 8620, section 2](https://www.rfc-editor.org/rfc/rfc8620#section-2)) we use.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    api_url: String,
+    download_url: String,
+    primary_accounts: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiResponse {
+    method_responses: Vec<(String, Value, String)>,
+}
+
+/// Error objects, both for method level errors and `/set` failures.
+#[derive(Debug, Deserialize)]
+struct ErrorObject {
+    #[serde(rename = "type")]
+    kind: String,
+    description: Option<String>,
+}
+
+impl From<ErrorObject> for crate::Error {
+    fn from(ErrorObject { kind, description }: ErrorObject) -> Self {
+        crate::Error::Jmap { kind, description }
+    }
+}
+
+pub struct Client {
+    http: reqwest::blocking::Client,
+    config: JmapConfig,
+    session: Session,
+    pub account_id: String,
+}
+
+impl Client {
+    /// Fetch the session resource, which tells us where everything else lives.
+    pub fn connect(config: JmapConfig) -> Result<Self, crate::Error> {
+        let http = reqwest::blocking::Client::new();
+        let response = authorize(&config, http.get(&config.session_url))
+            .send()?
+            .error_for_status()?;
+        let session: Session = serde_json::from_slice(&response.bytes()?)?;
+
+        let Some(account_id) = session.primary_accounts.get(MAIL).cloned() else {
+            return Err(crate::Error::Jmap {
+                kind: "accountNotFound".to_string(),
+                description: Some(format!("{} has no mail account", config.login)),
+            });
+        };
+
+        Ok(Self {
+            http,
+            config,
+            session,
+            account_id,
+        })
+    }
+
+    pub fn login(&self) -> &str {
+        &self.config.login
+    }
+
+    /// Make a single API request.
+    ///
+    /// Each call gets its position as the call id, so back-references can use `"0"`, `"1"`, etc.
+    /// Returns the arguments of the first response to each call, in order.
+    pub fn call(
+        &self,
+        using: &[&str],
+        calls: Vec<(&str, Value)>,
+    ) -> Result<Vec<Value>, crate::Error> {
+        let count = calls.len();
+        let method_calls = calls
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (name, arguments))| json!([name, arguments, idx.to_string()]))
+            .collect::<Vec<_>>();
+        let body = json!({
+            "using": using,
+            "methodCalls": method_calls,
+        });
+
+        let response = authorize(&self.config, self.http.post(&self.session.api_url))
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?)
+            .send()?
+            .error_for_status()?;
+        let response: ApiResponse = serde_json::from_slice(&response.bytes()?)?;
+
+        let mut results = vec![Value::Null; count];
+        for (name, arguments, call_id) in response.method_responses {
+            if name == "error" {
+                return Err(serde_json::from_value::<ErrorObject>(arguments)?.into());
+            }
+            // Some methods also respond with implicit calls (e.g. `onSuccessUpdateEmail`), we skip those
+            match call_id
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| results.get_mut(idx))
+            {
+                Some(result) if result.is_null() => *result = arguments,
+                _ => {}
+            }
+        }
+        Ok(results)
+    }
+
+    /// Download a blob, e.g. the full RFC 5322 message.
+    pub fn download(&self, blob_id: &str) -> Result<Vec<u8>, crate::Error> {
+        let url = self
+            .session
+            .download_url
+            .replace("{accountId}", &self.account_id)
+            .replace("{blobId}", blob_id)
+            .replace("{type}", "message%2Frfc822")
+            .replace("{name}", "message.eml");
+        let response = authorize(&self.config, self.http.get(url))
+            .send()?
+            .error_for_status()?;
+        Ok(response.bytes()?.to_vec())
+    }
+}
+
+fn authorize(config: &JmapConfig, request: RequestBuilder) -> RequestBuilder {
+    match &config.auth {
+        Auth::Password(password_config) => {
+            request.basic_auth(&config.login, Some(&password_config.raw))
+        }
+        Auth::Token(token_config) => request.bearer_auth(&token_config.raw),
+    }
+}
+
+/// Fail if any of the `/set` operations under `key` (e.g. `notCreated`) failed.
+pub fn check_set(arguments: &Value, key: &str) -> Result<(), crate::Error> {
+    let Some(failed) = arguments.get(key).and_then(Value::as_object) else {
+        return Ok(());
+    };
+    match failed.values().next() {
+        Some(error) => Err(serde_json::from_value::<ErrorObject>(error.clone())?.into()),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,65 @@
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct JmapConfig {
+    /// Where to discover the JMAP session, e.g. `https://api.fastmail.com/jmap/session`.
+    pub session_url: String,
+    pub login: String,
+    pub auth: Auth,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "lowercase")]
+pub enum Auth {
+    Password(PasswordConfig),
+    /// API token, sent as a bearer token.
+    Token(TokenConfig),
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PasswordConfig {
+    pub raw: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TokenConfig {
+    pub raw: String,
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::{imap::config::ReadBackend, smtp::config::SendBackend};
+
+    use super::{Auth, JmapConfig, TokenConfig};
+
+    #[test]
+    fn ensure_jmap_token_format() {
+        let json = json!({
+            "type": "jmap",
+            "session_url": "https://api.fastmail.com/jmap/session",
+            "login": "jose@fastmail.com",
+            "auth": {
+                "type": "token",
+                "raw": "fmu1-token"
+            }
+        });
+
+        let ReadBackend::Jmap(JmapConfig {
+            session_url,
+            login,
+            auth: Auth::Token(TokenConfig { raw }),
+        }) = serde_json::from_value(json.clone()).unwrap()
+        else {
+            panic!("wrong format");
+        };
+        assert_eq!(session_url, "https://api.fastmail.com/jmap/session");
+        assert_eq!(login, "jose@fastmail.com");
+        assert_eq!(raw, "fmu1-token");
+
+        // The same configuration is valid for sending
+        let SendBackend::Jmap(_) = serde_json::from_value(json).unwrap() else {
+            panic!("wrong format");
+        };
+    }
+}
@@ -0,0 +1,678 @@
+pub mod client;
+pub mod config;
+
+use std::{
+    collections::HashMap,
+    sync::mpsc::{Receiver, Sender},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    jmap::{
+        client::{check_set, Client, CORE, MAIL, SUBMISSION},
+        config::JmapConfig,
+    },
+    smtp::{MailTransport, PartialMessage},
+    store::{self, Flag, IdMap, MailStore, Mailbox, ParsedEmail, SpecialUse, INBOX},
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JmapMailbox {
+    id: String,
+    name: String,
+    role: Option<String>,
+    parent_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Email {
+    id: String,
+    received_at: Option<String>,
+    #[serde(default)]
+    keywords: HashMap<String, bool>,
+    #[serde(default)]
+    headers: Vec<Header>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    name: String,
+    value: String,
+}
+
+fn special_use(role: &str) -> Option<SpecialUse> {
+    match role {
+        "all" => Some(SpecialUse::All),
+        "archive" => Some(SpecialUse::Archive),
+        "drafts" => Some(SpecialUse::Drafts),
+        "flagged" => Some(SpecialUse::Flagged),
+        "junk" => Some(SpecialUse::Junk),
+        "sent" => Some(SpecialUse::Sent),
+        "trash" => Some(SpecialUse::Trash),
+        _ => None,
+    }
+}
+
+fn flag_to_keyword(flag: &Flag) -> Option<&'static str> {
+    match flag {
+        Flag::Seen => Some("$seen"),
+        Flag::Answered => Some("$answered"),
+        Flag::Flagged => Some("$flagged"),
+        Flag::Draft => Some("$draft"),
+        // JMAP deletes messages outright instead
+        Flag::Deleted => None,
+    }
+}
+
+fn flag_from_keyword(keyword: &str) -> Option<Flag> {
+    match keyword {
+        "$seen" => Some(Flag::Seen),
+        "$answered" => Some(Flag::Answered),
+        "$flagged" => Some(Flag::Flagged),
+        "$draft" => Some(Flag::Draft),
+        _ => None,
+    }
+}
+
+/// Get all mailboxes, named after their full path (e.g. `Lists/Rust`) except for the inbox.
+fn get_mailboxes(client: &Client) -> Result<Vec<(JmapMailbox, String)>, crate::Error> {
+    let responses = client.call(
+        &[CORE, MAIL],
+        vec![(
+            "Mailbox/get",
+            json!({
+                "accountId": client.account_id,
+                "ids": null,
+                "properties": ["id", "name", "role", "parentId"],
+            }),
+        )],
+    )?;
+    let mailboxes: Vec<JmapMailbox> = serde_json::from_value(responses[0]["list"].clone())?;
+
+    let by_id = mailboxes
+        .iter()
+        .map(|mailbox| (mailbox.id.as_str(), mailbox))
+        .collect::<HashMap<_, _>>();
+    let full_name = |mailbox: &JmapMailbox| {
+        if mailbox.role.as_deref() == Some("inbox") {
+            return INBOX.to_string();
+        }
+        let mut path = vec![mailbox.name.as_str()];
+        let mut parent = mailbox.parent_id.as_deref();
+        // Bounded in case the server sends us a cycle
+        while let Some(parent_mailbox) = parent.and_then(|id| by_id.get(id)) {
+            if path.len() > by_id.len() {
+                break;
+            }
+            path.push(&parent_mailbox.name);
+            parent = parent_mailbox.parent_id.as_deref();
+        }
+        path.reverse();
+        path.join("/")
+    };
+    let names = mailboxes.iter().map(full_name).collect::<Vec<_>>();
+
+    Ok(mailboxes.into_iter().zip(names).collect())
+}
+
+/// Run a [`JmapStore`], reporting connection errors to the main thread.
+#[tracing::instrument(skip_all)]
+pub fn jmap_thread(
+    config: JmapConfig,
+    rx: Receiver<store::Command>,
+    tx: Sender<store::Response>,
+) -> Result<(), crate::Error> {
+    match JmapStore::new(config) {
+        Ok(store) => store::run(store, rx, tx),
+        Err(err) => {
+            tracing::error!("Failed to connect to the JMAP server with error: {err}");
+            if let Err(err) = tx.send(store::Response::Error(err)) {
+                tracing::error!("Failed to send error message to main thread with error: {err}");
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Access to mail over JMAP ([RFC 8621](https://www.rfc-editor.org/rfc/rfc8621)).
+pub struct JmapStore {
+    client: Client,
+    ids: IdMap,
+    /// Mailbox IDs by name.
+    mailboxes: HashMap<String, String>,
+}
+
+impl JmapStore {
+    pub fn new(config: JmapConfig) -> Result<Self, crate::Error> {
+        Ok(Self {
+            client: Client::connect(config)?,
+            ids: IdMap::new(),
+            mailboxes: HashMap::new(),
+        })
+    }
+
+    fn mailbox_id(&mut self, mailbox: &str) -> Result<String, crate::Error> {
+        if !self.mailboxes.contains_key(mailbox) {
+            self.list_mailboxes()?;
+        }
+        match self.mailboxes.get(mailbox) {
+            Some(id) => Ok(id.clone()),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Mailbox {mailbox} does not exist"),
+            ))?,
+        }
+    }
+
+    fn email_id(&self, uid: u32) -> Result<String, crate::Error> {
+        match self.ids.key(uid) {
+            Some(id) => Ok(id.to_string()),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Unknown message {uid}"),
+            ))?,
+        }
+    }
+
+    fn update(&self, id: &str, patch: Value) -> Result<(), crate::Error> {
+        let responses = self.client.call(
+            &[CORE, MAIL],
+            vec![(
+                "Email/set",
+                json!({
+                    "accountId": self.client.account_id,
+                    "update": { id: patch },
+                }),
+            )],
+        )?;
+        check_set(&responses[0], "notUpdated")
+    }
+}
+
+impl MailStore for JmapStore {
+    fn list_mailboxes(&mut self) -> Result<Vec<Mailbox>, crate::Error> {
+        let mut mailboxes = get_mailboxes(&self.client)?
+            .into_iter()
+            .map(|(mailbox, name)| {
+                self.mailboxes.insert(name.clone(), mailbox.id);
+                Mailbox {
+                    name,
+                    special_use: mailbox.role.as_deref().and_then(special_use),
+                }
+            })
+            .collect::<Vec<_>>();
+        mailboxes.sort_by(|a, b| (a.name != INBOX, &a.name).cmp(&(b.name != INBOX, &b.name)));
+        Ok(mailboxes)
+    }
+
+    fn list_messages(
+        &mut self,
+        mailbox: &str,
+        count: u32,
+        offset: u32,
+    ) -> Result<Vec<ParsedEmail>, crate::Error> {
+        let mailbox_id = self.mailbox_id(mailbox)?;
+        let account_id = &self.client.account_id;
+        let responses = self.client.call(
+            &[CORE, MAIL],
+            vec![
+                (
+                    "Email/query",
+                    json!({
+                        "accountId": account_id,
+                        "filter": { "inMailbox": mailbox_id },
+                        "sort": [{ "property": "receivedAt", "isAscending": false }],
+                        "position": offset,
+                        "limit": count,
+                    }),
+                ),
+                (
+                    "Email/get",
+                    json!({
+                        "accountId": account_id,
+                        "#ids": { "resultOf": "0", "name": "Email/query", "path": "/ids" },
+                        "properties": ["id", "receivedAt", "keywords", "headers"],
+                    }),
+                ),
+            ],
+        )?;
+        let order: Vec<String> = serde_json::from_value(responses[0]["ids"].clone())?;
+        let mut emails: Vec<Email> = serde_json::from_value(responses[1]["list"].clone())?;
+        // `/get` doesn't have to keep the order of the query
+        emails.sort_by_key(|email| order.iter().position(|id| *id == email.id));
+
+        let mut parsed_emails = Vec::with_capacity(emails.len());
+        for email in emails {
+            // The headers come in their raw form, so the usual parser can handle them
+            let header = email
+                .headers
+                .iter()
+                .map(|header| format!("{}:{}\r\n", header.name, header.value))
+                .collect::<String>()
+                + "\r\n";
+            let date = email
+                .received_at
+                .as_deref()
+                .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                .map(|date| date.with_timezone(&Utc));
+            let flags = email
+                .keywords
+                .iter()
+                .filter(|(_, set)| **set)
+                .filter_map(|(keyword, _)| flag_from_keyword(keyword))
+                .collect();
+            let uid = self.ids.id(&email.id);
+            let Some(parsed) = ParsedEmail::parse(uid, header.as_bytes(), date, flags) else {
+                tracing::error!("Failed to parse email message, ignoring...");
+                continue;
+            };
+            parsed_emails.push(parsed);
+        }
+        Ok(parsed_emails)
+    }
+
+    fn fetch_message(&mut self, mailbox: &str, uid: u32) -> Result<Vec<u8>, crate::Error> {
+        let id = self.email_id(uid)?;
+        let responses = self.client.call(
+            &[CORE, MAIL],
+            vec![(
+                "Email/get",
+                json!({
+                    "accountId": self.client.account_id,
+                    "ids": [id],
+                    "properties": ["blobId"],
+                }),
+            )],
+        )?;
+        let Some(blob_id) = responses[0]["list"][0]["blobId"].as_str() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Message {uid} not found in {mailbox}"),
+            ))?;
+        };
+        self.client.download(blob_id)
+    }
+
+    fn set_flags(
+        &mut self,
+        _: &str,
+        uid: u32,
+        flags: &[Flag],
+        enable: bool,
+    ) -> Result<(), crate::Error> {
+        let id = self.email_id(uid)?;
+        let patch = flags
+            .iter()
+            .filter_map(flag_to_keyword)
+            .map(|keyword| {
+                let value = if enable {
+                    Value::Bool(true)
+                } else {
+                    Value::Null
+                };
+                (format!("keywords/{keyword}"), value)
+            })
+            .collect::<Map<_, _>>();
+        if patch.is_empty() {
+            return Ok(());
+        }
+        self.update(&id, Value::Object(patch))
+    }
+
+    fn move_message(&mut self, _: &str, uid: u32, destination: &str) -> Result<(), crate::Error> {
+        let id = self.email_id(uid)?;
+        let destination_id = self.mailbox_id(destination)?;
+        self.update(&id, json!({ "mailboxIds": { destination_id: true } }))
+    }
+}
+
+/// Mailboxes and identity needed for sending, looked up once.
+struct Submission {
+    identity_id: String,
+    drafts: String,
+    sent: Option<String>,
+}
+
+/// Sends mail by creating it in the Drafts and submitting it with `EmailSubmission/set`.
+pub struct JmapTransport {
+    config: JmapConfig,
+    /// Connected on the first send, to avoid a round trip when the user never sends anything.
+    client: Option<(Client, Submission)>,
+}
+
+impl JmapTransport {
+    pub fn new(config: JmapConfig) -> Self {
+        Self {
+            config,
+            client: None,
+        }
+    }
+
+    fn connect(config: JmapConfig) -> Result<(Client, Submission), crate::Error> {
+        let client = Client::connect(config)?;
+        let mailboxes = get_mailboxes(&client)?;
+        let role = |role: &str| {
+            mailboxes
+                .iter()
+                .find(|(mailbox, _)| mailbox.role.as_deref() == Some(role))
+                .map(|(mailbox, _)| mailbox.id.clone())
+        };
+        let Some(drafts) = role("drafts") else {
+            return Err(crate::Error::Jmap {
+                kind: "notFound".to_string(),
+                description: Some("there is no Drafts mailbox to send from".to_string()),
+            });
+        };
+        let sent = role("sent");
+
+        let responses = client.call(
+            &[CORE, MAIL, SUBMISSION],
+            vec![(
+                "Identity/get",
+                json!({ "accountId": client.account_id, "ids": null }),
+            )],
+        )?;
+        let identities = responses[0]["list"].as_array().cloned().unwrap_or_default();
+        // Prefer the identity matching the login, otherwise the server will pick the right one for us
+        let identity = identities
+            .iter()
+            .find(|identity| identity["email"].as_str() == Some(client.login()))
+            .or(identities.first());
+        let Some(identity_id) = identity.and_then(|identity| identity["id"].as_str()) else {
+            return Err(crate::Error::Jmap {
+                kind: "notFound".to_string(),
+                description: Some(format!("there is no identity for {}", client.login())),
+            });
+        };
+
+        let submission = Submission {
+            identity_id: identity_id.to_string(),
+            drafts,
+            sent,
+        };
+        Ok((client, submission))
+    }
+}
+
+impl MailTransport for JmapTransport {
+    fn send(&mut self, message: PartialMessage) -> Result<Option<Vec<u8>>, crate::Error> {
+        // Attachments would have to be uploaded as blobs first
+        if !message.attachments.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "JMAP can't send attachments yet",
+            ))?;
+        }
+        if self.client.is_none() {
+            self.client = Some(Self::connect(self.config.clone())?);
+        }
+        let (client, submission) = self.client.as_ref().expect("client was just connected");
+
+        let addresses = |addresses: Vec<lettre::Address>| {
+            addresses
+                .into_iter()
+                .map(|address| json!({ "email": address.to_string() }))
+                .collect::<Vec<_>>()
+        };
+        let email = json!({
+            "mailboxIds": { &submission.drafts: true },
+            "keywords": { "$draft": true, "$seen": true },
+            "from": [{ "email": client.login() }],
+            "to": addresses(message.to.into_iter().collect()),
+            "cc": addresses(message.cc),
+            "bcc": addresses(message.bcc),
+            "subject": message.subject.unwrap_or_default(),
+            "bodyValues": { "body": { "value": message.body.unwrap_or_default() } },
+            "textBody": [{ "partId": "body", "type": "text/plain" }],
+        });
+
+        let mut submission_set = json!({
+            "accountId": client.account_id,
+            "create": {
+                "submission": { "identityId": submission.identity_id, "emailId": "#draft" },
+            },
+        });
+        // Out of the drafts once it's sent, or gone if there's nowhere to keep it
+        match &submission.sent {
+            Some(sent) => {
+                submission_set["onSuccessUpdateEmail"] = json!({
+                    "#submission": {
+                        format!("mailboxIds/{}", submission.drafts): null,
+                        format!("mailboxIds/{sent}"): true,
+                        "keywords/$draft": null,
+                    },
+                });
+            }
+            None => submission_set["onSuccessDestroyEmail"] = json!(["#submission"]),
+        }
+
+        let responses = client.call(
+            &[CORE, MAIL, SUBMISSION],
+            vec![
+                (
+                    "Email/set",
+                    json!({
+                        "accountId": client.account_id,
+                        "create": { "draft": email },
+                    }),
+                ),
+                ("EmailSubmission/set", submission_set),
+            ],
+        )?;
+        check_set(&responses[0], "notCreated")?;
+        if let Err(err) = check_set(&responses[1], "notCreated") {
+            // Nothing was sent, the draft we made for it would only be clutter
+            if let Some(id) = responses[0]["created"]["draft"]["id"].as_str() {
+                let destroyed = client
+                    .call(
+                        &[CORE, MAIL],
+                        vec![(
+                            "Email/set",
+                            json!({ "accountId": client.account_id, "destroy": [id] }),
+                        )],
+                    )
+                    .and_then(|responses| check_set(&responses[0], "notDestroyed"));
+                if let Err(err) = destroyed {
+                    tracing::warn!("Failed to remove the draft of an unsent message: {err}");
+                }
+            }
+            return Err(err);
+        }
+        // The submission already moved the email into the Sent mailbox
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread::JoinHandle,
+    };
+
+    use serde_json::Value;
+
+    use crate::{
+        jmap::config::{Auth, JmapConfig, TokenConfig},
+        smtp::{MailTransport, PartialMessage},
+        store::{Flag, MailStore, SpecialUse, INBOX},
+    };
+
+    use super::{JmapStore, JmapTransport};
+
+    /// Serve the recorded responses in order, one per request, returning the requests' bodies.
+    fn serve(responses: Vec<&'static str>) -> (JmapConfig, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let config = JmapConfig {
+            session_url: format!("{base}/jmap/session"),
+            login: "jose@example.com".to_string(),
+            auth: Auth::Token(TokenConfig {
+                raw: "token".to_string(),
+            }),
+        };
+
+        let handle = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut content_length = 0;
+                let mut authorized = false;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    let (name, value) = line.split_once(':').unwrap_or_default();
+                    match name.to_lowercase().as_str() {
+                        "content-length" => content_length = value.trim().parse().unwrap(),
+                        "authorization" => authorized = value.trim() == "Bearer token",
+                        _ => {}
+                    }
+                }
+                assert!(authorized);
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                requests.push(String::from_utf8(body).unwrap());
+
+                let response = response.replace("$BASE", &base);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+            }
+            requests
+        });
+
+        (config, handle)
+    }
+
+    fn method_calls(request: &str) -> Value {
+        serde_json::from_str::<Value>(request).unwrap()["methodCalls"].clone()
+    }
+
+    #[test]
+    fn store_reads_and_updates() {
+        let (config, server) = serve(vec![
+            include_str!("fixtures/session.json"),
+            include_str!("fixtures/mailboxes.json"),
+            include_str!("fixtures/messages.json"),
+            include_str!("fixtures/updated.json"),
+            include_str!("fixtures/updated.json"),
+        ]);
+
+        let mut store = JmapStore::new(config).unwrap();
+        let mailboxes = store.list_mailboxes().unwrap();
+        let names = mailboxes
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [INBOX, "Drafts", "Lists", "Lists/Rust", "Sent"]);
+        assert_eq!(mailboxes[4].special_use, Some(SpecialUse::Sent));
+
+        let emails = store.list_messages(INBOX, 10, 0).unwrap();
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[0].subject, "Newest");
+        assert_eq!(emails[0].from, "José (jose@example.com)");
+        assert!(!emails[0].is_seen());
+        assert!(emails[1].is_seen());
+
+        store
+            .set_flags(INBOX, emails[0].uid, &[Flag::Seen], true)
+            .unwrap();
+        store
+            .move_message(INBOX, emails[0].uid, "Lists/Rust")
+            .unwrap();
+
+        let requests = server.join().unwrap();
+        let query = method_calls(&requests[2]);
+        assert_eq!(query[0][1]["filter"]["inMailbox"], "inbox");
+        assert_eq!(query[1][1]["#ids"]["resultOf"], "0");
+        assert_eq!(
+            method_calls(&requests[3])[0][1]["update"]["m2"]["keywords/$seen"],
+            true
+        );
+        assert_eq!(
+            method_calls(&requests[4])[0][1]["update"]["m2"]["mailboxIds"]["rust"],
+            true
+        );
+    }
+
+    #[test]
+    fn transport_submits() {
+        let (config, server) = serve(vec![
+            include_str!("fixtures/session.json"),
+            include_str!("fixtures/mailboxes.json"),
+            include_str!("fixtures/identities.json"),
+            include_str!("fixtures/submitted.json"),
+        ]);
+
+        let mut transport = JmapTransport::new(config);
+        transport
+            .send(PartialMessage {
+                to: Some("friend@example.com".parse().unwrap()),
+                cc: vec![],
+                bcc: vec![],
+                subject: Some("Hello".to_string()),
+                body: Some("Hi!".to_string()),
+                attachments: vec![],
+            })
+            .unwrap();
+
+        let requests = server.join().unwrap();
+        let calls = method_calls(&requests[3]);
+        let email = &calls[0][1]["create"]["draft"];
+        assert_eq!(email["mailboxIds"]["drafts"], true);
+        assert_eq!(email["to"][0]["email"], "friend@example.com");
+        assert_eq!(email["bodyValues"]["body"]["value"], "Hi!");
+        let submission = &calls[1][1];
+        assert_eq!(submission["create"]["submission"]["identityId"], "id1");
+        assert_eq!(
+            submission["onSuccessUpdateEmail"]["#submission"]["mailboxIds/sent"],
+            true
+        );
+    }
+
+    #[test]
+    fn transport_reports_failures() {
+        let (config, server) = serve(vec![
+            include_str!("fixtures/session.json"),
+            include_str!("fixtures/mailboxes.json"),
+            include_str!("fixtures/identities.json"),
+            include_str!("fixtures/not_submitted.json"),
+            include_str!("fixtures/destroyed.json"),
+        ]);
+
+        let mut transport = JmapTransport::new(config);
+        let err = transport
+            .send(PartialMessage {
+                to: Some("friend@example.com".parse().unwrap()),
+                cc: vec![],
+                bcc: vec![],
+                subject: None,
+                body: None,
+                attachments: vec![],
+            })
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::Jmap { kind, .. } if kind == "forbiddenFrom"));
+
+        let requests = server.join().unwrap();
+        let calls = method_calls(&requests[4]);
+        assert_eq!(calls[0][0], "Email/set");
+        assert_eq!(
+            calls[0][1]["destroy"][0], "m3",
+            "the draft doesn't stay behind"
+        );
+    }
+}
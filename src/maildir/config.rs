@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MaildirConfig {
+    /// Root of the Maildir, `~` is expanded to the home directory.
+    pub path: PathBuf,
+}
+
+impl MaildirConfig {
+    pub fn root(&self) -> PathBuf {
+        match (self.path.strip_prefix("~"), dirs::home_dir()) {
+            (Ok(rest), Some(home)) => home.join(rest),
+            _ => self.path.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use serde_json::json;
+
+    use crate::imap::config::ReadBackend;
+
+    use super::MaildirConfig;
+
+    #[test]
+    fn ensure_maildir_format() {
+        let json = json!({
+            "type": "maildir",
+            "path": "/home/jose/Mail"
+        });
+        let ReadBackend::Maildir(MaildirConfig { path }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
+        assert_eq!(path, PathBuf::from("/home/jose/Mail"));
+    }
+
+    #[test]
+    fn ensure_home_is_expanded() {
+        let config = MaildirConfig {
+            path: PathBuf::from("~/Mail"),
+        };
+        assert_eq!(config.root(), dirs::home_dir().unwrap().join("Mail"));
+    }
+}
@@ -0,0 +1,405 @@
+pub mod config;
+
+use std::{
+    cmp,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Utc};
+use config::MaildirConfig;
+
+use crate::store::{
+    special_use_mailbox, Flag, IdMap, MailStore, Mailbox, ParsedEmail, SpecialUse, INBOX,
+};
+
+/// Separator between the unique name and the info section of a filename.
+const INFO_SEPARATOR: &str = ":2,";
+
+/// A message file inside `cur` or `new`.
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    /// Filename without the info section, stays the same across flag changes.
+    unique: String,
+    /// Flag characters as found on the filename, we keep the ones we don't understand.
+    info: String,
+}
+
+impl Entry {
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let filename = path.file_name()?.to_str()?;
+        // Hidden files are not messages, e.g. Dovecot's or editors' temporary files
+        if filename.starts_with('.') {
+            return None;
+        }
+        let (unique, info) = match filename.split_once(INFO_SEPARATOR) {
+            Some((unique, info)) => (unique.to_string(), info.to_string()),
+            None => (filename.to_string(), String::new()),
+        };
+        Some(Self { path, unique, info })
+    }
+
+    /// Delivery timestamp, by convention it's the first part of the unique name.
+    fn timestamp(&self) -> u64 {
+        self.unique
+            .split('.')
+            .next()
+            .and_then(|timestamp| timestamp.parse().ok())
+            .unwrap_or_default()
+    }
+
+    fn flags(&self) -> Vec<Flag> {
+        self.info.chars().filter_map(flag_from_char).collect()
+    }
+}
+
+fn flag_from_char(c: char) -> Option<Flag> {
+    match c {
+        'S' => Some(Flag::Seen),
+        'R' => Some(Flag::Answered),
+        'F' => Some(Flag::Flagged),
+        'T' => Some(Flag::Deleted),
+        'D' => Some(Flag::Draft),
+        _ => None,
+    }
+}
+
+fn flag_to_char(flag: &Flag) -> char {
+    match flag {
+        Flag::Seen => 'S',
+        Flag::Answered => 'R',
+        Flag::Flagged => 'F',
+        Flag::Deleted => 'T',
+        Flag::Draft => 'D',
+    }
+}
+
+/// Apply the flag changes to an info section, keeping it sorted as the spec requires.
+fn update_info(info: &str, flags: &[Flag], enable: bool) -> String {
+    let mut chars = info.chars().collect::<Vec<_>>();
+    for flag in flags {
+        let c = flag_to_char(flag);
+        if enable {
+            if !chars.contains(&c) {
+                chars.push(c);
+            }
+        } else {
+            chars.retain(|existing| *existing != c);
+        }
+    }
+    chars.sort_unstable();
+    chars.into_iter().collect()
+}
+
+/// Guess the special use from the folder name, Maildir has no way of storing it.
+fn special_use(name: &str) -> Option<SpecialUse> {
+    match name.to_lowercase().as_str() {
+        "sent" | "sent items" | "sent messages" => Some(SpecialUse::Sent),
+        "drafts" => Some(SpecialUse::Drafts),
+        "trash" | "deleted items" | "deleted messages" => Some(SpecialUse::Trash),
+        "junk" | "spam" => Some(SpecialUse::Junk),
+        "archive" | "archives" => Some(SpecialUse::Archive),
+        _ => None,
+    }
+}
+
+/// Read everything up to the first empty line.
+fn read_header(path: &Path) -> Result<Vec<u8>, std::io::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = vec![];
+    loop {
+        let read = reader.read_until(b'\n', &mut header)?;
+        if read == 0 || header.ends_with(b"\n\n") || header.ends_with(b"\r\n\r\n") {
+            break;
+        }
+    }
+    Ok(header)
+}
+
+/// Read-write access to a Maildir, including Maildir++ subfolders.
+pub struct MaildirStore {
+    root: PathBuf,
+    ids: IdMap,
+}
+
+impl MaildirStore {
+    pub fn new(config: MaildirConfig) -> Result<Self, crate::Error> {
+        let root = config.root();
+        if !root.join("cur").is_dir() {
+            tracing::error!("{} is not a Maildir", root.display());
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} does not contain a cur directory", root.display()),
+            ))?;
+        }
+        Ok(Self {
+            root,
+            ids: IdMap::new(),
+        })
+    }
+
+    /// Maildir++ keeps subfolders as `.Name` (and `.Parent.Child`) inside the root.
+    fn mailbox_path(&self, mailbox: &str) -> PathBuf {
+        if mailbox.eq_ignore_ascii_case(INBOX) {
+            self.root.clone()
+        } else {
+            self.root.join(format!(".{mailbox}"))
+        }
+    }
+
+    fn entries(&self, mailbox: &str) -> Result<Vec<Entry>, crate::Error> {
+        let path = self.mailbox_path(mailbox);
+        let mut entries = vec![];
+        for subdir in ["new", "cur"] {
+            for dir_entry in fs::read_dir(path.join(subdir))? {
+                let dir_entry = dir_entry?;
+                if !dir_entry.file_type()?.is_file() {
+                    continue;
+                }
+                if let Some(entry) = Entry::from_path(dir_entry.path()) {
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn find(&self, mailbox: &str, uid: u32) -> Result<Entry, crate::Error> {
+        let unique = self.ids.key(uid).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Unknown message {uid}"),
+            )
+        })?;
+        self.entries(mailbox)?
+            .into_iter()
+            .find(|entry| entry.unique == unique)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("Message {uid} not found in {mailbox}"),
+                )
+                .into()
+            })
+    }
+}
+
+impl MailStore for MaildirStore {
+    fn list_mailboxes(&mut self) -> Result<Vec<Mailbox>, crate::Error> {
+        let mut mailboxes = vec![Mailbox {
+            name: INBOX.to_string(),
+            special_use: None,
+        }];
+        let mut folders = vec![];
+        for dir_entry in fs::read_dir(&self.root)? {
+            let dir_entry = dir_entry?;
+            let Some(name) = dir_entry.file_name().to_str().map(ToString::to_string) else {
+                continue;
+            };
+            let Some(name) = name.strip_prefix('.') else {
+                continue;
+            };
+            // Skip `.` and `..` just in case, along with anything that's not a Maildir
+            if name.is_empty() || name == "." || !dir_entry.path().join("cur").is_dir() {
+                continue;
+            }
+            folders.push(name.to_string());
+        }
+        folders.sort();
+        mailboxes.extend(folders.into_iter().map(|name| Mailbox {
+            special_use: special_use(name.rsplit('.').next().unwrap_or(&name)),
+            name,
+        }));
+        Ok(mailboxes)
+    }
+
+    fn list_messages(
+        &mut self,
+        mailbox: &str,
+        count: u32,
+        offset: u32,
+    ) -> Result<Vec<ParsedEmail>, crate::Error> {
+        let mut entries = self.entries(mailbox)?;
+        entries.sort_by_cached_key(|entry| cmp::Reverse((entry.timestamp(), entry.unique.clone())));
+
+        let mut parsed_emails = Vec::with_capacity(count as usize);
+        for entry in entries
+            .into_iter()
+            .skip(offset as usize)
+            .take(count as usize)
+        {
+            let header = match read_header(&entry.path) {
+                Ok(header) => header,
+                Err(err) => {
+                    tracing::error!("Failed to read {} with error: {err}", entry.path.display());
+                    continue;
+                }
+            };
+            // Better than nothing if the Date header is missing
+            let date = (entry.timestamp() > 0)
+                .then(|| DateTime::<Utc>::from_timestamp(entry.timestamp() as i64, 0))
+                .flatten();
+            let uid = self.ids.id(&entry.unique);
+            let Some(mut parsed) = ParsedEmail::parse(uid, &header, None, entry.flags()) else {
+                tracing::error!("Failed to parse email message, ignoring...");
+                continue;
+            };
+            if parsed.date == DateTime::<Utc>::UNIX_EPOCH {
+                parsed.date = date.unwrap_or(parsed.date);
+            }
+            parsed_emails.push(parsed);
+        }
+        Ok(parsed_emails)
+    }
+
+    fn fetch_message(&mut self, mailbox: &str, uid: u32) -> Result<Vec<u8>, crate::Error> {
+        let entry = self.find(mailbox, uid)?;
+        Ok(fs::read(entry.path)?)
+    }
+
+    fn set_flags(
+        &mut self,
+        mailbox: &str,
+        uid: u32,
+        flags: &[Flag],
+        enable: bool,
+    ) -> Result<(), crate::Error> {
+        let entry = self.find(mailbox, uid)?;
+        let info = update_info(&entry.info, flags, enable);
+        // Once a message has been seen by a client it belongs in `cur`
+        let destination = self
+            .mailbox_path(mailbox)
+            .join("cur")
+            .join(format!("{}{INFO_SEPARATOR}{info}", entry.unique));
+        fs::rename(entry.path, destination)?;
+        Ok(())
+    }
+
+    fn move_message(
+        &mut self,
+        mailbox: &str,
+        uid: u32,
+        destination: &str,
+    ) -> Result<(), crate::Error> {
+        let entry = self.find(mailbox, uid)?;
+        let subdir = if entry.info.is_empty() { "new" } else { "cur" };
+        let filename = entry
+            .path
+            .file_name()
+            .expect("entries always have a filename")
+            .to_owned();
+        fs::rename(
+            &entry.path,
+            self.mailbox_path(destination).join(subdir).join(filename),
+        )?;
+        Ok(())
+    }
+
+    fn append(
+        &mut self,
+        special_use: SpecialUse,
+        raw: &[u8],
+        flags: &[Flag],
+    ) -> Result<(), crate::Error> {
+        let mailboxes = self.list_mailboxes()?;
+        let path = self.mailbox_path(special_use_mailbox(&mailboxes, special_use)?);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let unique = format!(
+            "{}.M{}P{}.ectt",
+            now.as_secs(),
+            now.subsec_micros(),
+            std::process::id()
+        );
+        // Deliver through `tmp` so other clients never see half a message
+        let temporary = path.join("tmp").join(&unique);
+        fs::write(&temporary, raw)?;
+        let info = update_info("", flags, true);
+        fs::rename(
+            temporary,
+            path.join("cur")
+                .join(format!("{unique}{INFO_SEPARATOR}{info}")),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::store::{Flag, MailStore, SpecialUse, INBOX};
+
+    use super::{config::MaildirConfig, update_info, MaildirStore};
+
+    const MESSAGE: &str = "From: jose@example.com\nSubject: Hello\n\nHi!\n";
+
+    #[test]
+    fn ensure_info_is_sorted() {
+        assert_eq!(update_info("", &[Flag::Seen, Flag::Flagged], true), "FS");
+        assert_eq!(update_info("FPS", &[Flag::Answered], true), "FPRS");
+        assert_eq!(
+            update_info("FPRS", &[Flag::Seen, Flag::Draft], false),
+            "FPR"
+        );
+    }
+
+    #[test]
+    fn maildir_roundtrip() {
+        let root = std::env::temp_dir().join(format!("ectt-maildir-{}", std::process::id()));
+        for dir in ["", ".Sent", ".Work.Project"] {
+            for subdir in ["cur", "new", "tmp"] {
+                fs::create_dir_all(root.join(dir).join(subdir)).unwrap();
+            }
+        }
+        fs::write(root.join("new/1700000000.M1P1.host"), MESSAGE).unwrap();
+        fs::write(root.join("cur/1600000000.M1P1.host:2,S"), MESSAGE).unwrap();
+
+        let mut store = MaildirStore::new(MaildirConfig { path: root.clone() }).unwrap();
+
+        let mailboxes = store.list_mailboxes().unwrap();
+        let names = mailboxes
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [INBOX, "Sent", "Work.Project"]);
+        assert_eq!(mailboxes[1].special_use, Some(SpecialUse::Sent));
+
+        let emails = store.list_messages(INBOX, 10, 0).unwrap();
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[0].subject, "Hello");
+        assert!(!emails[0].is_seen());
+        assert!(emails[1].is_seen());
+
+        let uid = emails[0].uid;
+        store.set_flags(INBOX, uid, &[Flag::Seen], true).unwrap();
+        assert!(root.join("cur/1700000000.M1P1.host:2,S").is_file());
+        assert!(store.list_messages(INBOX, 10, 0).unwrap()[0].is_seen());
+
+        store.move_message(INBOX, uid, "Sent").unwrap();
+        assert!(root.join(".Sent/cur/1700000000.M1P1.host:2,S").is_file());
+        assert_eq!(store.list_messages(INBOX, 10, 0).unwrap().len(), 1);
+        let sent = store.list_messages("Sent", 10, 0).unwrap();
+        assert_eq!(sent[0].uid, uid);
+        assert_eq!(
+            store.fetch_message("Sent", uid).unwrap(),
+            MESSAGE.as_bytes()
+        );
+
+        store
+            .append(SpecialUse::Sent, MESSAGE.as_bytes(), &[Flag::Seen])
+            .unwrap();
+        let sent = store.list_messages("Sent", 10, 0).unwrap();
+        assert_eq!(sent.len(), 2);
+        assert!(sent.iter().all(|email| email.is_seen()));
+        assert!(store
+            .append(SpecialUse::Drafts, MESSAGE.as_bytes(), &[])
+            .is_err());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
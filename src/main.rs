@@ -1,27 +1,48 @@
 #[cfg(feature = "refresher")]
 mod oauth;
 
+mod check;
 mod cli;
 mod config;
+mod drafts;
+mod file;
+mod himalaya;
 mod imap;
+mod init;
+mod jmap;
+mod maildir;
+mod mbox;
+mod pop3;
+mod sendmail;
 mod smtp;
+mod store;
+mod tls;
 mod tui;
+mod workers;
 
+use std::io::{Read, StdoutLock, Write};
 use std::path::PathBuf;
+use std::process::ExitCode;
 use std::str::FromStr;
 use std::sync::mpsc::channel;
 
 use clap::Parser;
-use oauth2::basic::BasicRequestTokenError;
-use oauth2::{reqwest, HttpClientError};
+use oauth2::basic::{BasicErrorResponseType, BasicRequestTokenError};
+use oauth2::{reqwest, HttpClientError, RequestTokenError};
 use tracing::Level;
 use tracing_appender::non_blocking::WorkerGuard;
 
-use crate::cli::App;
-use crate::config::{ectt_config_dir, get_config_path, Config};
+use crate::cli::{App, ConfigArg};
+use crate::config::{
+    ectt_config_dir, find_config, get_config_path, read_value, write_value, Config,
+};
 use crate::imap::config::ReadBackend;
-use crate::imap::imap_thread;
+use crate::imap::state::AuthenticatedState;
 use crate::smtp::config::SendBackend;
+use crate::smtp::message::OutgoingMessage;
+use crate::store::{Flag, ParsedEmail, SpecialUse};
+use crate::tui::theme::ColorDepth;
+use crate::workers::Worker;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -31,6 +52,18 @@ pub enum Error {
     #[error(transparent)]
     Json(#[from] serde_json::Error),
 
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+
+    #[error(transparent)]
+    TomlWrite(#[from] toml::ser::Error),
+
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml_ng::Error),
+
+    #[error("Invalid configuration at {0}")]
+    Config(#[from] crate::config::ConfigError),
+
     #[error(transparent)]
     RefreshToken(#[from] BasicRequestTokenError<HttpClientError<reqwest::Error>>),
 
@@ -45,6 +78,74 @@ pub enum Error {
 
     #[error(transparent)]
     Smtp(#[from] lettre::transport::smtp::Error),
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Tls(#[from] rustls::Error),
+
+    #[error(
+        "SMTP server offers none of the configured authentication mechanisms, it supports: {0}"
+    )]
+    SmtpMechanism(String),
+
+    #[error("POP3 server replied: {0}")]
+    Pop3(String),
+
+    #[error("{command} failed with {status}{}", if stderr.is_empty() { String::new() } else { format!(": {stderr}") })]
+    Sendmail {
+        command: String,
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+
+    #[error("JMAP server returned \"{kind}\"{}", description.as_ref().map(|d| format!(": {d}")).unwrap_or_default())]
+    Jmap {
+        kind: String,
+        description: Option<String>,
+    },
+
+    #[cfg(feature = "refresher")]
+    #[error("OAuth provider returned \"{error}\"{}", description.as_ref().map(|d| format!(": {d}")).unwrap_or_default())]
+    OAuthProvider {
+        error: String,
+        description: Option<String>,
+    },
+
+    #[cfg(feature = "refresher")]
+    #[error("OAuth redirect state does not match the request, refusing to continue")]
+    OAuthStateMismatch,
+
+    #[cfg(feature = "refresher")]
+    #[error("Timed out waiting for the OAuth redirect")]
+    OAuthTimeout,
+}
+
+impl Error {
+    /// Whether sending might work if tried again later.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            // Anything but an answer from the server, e.g. the network being down
+            Error::Smtp(err) => {
+                err.is_transient()
+                    || !(err.is_permanent() || err.is_client() || err.is_response() || err.is_tls())
+            }
+            Error::Io(_) | Error::Http(_) => true,
+            // EX_TEMPFAIL
+            Error::Sendmail { status, .. } => status.code() == Some(75),
+            _ => false,
+        }
+    }
+
+    /// Whether the OAuth refresh token was rejected (e.g. revoked) and the user needs to login again.
+    pub fn requires_login(&self) -> bool {
+        matches!(
+            self,
+            Error::RefreshToken(RequestTokenError::ServerResponse(response))
+                if *response.error() == BasicErrorResponseType::InvalidGrant
+        )
+    }
 }
 
 fn setup_logging() -> WorkerGuard {
@@ -75,7 +176,8 @@ fn setup_logging() -> WorkerGuard {
     _guard
 }
 
-fn main() -> Result<(), Error> {
+// Exit codes are returned instead of calling `process::exit`, which would skip flushing the log
+fn main() -> Result<ExitCode, Error> {
     let _guard = setup_logging();
     color_eyre::install().unwrap();
 
@@ -83,85 +185,358 @@ fn main() -> Result<(), Error> {
 
     match app.command {
         #[cfg(feature = "refresher")]
-        cli::Command::Login { provider } => {
+        cli::Command::Login {
+            provider,
+            port,
+            timeout,
+        } => {
             let (client, scopes) = provider.into();
             tokio::runtime::Builder::new_multi_thread()
                 .enable_all()
                 .build()
                 .expect("failed to build the runtime")
-                .block_on(crate::oauth::execute_authentication_flow(client, scopes))
+                .block_on(crate::oauth::execute_authentication_flow(
+                    client,
+                    scopes,
+                    port,
+                    std::time::Duration::from_secs(timeout),
+                ))?;
+            Ok(ExitCode::SUCCESS)
         }
-        cli::Command::Run { config } => {
+        cli::Command::Run {
+            config: ConfigArg { config },
+            dry_run,
+        } => {
             let config_path = get_config_path(config).inspect_err(|err| {
                 tracing::error!("Failed to get a configuration path: {err}");
             })?;
-            let config = Config::load(&config_path).inspect_err(|err| {
-                tracing::error!(
-                    "Failed to load configuration from path {} with error: {err}",
-                    config_path.display()
-                );
+            let (config, value) = read_value(&config_path)
+                .and_then(|value| Ok((Config::from_value(&value)?, value)))
+                .inspect_err(|err| {
+                    tracing::error!(
+                        "Failed to load configuration from path {} with error: {err}",
+                        config_path.display()
+                    );
+                })?;
+
+            let dry_run =
+                dry_run.map(|dir| dir.unwrap_or_else(|| std::env::temp_dir().join("ectt-dry-run")));
+            run(config, value, config_path, dry_run)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        cli::Command::Init { config } => {
+            let path = config
+                .or_else(|| ectt_config_dir().map(|dir| dir.join("config.json")))
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        "No configuration folder, pass --config",
+                    )
+                })?;
+            init::run(&path)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        cli::Command::ImportHimalaya {
+            from,
+            account,
+            config,
+            force,
+        } => {
+            let from = from.or_else(himalaya::default_path).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "No configuration folder, pass --from",
+                )
+            })?;
+            let import = himalaya::import(&std::fs::read_to_string(&from)?, account.as_deref())?;
+            Config::from_value(&import.config)?;
+
+            // Any existing configuration would be picked over the imported one, so that's
+            // the one replaced, in its own format
+            let (path, exists) = match config {
+                Some(path) => {
+                    let exists = path.exists();
+                    (path, exists)
+                }
+                None => {
+                    let dir = ectt_config_dir().ok_or_else(|| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::NotFound,
+                            "No configuration folder, pass --config",
+                        )
+                    })?;
+                    match find_config(&dir) {
+                        Some(existing) => (existing, true),
+                        None => (dir.join("config.toml"), false),
+                    }
+                }
+            };
+            if exists && !force {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} already exists, pass --force to overwrite it",
+                        path.display()
+                    ),
+                ))?
+            }
+            write_value(&path, &import.config)?;
+            println!(
+                "Imported the {} account to {}",
+                import.account,
+                path.display()
+            );
+            for warning in import.warnings {
+                println!("  {warning}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        cli::Command::Check {
+            config: ConfigArg { config },
+        } => {
+            let config_path = get_config_path(config).inspect_err(|err| {
+                tracing::error!("Failed to get a configuration path: {err}");
             })?;
+            Ok(if check::run(&config_path) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+        cli::Command::Export {
+            config: ConfigArg { config },
+            mailbox,
+            search,
+            output,
+        } => {
+            let mut state = imap_state(load_config(config)?.read, "exporting")?;
+            let exported = mbox::export(&mut state, &mailbox, &search, &output)?;
+            println!("Exported {exported} messages to {}", output.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        cli::Command::Send {
+            config: ConfigArg { config },
+            to,
+            cc,
+            bcc,
+            subject,
+            attach,
+            body_file,
+            raw,
+        } => {
+            let config = load_config(config)?;
+            let SendBackend::Smtp(smtp_config) = config.send else {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "sending requires an SMTP send backend",
+                ))?
+            };
 
-            run(config)
+            let from = smtp_config.login.parse::<lettre::Address>()?;
+            let (envelope, message) = if raw {
+                let mut message = vec![];
+                std::io::stdin().read_to_end(&mut message)?;
+                smtp::message::raw_envelope(&message, from)?
+            } else {
+                let body = match body_file {
+                    Some(path) => std::fs::read_to_string(path)?,
+                    None => std::io::read_to_string(std::io::stdin())?,
+                };
+                let message = OutgoingMessage {
+                    to,
+                    cc,
+                    bcc,
+                    subject,
+                    body,
+                    attachments: attach,
+                }
+                .into_message(from)?;
+                (message.envelope().clone(), message.formatted())
+            };
+
+            let save_sent = smtp_config.save_sent;
+            let mut client = smtp::Client::new(smtp_config)?;
+            if let Err(err) = client.send_raw(&envelope, &message) {
+                tracing::error!("Failed to send the message with error: {err}");
+                eprintln!("Failed to send the message: {err}");
+                // EX_TEMPFAIL and EX_UNAVAILABLE, like sendmail
+                return Ok(ExitCode::from(if err.is_transient() { 75 } else { 69 }));
+            }
+            // It's sent either way, failing to keep a copy is only worth a warning
+            if save_sent {
+                if let Err(err) = save_sent_copy(config.read, message) {
+                    tracing::error!("Failed to save the sent message with error: {err}");
+                    eprintln!(
+                        "WARNING: Sent, but failed to save a copy to the Sent mailbox: {err}"
+                    );
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        cli::Command::List {
+            config: ConfigArg { config },
+            mailbox,
+            limit,
+            unseen,
+            json,
+        } => {
+            let mut state = imap_state(load_config(config)?.read, "listing")?;
+            let query = if unseen { "UNSEEN" } else { "ALL" };
+            let emails = state.search_messages(&mailbox, query, limit)?;
+            print_all(|stdout| {
+                for email in emails {
+                    if json {
+                        writeln!(stdout, "{}", serde_json::to_string(&email)?)?;
+                    } else {
+                        writeln!(stdout, "{}", cli::output::row(&email))?;
+                    }
+                }
+                Ok(())
+            })
+        }
+        cli::Command::Read {
+            config: ConfigArg { config },
+            mailbox,
+            uid,
+            raw,
+            text: _,
+            json,
+        } => {
+            let mut state = imap_state(load_config(config)?.read, "reading")?;
+            let message = state.fetch_one(&mailbox, uid)?;
+            if raw {
+                return print_all(|stdout| Ok(stdout.write_all(&message.raw)?));
+            }
+
+            let email = ParsedEmail::parse(uid, &message.raw, message.internal_date, message.flags)
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("Message {uid} couldn't be parsed"),
+                    )
+                })?;
+            print_all(|stdout| {
+                if json {
+                    writeln!(stdout, "{}", serde_json::to_string(&email)?)?;
+                } else {
+                    write!(stdout, "{}", cli::output::text(&email))?;
+                }
+                Ok(())
+            })
         }
     }
 }
 
-fn run(config: Config) -> Result<(), Error> {
-    let Config {
-        read: ReadBackend::Imap(imap_config),
-        send: SendBackend::Smtp(smtp_config),
-    } = config;
-
-    let (main_tx_imap, imap_rx_main) = channel::<imap::Command>();
-    let (imap_tx_main, main_rx_imap) = channel::<imap::Response>();
-    let imap_thread = std::thread::spawn(|| {
-        tracing::debug!("Launching IMAP thread");
-        imap_thread(imap_config, imap_rx_main, imap_tx_main)
-    });
-
-    let (main_tx_smtp, smtp_rx_main) = channel::<smtp::Command>();
-    let (smtp_tx_main, main_rx_smtp) = channel::<smtp::Response>();
-    let smtp_thread = std::thread::spawn(|| {
-        tracing::debug!("Launching SMTP thread");
-        smtp::run(smtp_config, smtp_rx_main, smtp_tx_main)
-    });
+/// Write the output of a command, a reader which stopped early (e.g. `| head`) is fine.
+fn print_all(write: impl FnOnce(&mut StdoutLock) -> Result<(), Error>) -> Result<ExitCode, Error> {
+    let mut stdout = std::io::stdout().lock();
+    match write(&mut stdout).and_then(|()| Ok(stdout.flush()?)) {
+        Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::BrokenPipe => {
+            Ok(ExitCode::SUCCESS)
+        }
+        result => result.map(|()| ExitCode::SUCCESS),
+    }
+}
+
+/// Append `raw` to the Sent mailbox of `read`, through the same store the TUI uses.
+fn save_sent_copy(read: ReadBackend, raw: Vec<u8>) -> Result<(), Error> {
+    let (tx, store_rx) = channel();
+    let (store_tx, rx) = channel();
+    tx.send(store::Command::Append {
+        special_use: SpecialUse::Sent,
+        raw,
+        flags: vec![Flag::Seen],
+    })
+    .expect("the store's receiver is still around");
+    // Closed right away, the store stops once the copy is saved
+    drop(tx);
+    workers::store(read)?(store_rx, store_tx)?;
+    match rx.try_iter().next() {
+        Some(store::Response::Failed(err) | store::Response::Error(err)) => Err(err),
+        #[cfg(feature = "refresher")]
+        Some(store::Response::LoginRequired(_)) => Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "the login expired, open ectt to log in again",
+        ))?,
+        _ => Ok(()),
+    }
+}
+
+/// Load the configuration for one of the non-interactive commands.
+fn load_config(config: Option<PathBuf>) -> Result<Config, Error> {
+    let config_path = get_config_path(config).inspect_err(|err| {
+        tracing::error!("Failed to get a configuration path: {err}");
+    })?;
+    let config = Config::load(&config_path).inspect_err(|err| {
+        tracing::error!(
+            "Failed to load configuration from path {} with error: {err}",
+            config_path.display()
+        );
+    })?;
+    warn_tls_dangers(&config);
+    Ok(config)
+}
+
+/// The log is easy to miss, turning off certificate checks shouldn't be.
+fn warn_tls_dangers(config: &Config) {
+    for danger in config.tls_dangers() {
+        tracing::warn!("{danger}");
+        eprintln!("WARNING: {danger}");
+    }
+}
+
+/// Log into the IMAP server, `action` names what needs it for the error otherwise.
+fn imap_state(read: ReadBackend, action: &str) -> Result<AuthenticatedState, Error> {
+    let ReadBackend::Imap(imap_config) = read else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{action} requires an IMAP read backend"),
+        ))?
+    };
+    imap::state::UnauthenticatedState::new(imap_config)?
+        .authenticate()
+        .map_err(|(err, _)| err)
+}
+
+fn run(
+    config: Config,
+    value: serde_json::Value,
+    config_path: PathBuf,
+    dry_run: Option<PathBuf>,
+) -> Result<(), Error> {
+    if let Some(dir) = &dry_run {
+        tracing::info!("Dry run, outgoing mail is written to {}", dir.display());
+    }
+    warn_tls_dangers(&config);
+    let tls_dangers = config.tls_dangers();
+    let store = Worker::spawn(workers::store(config.read)?);
+    let sender = Worker::spawn(workers::sender(config.send, dry_run.as_deref())?);
 
     let terminal = ratatui::init();
-    let result = tui::run(
+    let (result, store, sender) = tui::run(
         terminal,
-        main_tx_imap,
-        main_rx_imap,
-        main_tx_smtp,
-        main_rx_smtp,
+        tui::Settings {
+            config_path,
+            value,
+            dry_run: dry_run.clone(),
+            undo_send: std::time::Duration::from_secs(config.undo_send),
+            signature: config.signature,
+            keymap: config.keys,
+            tls_dangers,
+            theme: config
+                .theme
+                .adapt(config.colors.unwrap_or_else(ColorDepth::detect)),
+        },
+        store,
+        sender,
     );
     ratatui::restore();
 
-    if let Err(err) = imap_thread.join() {
-        if err.is::<Box<dyn std::error::Error>>() {
-            tracing::error!(
-                "Thread panicked with error: {}",
-                err.downcast::<Box<dyn std::error::Error>>()
-                    .expect("`.is` failed us")
-            );
-        } else {
-            tracing::error!("Thread panicked with error: {:?}", err);
-        }
-        Err(std::io::Error::other("Thread panic"))?
-    };
-
-    if let Err(err) = smtp_thread.join() {
-        if err.is::<Box<dyn std::error::Error>>() {
-            tracing::error!(
-                "Thread panicked with error: {}",
-                err.downcast::<Box<dyn std::error::Error>>()
-                    .expect("`.is` failed us")
-            );
-        } else {
-            tracing::error!("Thread panicked with error: {:?}", err);
-        }
-        Err(std::io::Error::other("Thread panic"))?
-    };
+    if let Some(dir) = dry_run {
+        println!("Dry run, outgoing mail was written to {}", dir.display());
+    }
 
+    store.join()?;
+    sender.join()?;
     result
 }
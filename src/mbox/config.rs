@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MboxFormat {
+    /// `From ` lines in the body are quoted with `>`, as are already quoted ones.
+    #[default]
+    Mboxrd,
+    /// Messages carry a `Content-Length` header and bodies are left untouched.
+    Mboxcl2,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MboxConfig {
+    /// Path to the mbox file, `~` is expanded to the home directory.
+    pub path: PathBuf,
+    #[serde(default)]
+    pub format: MboxFormat,
+}
+
+impl MboxConfig {
+    pub fn file(&self) -> PathBuf {
+        match (self.path.strip_prefix("~"), dirs::home_dir()) {
+            (Ok(rest), Some(home)) => home.join(rest),
+            _ => self.path.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use serde_json::json;
+
+    use crate::imap::config::ReadBackend;
+
+    use super::{MboxConfig, MboxFormat};
+
+    #[test]
+    fn ensure_mbox_format() {
+        let json = json!({
+            "type": "mbox",
+            "path": "/home/jose/lists/rust.mbox"
+        });
+        let ReadBackend::Mbox(MboxConfig { path, format }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
+        assert_eq!(path, PathBuf::from("/home/jose/lists/rust.mbox"));
+        assert_eq!(format, MboxFormat::Mboxrd);
+
+        let json = json!({
+            "type": "mbox",
+            "path": "/home/jose/lists/rust.mbox",
+            "format": "mboxcl2"
+        });
+        let ReadBackend::Mbox(MboxConfig { format, .. }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
+        assert_eq!(format, MboxFormat::Mboxcl2);
+    }
+}
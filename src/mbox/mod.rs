@@ -0,0 +1,419 @@
+pub mod config;
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+use config::{MboxConfig, MboxFormat};
+use itertools::Itertools;
+
+use crate::{
+    imap::state::AuthenticatedState,
+    store::{Flag, MailStore, Mailbox, ParsedEmail, INBOX},
+};
+
+/// How many messages to fetch per request when exporting.
+const EXPORT_BATCH_SIZE: usize = 100;
+
+fn is_separator(line: &[u8]) -> bool {
+    line.starts_with(b"From ")
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line == b"\n" || line == b"\r\n"
+}
+
+/// Matches `>From `, `>>From `, etc.
+fn is_quoted_separator(line: &[u8]) -> bool {
+    match line.iter().position(|b| *b != b'>') {
+        Some(quotes) => quotes > 0 && is_separator(&line[quotes..]),
+        None => false,
+    }
+}
+
+/// Undo the mboxrd quoting by removing a single `>` from quoted `From ` lines.
+fn unquote(raw: &[u8]) -> Vec<u8> {
+    let mut unquoted = Vec::with_capacity(raw.len());
+    for line in raw.split_inclusive(|b| *b == b'\n') {
+        if is_quoted_separator(line) {
+            unquoted.extend_from_slice(&line[1..]);
+        } else {
+            unquoted.extend_from_slice(line);
+        }
+    }
+    unquoted
+}
+
+/// Value of the `Content-Length` header, if the line is one.
+fn content_length(line: &[u8]) -> Option<u64> {
+    let line = std::str::from_utf8(line).ok()?;
+    let (name, value) = line.split_once(':')?;
+    if !name.eq_ignore_ascii_case("Content-Length") {
+        return None;
+    }
+    value.trim().parse().ok()
+}
+
+/// Scan the file once, recording where each message starts and ends (excluding the `From ` line).
+fn build_index<R: BufRead + Seek>(
+    mut reader: R,
+    format: MboxFormat,
+) -> Result<Vec<Range<u64>>, io::Error> {
+    let mut index = vec![];
+    let mut line = vec![];
+    let mut offset = 0;
+    let mut start = None;
+    // Where the previous line started, if it was blank, since it belongs to the separator
+    let mut blank = None;
+
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)? as u64;
+        if read == 0 {
+            break;
+        }
+        let line_start = offset;
+        offset += read;
+
+        // Requiring the blank line makes us a bit more forgiving with unquoted `From ` lines
+        if !(is_separator(&line) && (line_start == 0 || blank.is_some())) {
+            blank = is_blank(&line).then_some(line_start);
+            continue;
+        }
+        if let Some(start) = start.take() {
+            index.push(start..blank.unwrap_or(line_start));
+        }
+        blank = None;
+        start = Some(offset);
+
+        if format == MboxFormat::Mboxcl2 {
+            // Read the header looking for the length, the body can then be skipped entirely
+            let mut length = None;
+            loop {
+                line.clear();
+                let read = reader.read_until(b'\n', &mut line)? as u64;
+                offset += read;
+                if read == 0 || is_blank(&line) {
+                    break;
+                }
+                length = length.or_else(|| content_length(&line));
+            }
+            // Without it, fallback to looking for the next separator
+            if let Some(length) = length {
+                reader.seek_relative(length as i64)?;
+                offset += length;
+                index.push(start.take().expect("start was just set")..offset);
+                // The next separator may follow right away, there's no quoting to worry about
+                blank = Some(offset);
+            }
+        }
+    }
+    if let Some(start) = start {
+        index.push(start..blank.unwrap_or(offset).max(start));
+    }
+
+    Ok(index)
+}
+
+/// Flags as written by mutt and friends in the `Status` and `X-Status` headers.
+fn flags(header: &[u8]) -> Vec<Flag> {
+    let mut flags = vec![];
+    for line in header.split(|b| *b == b'\n') {
+        let Some((name, value)) = std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| line.split_once(':'))
+        else {
+            continue;
+        };
+        if name.eq_ignore_ascii_case("Status") {
+            if value.contains('R') {
+                flags.push(Flag::Seen);
+            }
+        } else if name.eq_ignore_ascii_case("X-Status") {
+            flags.extend(value.chars().filter_map(|c| match c {
+                'A' => Some(Flag::Answered),
+                'F' => Some(Flag::Flagged),
+                'D' => Some(Flag::Deleted),
+                'T' => Some(Flag::Draft),
+                _ => None,
+            }));
+        }
+    }
+    flags
+}
+
+/// Read-only access to a single mbox file, shown as the INBOX.
+pub struct MboxStore {
+    file: File,
+    format: MboxFormat,
+    /// Byte range of every message in the file, in order of appearance.
+    index: Vec<Range<u64>>,
+}
+
+impl MboxStore {
+    pub fn new(config: MboxConfig) -> Result<Self, crate::Error> {
+        let path = config.file();
+        let file = File::open(&path).inspect_err(|err| {
+            tracing::error!("Failed to open {} with error: {err}", path.display());
+        })?;
+        let index = build_index(BufReader::new(&file), config.format)?;
+        tracing::debug!("Indexed {} messages in {}", index.len(), path.display());
+        Ok(Self {
+            file,
+            format: config.format,
+            index,
+        })
+    }
+
+    fn range(&self, mailbox: &str, uid: u32) -> Result<Range<u64>, crate::Error> {
+        if !mailbox.eq_ignore_ascii_case(INBOX) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("mbox files only have an {INBOX}, {mailbox} does not exist"),
+            ))?;
+        }
+        let range = (uid as usize)
+            .checked_sub(1)
+            .and_then(|idx| self.index.get(idx));
+        match range {
+            Some(range) => Ok(range.clone()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Message {uid} not found in {mailbox}"),
+            ))?,
+        }
+    }
+
+    /// Read a message, or only its header.
+    fn read(&self, range: Range<u64>, header_only: bool) -> Result<Vec<u8>, io::Error> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(range.start))?;
+        let mut reader = BufReader::new(file.take(range.end - range.start));
+
+        let mut raw = vec![];
+        if header_only {
+            loop {
+                let read = reader.read_until(b'\n', &mut raw)?;
+                if read == 0 || raw.ends_with(b"\n\n") || raw.ends_with(b"\r\n\r\n") {
+                    break;
+                }
+            }
+        } else {
+            reader.read_to_end(&mut raw)?;
+        }
+
+        match self.format {
+            MboxFormat::Mboxrd => Ok(unquote(&raw)),
+            MboxFormat::Mboxcl2 => Ok(raw),
+        }
+    }
+
+    fn read_only() -> crate::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "mbox files are read-only").into()
+    }
+}
+
+impl MailStore for MboxStore {
+    fn list_mailboxes(&mut self) -> Result<Vec<Mailbox>, crate::Error> {
+        Ok(vec![Mailbox {
+            name: INBOX.to_string(),
+            special_use: None,
+        }])
+    }
+
+    fn list_messages(
+        &mut self,
+        mailbox: &str,
+        count: u32,
+        offset: u32,
+    ) -> Result<Vec<ParsedEmail>, crate::Error> {
+        let mut parsed_emails = Vec::with_capacity(count as usize);
+        // Messages are appended, so the newest ones are at the end
+        for uid in (1..=self.index.len() as u32)
+            .rev()
+            .skip(offset as usize)
+            .take(count as usize)
+        {
+            let header = self.read(self.range(mailbox, uid)?, true)?;
+            let Some(parsed) = ParsedEmail::parse(uid, &header, None, flags(&header)) else {
+                tracing::error!("Failed to parse email message, ignoring...");
+                continue;
+            };
+            parsed_emails.push(parsed);
+        }
+        Ok(parsed_emails)
+    }
+
+    fn fetch_message(&mut self, mailbox: &str, uid: u32) -> Result<Vec<u8>, crate::Error> {
+        Ok(self.read(self.range(mailbox, uid)?, false)?)
+    }
+
+    fn set_flags(&mut self, _: &str, _: u32, _: &[Flag], _: bool) -> Result<(), crate::Error> {
+        Err(Self::read_only())
+    }
+
+    fn move_message(&mut self, _: &str, _: u32, _: &str) -> Result<(), crate::Error> {
+        Err(Self::read_only())
+    }
+}
+
+/// Append a message in mboxrd format, line endings are normalized to `\n`.
+pub fn write_message<W: Write>(
+    writer: &mut W,
+    raw: &[u8],
+    date: DateTime<Utc>,
+) -> Result<(), io::Error> {
+    writeln!(
+        writer,
+        "From MAILER-DAEMON {}",
+        date.format("%a %b %e %H:%M:%S %Y")
+    )?;
+    for line in raw.split_inclusive(|b| *b == b'\n') {
+        if is_separator(line) || is_quoted_separator(line) {
+            writer.write_all(b">")?;
+        }
+        match line.strip_suffix(b"\r\n") {
+            Some(line) => {
+                writer.write_all(line)?;
+                writer.write_all(b"\n")?;
+            }
+            None => writer.write_all(line)?,
+        }
+    }
+    if !raw.ends_with(b"\n") {
+        writer.write_all(b"\n")?;
+    }
+    writer.write_all(b"\n")
+}
+
+/// Write every message in `mailbox` matching the IMAP `query` to an mbox file at `output`.
+///
+/// Returns the number of exported messages.
+pub fn export(
+    state: &mut AuthenticatedState,
+    mailbox: &str,
+    query: &str,
+    output: &Path,
+) -> Result<usize, crate::Error> {
+    let uids = state.search(mailbox, query)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+
+    let mut exported = 0;
+    for chunk in &uids.into_iter().chunks(EXPORT_BATCH_SIZE) {
+        let uids = chunk.collect::<Vec<_>>();
+        for message in state.fetch_raw(&uids)? {
+            let date = message.internal_date.unwrap_or(DateTime::<Utc>::UNIX_EPOCH);
+            write_message(&mut writer, &message.raw, date)?;
+            exported += 1;
+        }
+    }
+    writer.flush()?;
+
+    Ok(exported)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use chrono::DateTime;
+
+    use crate::store::Flag;
+
+    use super::{build_index, config::MboxFormat, flags, unquote, write_message};
+
+    const MBOXRD: &str = "From jose@example.com Mon Jan  1 00:00:00 2024
+From: jose@example.com
+Subject: First
+Status: RO
+
+>From the start
+>>From quoted
+From unquoted, but not a separator
+
+From jose@example.com Tue Jan  2 00:00:00 2024
+From: jose@example.com
+Subject: Second
+
+Bye!
+
+";
+
+    const MBOXCL2: &str = "From jose@example.com Mon Jan  1 00:00:00 2024
+From: jose@example.com
+Subject: First
+Content-Length: 28
+
+From the start
+
+From inside
+From jose@example.com Tue Jan  2 00:00:00 2024
+From: jose@example.com
+Subject: Second
+Content-Length: 5
+
+Bye!
+
+";
+
+    fn messages(mbox: &str, format: MboxFormat) -> Vec<String> {
+        build_index(Cursor::new(mbox), format)
+            .unwrap()
+            .into_iter()
+            .map(|range| mbox[range.start as usize..range.end as usize].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn ensure_mboxrd_is_indexed() {
+        let messages = messages(MBOXRD, MboxFormat::Mboxrd);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].ends_with("From unquoted, but not a separator\n"));
+        assert_eq!(
+            messages[1],
+            "From: jose@example.com\nSubject: Second\n\nBye!\n"
+        );
+        assert_eq!(
+            String::from_utf8(unquote(messages[0].as_bytes())).unwrap(),
+            "From: jose@example.com\nSubject: First\nStatus: RO\n\nFrom the start\n>From quoted\nFrom unquoted, but not a separator\n"
+        );
+    }
+
+    #[test]
+    fn ensure_mboxcl2_is_indexed() {
+        let messages = messages(MBOXCL2, MboxFormat::Mboxcl2);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].ends_with("\n\nFrom the start\n\nFrom inside\n"));
+        assert!(messages[1].ends_with("\n\nBye!\n"));
+    }
+
+    #[test]
+    fn ensure_flags_are_read() {
+        assert_eq!(
+            flags(b"Status: RO\nX-Status: AF\n"),
+            [Flag::Seen, Flag::Answered, Flag::Flagged]
+        );
+        assert_eq!(flags(b"Status: O\n"), []);
+    }
+
+    #[test]
+    fn written_messages_roundtrip() {
+        let raw = b"From: jose@example.com\r\nSubject: Hi\r\n\r\nFrom here\r\n>From there\r\n";
+        let mut mbox = vec![];
+        write_message(&mut mbox, raw, DateTime::UNIX_EPOCH).unwrap();
+        write_message(&mut mbox, raw, DateTime::UNIX_EPOCH).unwrap();
+        let mbox = String::from_utf8(mbox).unwrap();
+        assert!(mbox.starts_with("From MAILER-DAEMON Thu Jan  1 00:00:00 1970\n"));
+
+        let messages = messages(&mbox, MboxFormat::Mboxrd);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            String::from_utf8(unquote(messages[1].as_bytes())).unwrap(),
+            "From: jose@example.com\nSubject: Hi\n\nFrom here\n>From there\n"
+        );
+    }
+}
@@ -1,19 +1,28 @@
 #![cfg(feature = "refresher")]
-use std::env;
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
 
 use axum::{
     extract::{Query, State},
+    response::Html,
     routing::get,
     Router,
 };
 use oauth2::{
-    basic::BasicClient,
+    basic::{BasicClient, BasicTokenResponse},
     reqwest::{self},
-    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointNotSet, EndpointSet,
-    PkceCodeChallenge, RedirectUrl, Scope, TokenResponse, TokenUrl,
+    AccessToken, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointNotSet,
+    EndpointSet, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, RefreshToken, Scope,
+    TokenResponse, TokenUrl,
 };
 use serde::Deserialize;
-use tokio::{io::AsyncWriteExt, net::TcpListener, sync::mpsc};
+use tokio::{
+    io::AsyncWriteExt,
+    net::TcpListener,
+    sync::{mpsc, oneshot},
+};
 use tokio_util::{sync::CancellationToken, task::TaskTracker};
 
 use crate::Error;
@@ -23,6 +32,30 @@ const GMAIL_CLIENT_SECRET: &str = env!("GMAIL_CLIENT_SECRET");
 
 const GMAIL_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/auth";
 
+/// Default amount of time we wait for the user to complete the flow in the browser.
+pub const DEFAULT_REDIRECT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long the redirect server gets to close its connections once the flow is over.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SUCCESS_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>eCTT</title></head>
+<body style="font-family: sans-serif; text-align: center; margin-top: 4em;">
+<h1>You're all set!</h1>
+<p>eCTT has been authorized, you can close this tab and go back to your terminal.</p>
+</body>
+</html>"#;
+
+/// Shown for requests which aren't the redirect, e.g. a browser prefetching the page.
+const WAITING_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>eCTT</title></head>
+<body style="font-family: sans-serif; text-align: center; margin-top: 4em;">
+<p>eCTT is waiting for the authorization to complete.</p>
+</body>
+</html>"#;
+
 pub type AppClient = BasicClient<
     EndpointSet, // Auth URL
     EndpointNotSet,
@@ -45,6 +78,15 @@ impl std::fmt::Display for Provider {
     }
 }
 
+impl Provider {
+    /// Secret of eCTT's own client, it's baked into the binary so it isn't much of a secret.
+    pub fn client_secret(&self) -> ClientSecret {
+        match self {
+            Provider::Gmail => ClientSecret::new(GMAIL_CLIENT_SECRET.to_string()),
+        }
+    }
+}
+
 impl From<Provider> for (AppClient, Vec<Scope>) {
     fn from(value: Provider) -> Self {
         match value {
@@ -54,14 +96,12 @@ impl From<Provider> for (AppClient, Vec<Scope>) {
                     .expect("passed URL should be valid");
                 let auth_url =
                     AuthUrl::new(GMAIL_AUTH_URL.to_string()).expect("passed URL should be valid");
-                let redirect_url = RedirectUrl::new("http://localhost:3000".to_string())
-                    .expect("passed URL should be valid");
 
+                // The redirect URL is only known once the redirect server is bound
                 let client = BasicClient::new(client_id)
                     .set_token_uri(token_url)
                     .set_auth_uri(auth_url)
-                    .set_redirect_uri(redirect_url)
-                    .set_client_secret(ClientSecret::new(GMAIL_CLIENT_SECRET.to_string()));
+                    .set_client_secret(Provider::Gmail.client_secret());
 
                 let scopes = vec![Scope::new("https://mail.google.com/".to_string())];
 
@@ -74,80 +114,26 @@ impl From<Provider> for (AppClient, Vec<Scope>) {
 pub async fn execute_authentication_flow(
     client: AppClient,
     scopes: Vec<Scope>,
+    port: u16,
+    timeout: Duration,
 ) -> Result<(), Error> {
-    let tracker = TaskTracker::new();
-    let cancellation_token = CancellationToken::new();
-
-    let (tx, rx) = mpsc::channel(1);
-
-    let state = AuthorizationState {
-        rx,
-        cancellation_token: cancellation_token.child_token(),
-    };
-    tracker.spawn(get_authorization(state, client, scopes));
-
-    let state = RedirectServerState {
-        tx,
-        cancellation_token: cancellation_token.child_token(),
-    };
-    tracker.spawn(setup_redirect_server(state));
-
-    tokio::signal::ctrl_c()
-        .await
-        .expect("Failed to listen for Ctrl-C");
-    tracing::info!("Received Ctrl-C, closing...");
-    cancellation_token.cancel();
-    tracker.close();
-
-    Ok(())
-}
-
-struct AuthorizationState {
-    rx: mpsc::Receiver<AuthorizationPayload>,
-    cancellation_token: CancellationToken,
-}
-
-async fn get_authorization(
-    mut state: AuthorizationState,
-    client: AppClient,
-    scopes: Vec<Scope>,
-) -> Result<(), Error> {
-    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
-
-    let (auth_url, csrf_token) = client
-        .authorize_url(CsrfToken::new_random)
-        .add_scopes(scopes)
-        .set_pkce_challenge(pkce_challenge)
-        .url();
+    let flow = AuthorizationFlow::start(client, scopes, port).await?;
 
     let mut stdout = tokio::io::stdout();
     stdout
-        .write_all(format!("Open URL: {}", auth_url).as_bytes())
+        .write_all(format!("Open URL: {}\n", flow.url()).as_bytes())
         .await
         // If it fails writing to the stdout this error message is useless but ¯\_(ツ)_/¯
         .expect("Failed to write to stdout");
     stdout.flush().await.expect("Failed to flush stdout");
 
-    let http_client = reqwest::ClientBuilder::new()
-        // Following redirects opens the client up to SSRF vulnerabilities.
-        .redirect(reqwest::redirect::Policy::none())
-        .build()
-        .expect("Client should build");
-
-    // cancel should be here
-    let authorization_payload = state.rx.recv().await.unwrap();
-
-    debug_assert_eq!(authorization_payload.state.secret(), csrf_token.secret());
-
-    // Now you can trade it for an access token.
-    let token_result = client
-        .exchange_code(authorization_payload.code)
-        // Set the PKCE code verifier.
-        .set_pkce_verifier(pkce_verifier)
-        .request_async(&http_client)
-        // and here
-        .await
-        .unwrap(); // TODO
+    let token_result = tokio::select! {
+        result = flow.finish(timeout) => result?,
+        _ = tokio::signal::ctrl_c() => {
+            tracing::info!("Received Ctrl-C, closing...");
+            return Ok(());
+        }
+    };
 
     println!(
         "token: {}",
@@ -161,60 +147,383 @@ async fn get_authorization(
             .map(|t| t.into_secret())
     );
 
-    state.cancellation_token.cancel();
-
     Ok(())
 }
 
+/// Progress of a login flow running in the background, see [`spawn_login`].
+pub enum LoginEvent {
+    /// The redirect server is up and the user should open this URL.
+    Url(String),
+    Success {
+        access_token: AccessToken,
+        /// Providers are not required to send a new refresh token.
+        refresh_token: Option<RefreshToken>,
+    },
+    Failed(Error),
+}
+
+/// Run the authorization flow in its own thread (and runtime) so it can be driven from the TUI.
+pub fn spawn_login(client: AppClient, scopes: Vec<Scope>) -> std::sync::mpsc::Receiver<LoginEvent> {
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(err) => {
+                let _ = tx.send(LoginEvent::Failed(err.into()));
+                return;
+            }
+        };
+
+        let event = runtime.block_on(async {
+            let flow = match AuthorizationFlow::start(client, scopes, 0).await {
+                Ok(flow) => flow,
+                Err(err) => return LoginEvent::Failed(err),
+            };
+            if tx.send(LoginEvent::Url(flow.url().to_string())).is_err() {
+                // Nobody is listening anymore, the flow is pointless
+                return LoginEvent::Failed(std::io::Error::other("Login was cancelled").into());
+            }
+            match flow.finish(DEFAULT_REDIRECT_TIMEOUT).await {
+                Ok(token_result) => LoginEvent::Success {
+                    access_token: token_result.access_token().clone(),
+                    refresh_token: token_result.refresh_token().cloned(),
+                },
+                Err(err) => LoginEvent::Failed(err),
+            }
+        });
+
+        if tx.send(event).is_err() {
+            tracing::warn!("Login finished but the TUI is no longer listening");
+        }
+    });
+
+    rx
+}
+
+/// An authorization flow which has its redirect server running and is waiting for the user.
+pub struct AuthorizationFlow {
+    client: AppClient,
+    url: oauth2::url::Url,
+    csrf_token: CsrfToken,
+    pkce_verifier: PkceCodeVerifier,
+
+    rx: mpsc::Receiver<Callback>,
+    tracker: TaskTracker,
+    cancellation_token: CancellationToken,
+}
+
+impl AuthorizationFlow {
+    /// Bind the redirect server to the loopback interface and build the authorization URL.
+    ///
+    /// Passing `0` as the port will make the OS pick an ephemeral one.
+    pub async fn start(client: AppClient, scopes: Vec<Scope>, port: u16) -> Result<Self, Error> {
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).await?;
+        let address = listener.local_addr()?;
+        tracing::debug!("Starting redirect server on {address}");
+
+        let redirect_url = RedirectUrl::new(format!("http://{address}"))
+            .expect("loopback address should be a valid URL");
+        let client = client.set_redirect_uri(redirect_url);
+
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let (url, csrf_token) = client
+            .authorize_url(CsrfToken::new_random)
+            .add_scopes(scopes)
+            .set_pkce_challenge(pkce_challenge)
+            // Without these, Google will only hand out a refresh token on the very first login
+            .add_extra_param("access_type", "offline")
+            .add_extra_param("prompt", "consent")
+            .url();
+
+        let tracker = TaskTracker::new();
+        let cancellation_token = CancellationToken::new();
+        let (tx, rx) = mpsc::channel(1);
+
+        let state = RedirectServerState { tx };
+        tracker.spawn(serve_redirect(
+            listener,
+            state,
+            cancellation_token.child_token(),
+        ));
+        tracker.close();
+
+        Ok(Self {
+            client,
+            url,
+            csrf_token,
+            pkce_verifier,
+            rx,
+            tracker,
+            cancellation_token,
+        })
+    }
+
+    pub fn url(&self) -> &oauth2::url::Url {
+        &self.url
+    }
+
+    /// Wait for the redirect, validate it and trade the authorization code for the tokens.
+    pub async fn finish(mut self, timeout: Duration) -> Result<BasicTokenResponse, Error> {
+        let result = match tokio::time::timeout(timeout, self.complete()).await {
+            Ok(result) => result,
+            Err(_) => {
+                tracing::error!("Timed out waiting for the OAuth redirect");
+                Err(Error::OAuthTimeout)
+            }
+        };
+
+        // Requests still queued or arriving now are answered right away, otherwise their
+        // connections would keep the server from shutting down
+        self.rx.close();
+        while self.rx.try_recv().is_ok() {}
+
+        // The server has received the redirect (or an error), it can now shutdown
+        tracing::debug!("Requesting web server to stop...");
+        self.cancellation_token.cancel();
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.tracker.wait())
+            .await
+            .is_err()
+        {
+            tracing::warn!("Redirect server didn't stop in time, leaving it behind");
+        }
+
+        result
+    }
+
+    async fn complete(&mut self) -> Result<BasicTokenResponse, Error> {
+        let (params, reply) = loop {
+            let Some(Callback { params, reply }) = self.rx.recv().await else {
+                return Err(std::io::Error::other(
+                    "Redirect server stopped unexpectedly",
+                ))?;
+            };
+            // Only the redirect carries the state, anything else (prefetches, a reload of the
+            // bare URL) can't abort the flow
+            if params.state.is_none() {
+                tracing::debug!("Ignoring a request without state");
+                let _ = reply.send(WAITING_PAGE.to_string());
+                continue;
+            }
+            break (params, reply);
+        };
+
+        let result = self.exchange(params).await;
+        let page = match &result {
+            Ok(_) => SUCCESS_PAGE.to_string(),
+            Err(err) => failure_page(err),
+        };
+        if reply.send(page).is_err() {
+            tracing::warn!("Failed to reply to the browser, the connection was closed");
+        }
+
+        result
+    }
+
+    async fn exchange(&mut self, params: CallbackParams) -> Result<BasicTokenResponse, Error> {
+        if params.state.as_deref() != Some(self.csrf_token.secret().as_str()) {
+            tracing::error!("Received a redirect with a mismatched CSRF state");
+            return Err(Error::OAuthStateMismatch);
+        }
+
+        if let Some(error) = params.error {
+            tracing::error!("OAuth provider returned an error: {error}");
+            return Err(Error::OAuthProvider {
+                error,
+                description: params.error_description,
+            });
+        }
+
+        let Some(code) = params.code else {
+            return Err(Error::OAuthProvider {
+                error: "invalid_request".to_string(),
+                description: Some("The redirect did not contain an authorization code".to_string()),
+            });
+        };
+
+        let http_client = reqwest::ClientBuilder::new()
+            // Following redirects opens the client up to SSRF vulnerabilities.
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Client should build");
+
+        // The verifier can only be used once, and so can this flow
+        let pkce_verifier = std::mem::replace(
+            &mut self.pkce_verifier,
+            PkceCodeVerifier::new(String::new()),
+        );
+
+        Ok(self
+            .client
+            .exchange_code(AuthorizationCode::new(code))
+            .set_pkce_verifier(pkce_verifier)
+            .request_async(&http_client)
+            .await?)
+    }
+}
+
+fn failure_page(err: &Error) -> String {
+    // The error messages are ours or the provider's error codes, still, no reason to trust them
+    let message = err
+        .to_string()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>eCTT</title></head>
+<body style="font-family: sans-serif; text-align: center; margin-top: 4em;">
+<h1>Authorization failed</h1>
+<p>{message}</p>
+<p>Go back to your terminal and try again.</p>
+</body>
+</html>"#
+    )
+}
+
+/// The redirect payload along with the channel used to reply to the browser.
+struct Callback {
+    params: CallbackParams,
+    reply: oneshot::Sender<String>,
+}
+
 #[derive(Debug, Clone)]
 struct RedirectServerState {
     // Ideally, this would be a oneshot channel, however, due to oneshot being moved on send,
     // it doesn't work for our purposes, we could use an Arc<Mutex<C>> but that's overkill
-    tx: mpsc::Sender<AuthorizationPayload>,
-    cancellation_token: CancellationToken,
+    tx: mpsc::Sender<Callback>,
 }
 
-async fn setup_redirect_server(state: RedirectServerState) -> Result<(), std::io::Error> {
-    let token = state.cancellation_token.clone();
-
+async fn serve_redirect(
+    listener: TcpListener,
+    state: RedirectServerState,
+    cancellation_token: CancellationToken,
+) -> Result<(), std::io::Error> {
     let app = Router::new()
         .route("/", get(authorization_callback))
         .with_state(state);
 
-    tracing::debug!("Starting redirect server on 0.0.0.0:3000");
-    let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
     axum::serve(listener, app.into_make_service())
         .with_graceful_shutdown(async move {
-            token.cancelled().await;
+            cancellation_token.cancelled().await;
         })
         .await
+        .inspect_err(|err| tracing::error!("Redirect server failed with error: {err}"))
 }
 
+/// Everything is optional because the provider either sends the code or an error.
 #[derive(Debug, Deserialize)]
-struct AuthorizationPayload {
-    state: CsrfToken,
-    code: AuthorizationCode,
-
-    // Still comes in the payload
-    #[allow(unused)]
-    scope: Scope,
+struct CallbackParams {
+    state: Option<String>,
+    code: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
 }
 
 #[axum::debug_handler]
 async fn authorization_callback(
-    Query(payload): Query<AuthorizationPayload>,
-    State(RedirectServerState {
-        tx,
-        cancellation_token,
-    }): State<RedirectServerState>,
-) {
-    tracing::debug!("Received authorization payload: {payload:?}");
-    if let Err(err) = tx.send(payload).await {
+    Query(params): Query<CallbackParams>,
+    State(RedirectServerState { tx }): State<RedirectServerState>,
+) -> Html<String> {
+    tracing::debug!("Received authorization callback");
+    let (reply, response) = oneshot::channel();
+    if let Err(err) = tx.send(Callback { params, reply }).await {
         tracing::error!(
             "Failed to send the payload code to the application thread with error: {err}"
         );
     };
-    // The server has received the redirect (or an error), it can now shutdown
-    tracing::debug!("Requesting web server to stop...");
-    cancellation_token.cancel();
+
+    match response.await {
+        Ok(page) => Html(page),
+        // Either a second redirect or the flow is already gone
+        Err(_) => Html(failure_page(&Error::OAuthProvider {
+            error: "invalid_request".to_string(),
+            description: Some("This authorization request is no longer active".to_string()),
+        })),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use oauth2::{basic::BasicClient, reqwest, AuthUrl, ClientId, TokenUrl};
+
+    use super::{AppClient, AuthorizationFlow, WAITING_PAGE};
+    use crate::Error;
+
+    /// A client whose endpoints are never reached, the flows here stop before the exchange.
+    fn client() -> AppClient {
+        BasicClient::new(ClientId::new("ectt".to_string()))
+            .set_auth_uri(AuthUrl::new("https://auth.example.com/".to_string()).unwrap())
+            .set_token_uri(TokenUrl::new("https://token.example.com/".to_string()).unwrap())
+    }
+
+    /// Start a flow and return it along with its redirect URL and state.
+    async fn start() -> (AuthorizationFlow, String, String) {
+        let flow = AuthorizationFlow::start(client(), vec![], 0).await.unwrap();
+        let redirect = flow.client.redirect_uri().unwrap().url().to_string();
+        let state = flow.csrf_token.secret().clone();
+        (flow, redirect, state)
+    }
+
+    async fn get(url: String) -> String {
+        reqwest::get(url).await.unwrap().text().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn mismatched_state_is_rejected() {
+        let (flow, redirect, _) = start().await;
+        let finished = tokio::spawn(flow.finish(Duration::from_secs(10)));
+
+        let page = get(format!("{redirect}?state=forged&code=stolen")).await;
+        assert!(page.contains("Authorization failed"));
+        assert!(matches!(
+            finished.await.unwrap(),
+            Err(Error::OAuthStateMismatch)
+        ));
+    }
+
+    #[tokio::test]
+    async fn provider_errors_are_reported() {
+        let (flow, redirect, state) = start().await;
+        let finished = tokio::spawn(flow.finish(Duration::from_secs(10)));
+
+        let page = get(format!("{redirect}?state={state}&error=access_denied")).await;
+        assert!(page.contains("access_denied"));
+        assert!(matches!(
+            finished.await.unwrap(),
+            Err(Error::OAuthProvider { error, .. }) if error == "access_denied"
+        ));
+    }
+
+    #[tokio::test]
+    async fn requests_without_state_are_ignored() {
+        let (flow, redirect, state) = start().await;
+        let finished = tokio::spawn(flow.finish(Duration::from_secs(10)));
+
+        assert_eq!(get(redirect.clone()).await, WAITING_PAGE);
+        assert_eq!(get(format!("{redirect}?code=early")).await, WAITING_PAGE);
+        assert!(!finished.is_finished());
+
+        // The flow is still waiting for the actual redirect
+        get(format!("{redirect}?state={state}&error=access_denied")).await;
+        assert!(matches!(
+            finished.await.unwrap(),
+            Err(Error::OAuthProvider { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn finish_times_out() {
+        let (flow, _, _) = start().await;
+        assert!(matches!(
+            flow.finish(Duration::from_millis(50)).await,
+            Err(Error::OAuthTimeout)
+        ));
+    }
 }
@@ -0,0 +1,255 @@
+//! Just enough of [RFC 1939](https://www.rfc-editor.org/rfc/rfc1939) and
+//! [RFC 2595](https://www.rfc-editor.org/rfc/rfc2595) (`STLS`) to read mail.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+};
+
+use rustls::{ClientConnection, StreamOwned};
+
+use crate::{
+    pop3::config::{Auth, Pop3Config},
+    tls::{TlsConfig, TlsMode},
+};
+
+pub enum Stream {
+    Tcp(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+fn wrap_tls(tls: &TlsConfig, host: &str, stream: TcpStream) -> Result<Stream, crate::Error> {
+    let stream = tls.connect(host, stream)?;
+    Ok(Stream::Tls(Box::new(stream)))
+}
+
+pub struct Client {
+    stream: BufReader<Stream>,
+}
+
+impl Client {
+    /// Connect, upgrade to TLS if needed and login.
+    pub fn connect(config: &Pop3Config) -> Result<Self, crate::Error> {
+        config.tls.warn_if_dangerous(&config.host);
+        let tcp = TcpStream::connect((config.host.as_str(), config.port))?;
+        let mut client = match config.tls.mode {
+            TlsMode::Implicit => Self::new(wrap_tls(&config.tls, &config.host, tcp)?)?,
+            TlsMode::None => Self::new(Stream::Tcp(tcp))?,
+            TlsMode::StartTls => {
+                let mut client = Self::new(Stream::Tcp(tcp))?;
+                client.command("STLS")?;
+                let Stream::Tcp(tcp) = client.stream.into_inner() else {
+                    unreachable!("the connection was created without TLS")
+                };
+                Self {
+                    stream: BufReader::new(wrap_tls(&config.tls, &config.host, tcp)?),
+                }
+            }
+        };
+
+        match &config.auth {
+            Auth::Password(password_config) => {
+                client.command(&format!("USER {}", config.login))?;
+                client.command(&format!("PASS {}", password_config.raw))?;
+            }
+        }
+        Ok(client)
+    }
+
+    /// Wrap an established connection, reading the server's greeting.
+    pub fn new(stream: Stream) -> Result<Self, crate::Error> {
+        let mut client = Self {
+            stream: BufReader::new(stream),
+        };
+        client.read_status()?;
+        Ok(client)
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, crate::Error> {
+        let mut line = vec![];
+        if self.stream.read_until(b'\n', &mut line)? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        }
+        Ok(line)
+    }
+
+    /// Read a `+OK`/`-ERR` line, returning the rest of it on success.
+    fn read_status(&mut self) -> Result<String, crate::Error> {
+        let line = self.read_line()?;
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end();
+        if let Some(rest) = line.strip_prefix("+OK") {
+            return Ok(rest.trim_start().to_string());
+        }
+        let reason = line.strip_prefix("-ERR").unwrap_or(line).trim_start();
+        Err(crate::Error::Pop3(reason.to_string()))
+    }
+
+    fn command(&mut self, command: &str) -> Result<String, crate::Error> {
+        let stream = self.stream.get_mut();
+        stream.write_all(command.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()?;
+        self.read_status()
+    }
+
+    /// Run a command with a multi-line response, removing the dot-stuffing.
+    fn multiline(&mut self, command: &str) -> Result<Vec<u8>, crate::Error> {
+        self.command(command)?;
+        let mut response = vec![];
+        loop {
+            let line = self.read_line()?;
+            if line == b".\r\n" || line == b".\n" {
+                break;
+            }
+            match line.strip_prefix(b".") {
+                Some(line) => response.extend_from_slice(line),
+                None => response.extend_from_slice(&line),
+            }
+        }
+        Ok(response)
+    }
+
+    /// Number of messages and their total size.
+    pub fn stat(&mut self) -> Result<(u32, u64), crate::Error> {
+        let response = self.command("STAT")?;
+        let mut parts = response.split_whitespace().map(str::parse::<u64>);
+        match (parts.next(), parts.next()) {
+            (Some(Ok(count)), Some(Ok(size))) => Ok((count as u32, size)),
+            _ => Err(crate::Error::Pop3(format!(
+                "malformed STAT response: {response}"
+            ))),
+        }
+    }
+
+    /// Size of message `number`.
+    pub fn list(&mut self, number: u32) -> Result<u64, crate::Error> {
+        let response = self.command(&format!("LIST {number}"))?;
+        response
+            .split_whitespace()
+            .nth(1)
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| crate::Error::Pop3(format!("malformed LIST response: {response}")))
+    }
+
+    /// Message numbers along with their unique IDs, in the server's order.
+    pub fn uidl(&mut self) -> Result<Vec<(u32, String)>, crate::Error> {
+        let response = self.multiline("UIDL")?;
+        Ok(String::from_utf8_lossy(&response)
+            .lines()
+            .filter_map(|line| {
+                let (number, uidl) = line.trim().split_once(' ')?;
+                Some((number.parse().ok()?, uidl.to_string()))
+            })
+            .collect())
+    }
+
+    /// The header of message `number`.
+    pub fn top(&mut self, number: u32) -> Result<Vec<u8>, crate::Error> {
+        self.multiline(&format!("TOP {number} 0"))
+    }
+
+    pub fn retr(&mut self, number: u32) -> Result<Vec<u8>, crate::Error> {
+        self.multiline(&format!("RETR {number}"))
+    }
+
+    /// Mark message `number` for deletion, which only happens on [`Client::quit`].
+    pub fn dele(&mut self, number: u32) -> Result<(), crate::Error> {
+        self.command(&format!("DELE {number}"))?;
+        Ok(())
+    }
+
+    pub fn quit(mut self) -> Result<(), crate::Error> {
+        self.command("QUIT")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::{Client, Stream};
+
+    /// Reply to each expected command in order, failing on anything else.
+    fn serve(script: Vec<(&'static str, &'static str)>) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            stream.write_all(b"+OK POP3 ready\r\n").unwrap();
+            for (command, reply) in script {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                assert_eq!(line, format!("{command}\r\n"));
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        TcpStream::connect(address).unwrap()
+    }
+
+    #[test]
+    fn client_speaks_pop3() {
+        let stream = serve(vec![
+            ("STAT", "+OK 2 320\r\n"),
+            ("UIDL", "+OK\r\n1 aaa\r\n2 bbb\r\n.\r\n"),
+            (
+                "TOP 2 0",
+                "+OK\r\nFrom: jose@example.com\r\nSubject: Hi\r\n\r\n.\r\n",
+            ),
+            ("LIST 2", "+OK 2 160\r\n"),
+            ("RETR 2", "+OK\r\nSubject: Hi\r\n\r\n..dotted\r\n.\r\n"),
+            ("DELE 1", "+OK\r\n"),
+            ("DELE 3", "-ERR no such message\r\n"),
+            ("QUIT", "+OK bye\r\n"),
+        ]);
+        let mut client = Client::new(Stream::Tcp(stream)).unwrap();
+
+        assert_eq!(client.stat().unwrap(), (2, 320));
+        assert_eq!(
+            client.uidl().unwrap(),
+            [(1, "aaa".to_string()), (2, "bbb".to_string())]
+        );
+        assert_eq!(
+            client.top(2).unwrap(),
+            b"From: jose@example.com\r\nSubject: Hi\r\n\r\n"
+        );
+        assert_eq!(client.list(2).unwrap(), 160);
+        assert_eq!(client.retr(2).unwrap(), b"Subject: Hi\r\n\r\n.dotted\r\n");
+        client.dele(1).unwrap();
+        assert!(matches!(
+            client.dele(3),
+            Err(crate::Error::Pop3(reason)) if reason == "no such message"
+        ));
+        client.quit().unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use crate::tls::TlsConfig;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Pop3Config {
+    pub host: String,
+    pub port: u16,
+    pub login: String,
+    pub auth: Auth,
+    #[serde(flatten)]
+    pub tls: TlsConfig,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "lowercase")]
+pub enum Auth {
+    Password(PasswordConfig),
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PasswordConfig {
+    pub raw: String,
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::{imap::config::ReadBackend, tls::TlsMode};
+
+    use super::{Auth, PasswordConfig, Pop3Config};
+
+    #[test]
+    fn ensure_pop3_format() {
+        let json = json!({
+            "type": "pop3",
+            "host": "pop.example.com",
+            "port": 995,
+            "login": "jose@example.com",
+            "auth": {
+                "type": "password",
+                "raw": "super-secret"
+            }
+        });
+        let ReadBackend::Pop3(Pop3Config {
+            host,
+            port,
+            login,
+            auth: Auth::Password(PasswordConfig { raw }),
+            tls,
+        }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
+        assert_eq!(host, "pop.example.com");
+        assert_eq!(port, 995);
+        assert_eq!(login, "jose@example.com");
+        assert_eq!(raw, "super-secret");
+        assert_eq!(tls.mode, TlsMode::Implicit);
+    }
+
+    #[test]
+    fn ensure_starttls_format() {
+        let json = json!({
+            "type": "pop3",
+            "host": "pop.example.com",
+            "port": 110,
+            "login": "jose@example.com",
+            "auth": {
+                "type": "password",
+                "raw": "super-secret"
+            },
+            "tls": "starttls",
+            "ca_file": "/etc/ssl/local-ca.pem"
+        });
+        let ReadBackend::Pop3(Pop3Config { tls, .. }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
+        assert_eq!(tls.mode, TlsMode::StartTls);
+        assert!(tls.has_custom_certs());
+    }
+}
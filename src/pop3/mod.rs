@@ -0,0 +1,256 @@
+pub mod client;
+pub mod config;
+
+use std::{collections::HashSet, fs, path::PathBuf};
+
+use crate::{
+    config::ectt_data_dir,
+    pop3::{client::Client, config::Pop3Config},
+    store::{Flag, IdMap, MailStore, Mailbox, ParsedEmail, INBOX},
+};
+
+/// UIDLs of the messages the user has already seen, POP3 has no flags of its own.
+struct SeenUidls {
+    path: PathBuf,
+    uidls: HashSet<String>,
+}
+
+impl SeenUidls {
+    fn load(path: PathBuf) -> Result<Self, crate::Error> {
+        let uidls = match fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().map(ToString::to_string).collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashSet::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self { path, uidls })
+    }
+
+    fn contains(&self, uidl: &str) -> bool {
+        self.uidls.contains(uidl)
+    }
+
+    /// Mark `uidl` as seen (or not) and save it right away.
+    fn set(&mut self, uidl: &str, seen: bool) -> Result<(), crate::Error> {
+        let changed = if seen {
+            self.uidls.insert(uidl.to_string())
+        } else {
+            self.uidls.remove(uidl)
+        };
+        if changed {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Forget the UIDLs which are no longer on the server.
+    fn retain(&mut self, on_server: &HashSet<&str>) -> Result<(), crate::Error> {
+        let before = self.uidls.len();
+        self.uidls.retain(|uidl| on_server.contains(uidl.as_str()));
+        if self.uidls.len() != before {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), crate::Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut contents = self.uidls.iter().cloned().collect::<Vec<_>>();
+        contents.sort();
+        fs::write(&self.path, contents.join("\n"))?;
+        Ok(())
+    }
+}
+
+fn seen_path(config: &Pop3Config) -> PathBuf {
+    ectt_data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("pop3")
+        .join(format!("{}-{}.seen", config.host, config.login))
+}
+
+/// POP3 access, a new session is opened for every operation since servers lock the maildrop
+/// while a session is open and drop idle ones after a few minutes anyway.
+pub struct Pop3Store {
+    config: Pop3Config,
+    seen: SeenUidls,
+    ids: IdMap,
+}
+
+impl Pop3Store {
+    pub fn new(config: Pop3Config) -> Result<Self, crate::Error> {
+        Ok(Self {
+            seen: SeenUidls::load(seen_path(&config))?,
+            config,
+            ids: IdMap::new(),
+        })
+    }
+
+    fn check_mailbox(mailbox: &str) -> Result<(), crate::Error> {
+        if !mailbox.eq_ignore_ascii_case(INBOX) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("POP3 only has an {INBOX}, {mailbox} does not exist"),
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn uidl(&self, uid: u32) -> Result<String, crate::Error> {
+        match self.ids.key(uid) {
+            Some(uidl) => Ok(uidl.to_string()),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Unknown message {uid}"),
+            ))?,
+        }
+    }
+
+    /// Find the message number for `uidl` in this session, they change between sessions.
+    fn number(client: &mut Client, uidl: &str) -> Result<u32, crate::Error> {
+        client
+            .uidl()?
+            .into_iter()
+            .find(|(_, candidate)| candidate == uidl)
+            .map(|(number, _)| number)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("Message {uidl} is no longer on the server"),
+                )
+                .into()
+            })
+    }
+
+    fn delete(&self, uidl: &str) -> Result<(), crate::Error> {
+        let mut client = Client::connect(&self.config)?;
+        let number = Self::number(&mut client, uidl)?;
+        client.dele(number)?;
+        // Deletions only happen once the session is over
+        client.quit()
+    }
+}
+
+impl MailStore for Pop3Store {
+    fn list_mailboxes(&mut self) -> Result<Vec<Mailbox>, crate::Error> {
+        Ok(vec![Mailbox {
+            name: INBOX.to_string(),
+            special_use: None,
+        }])
+    }
+
+    fn list_messages(
+        &mut self,
+        mailbox: &str,
+        count: u32,
+        offset: u32,
+    ) -> Result<Vec<ParsedEmail>, crate::Error> {
+        Self::check_mailbox(mailbox)?;
+        let mut client = Client::connect(&self.config)?;
+        let (total, _) = client.stat()?;
+        if total == 0 {
+            client.quit()?;
+            return Ok(vec![]);
+        }
+
+        let uidls = client.uidl()?;
+        if offset == 0 {
+            self.seen
+                .retain(&uidls.iter().map(|(_, uidl)| uidl.as_str()).collect())?;
+        }
+
+        let mut parsed_emails = Vec::with_capacity(count as usize);
+        // New messages are appended to the maildrop
+        for (number, uidl) in uidls
+            .iter()
+            .rev()
+            .skip(offset as usize)
+            .take(count as usize)
+        {
+            let header = client.top(*number)?;
+            let flags = if self.seen.contains(uidl) {
+                vec![Flag::Seen]
+            } else {
+                vec![]
+            };
+            let uid = self.ids.id(uidl);
+            let Some(parsed) = ParsedEmail::parse(uid, &header, None, flags) else {
+                tracing::error!("Failed to parse email message, ignoring...");
+                continue;
+            };
+            parsed_emails.push(parsed);
+        }
+        client.quit()?;
+        Ok(parsed_emails)
+    }
+
+    fn fetch_message(&mut self, mailbox: &str, uid: u32) -> Result<Vec<u8>, crate::Error> {
+        Self::check_mailbox(mailbox)?;
+        let uidl = self.uidl(uid)?;
+        let mut client = Client::connect(&self.config)?;
+        let number = Self::number(&mut client, &uidl)?;
+        let size = client.list(number)?;
+        tracing::debug!("Retrieving message {number} ({size} bytes)");
+        let raw = client.retr(number)?;
+        client.quit()?;
+        Ok(raw)
+    }
+
+    fn set_flags(
+        &mut self,
+        mailbox: &str,
+        uid: u32,
+        flags: &[Flag],
+        enable: bool,
+    ) -> Result<(), crate::Error> {
+        Self::check_mailbox(mailbox)?;
+        let uidl = self.uidl(uid)?;
+        if flags.contains(&Flag::Seen) {
+            self.seen.set(&uidl, enable)?;
+        }
+        // Nothing is kept anywhere else, so only an explicit delete removes a message
+        if enable && flags.contains(&Flag::Deleted) {
+            self.delete(&uidl)?;
+        }
+        Ok(())
+    }
+
+    fn move_message(&mut self, _: &str, _: u32, _: &str) -> Result<(), crate::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("POP3 only has an {INBOX}, messages can't be moved"),
+        ))?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::SeenUidls;
+
+    #[test]
+    fn seen_uidls_are_kept() {
+        let path = std::env::temp_dir()
+            .join(format!("ectt-pop3-{}", std::process::id()))
+            .join("seen");
+
+        let mut seen = SeenUidls::load(path.clone()).unwrap();
+        seen.set("aaa", true).unwrap();
+        seen.set("bbb", true).unwrap();
+        seen.set("ccc", true).unwrap();
+        seen.set("bbb", false).unwrap();
+
+        let mut seen = SeenUidls::load(path.clone()).unwrap();
+        assert!(seen.contains("aaa"));
+        assert!(!seen.contains("bbb"));
+
+        seen.retain(&HashSet::from(["ccc"])).unwrap();
+        let seen = SeenUidls::load(path.clone()).unwrap();
+        assert!(!seen.contains("aaa"));
+        assert!(seen.contains("ccc"));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+pub const DEFAULT_COMMAND: &str = "/usr/sbin/sendmail -t -i";
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SendmailConfig {
+    /// Address the mail is sent from.
+    pub from: String,
+    /// Command the message is piped into, split on whitespace (there's no shell quoting).
+    #[serde(default = "default_command")]
+    pub command: String,
+    /// Append a copy of every sent message to the Sent mailbox, the MTA doesn't keep one.
+    #[serde(default = "crate::smtp::config::default_save_sent")]
+    pub save_sent: bool,
+}
+
+fn default_command() -> String {
+    DEFAULT_COMMAND.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::smtp::config::SendBackend;
+
+    use super::{SendmailConfig, DEFAULT_COMMAND};
+
+    #[test]
+    fn ensure_sendmail_format() {
+        let json = json!({
+            "type": "sendmail",
+            "from": "jose@example.com"
+        });
+        let SendBackend::Sendmail(SendmailConfig {
+            from,
+            command,
+            save_sent,
+        }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
+        assert_eq!(from, "jose@example.com");
+        assert_eq!(command, DEFAULT_COMMAND);
+        assert!(save_sent);
+
+        let json = json!({
+            "type": "sendmail",
+            "from": "jose@example.com",
+            "command": "msmtp -a work -t",
+            "save_sent": false
+        });
+        let SendBackend::Sendmail(SendmailConfig {
+            command, save_sent, ..
+        }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
+        assert_eq!(command, "msmtp -a work -t");
+        assert!(!save_sent);
+    }
+}
@@ -0,0 +1,139 @@
+pub mod config;
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use lettre::Address;
+
+use crate::{
+    sendmail::config::SendmailConfig,
+    smtp::{MailTransport, PartialMessage},
+};
+
+/// Hands the message over to a local MTA (sendmail, msmtp, etc).
+pub struct Sendmail {
+    config: SendmailConfig,
+}
+
+impl Sendmail {
+    pub fn new(config: SendmailConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl MailTransport for Sendmail {
+    fn send(&mut self, message: PartialMessage) -> Result<Option<Vec<u8>>, crate::Error> {
+        // The usual `-t` reads the recipients from the headers, so Bcc needs to be there
+        let message = message.into_message(self.config.from.parse::<Address>()?, true)?;
+
+        let mut args = self.config.command.split_whitespace();
+        let Some(program) = args.next() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the sendmail command is empty",
+            ))?;
+        };
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let raw = message.formatted();
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let message = raw.as_slice();
+        // Written alongside reading stderr, a command filling it up before reading all of its
+        // input would otherwise wait on us forever
+        let (written, output) = std::thread::scope(|scope| {
+            let writer = scope.spawn(move || stdin.write_all(message));
+            let output = child.wait_with_output();
+            (writer.join().expect("the writer doesn't panic"), output)
+        });
+        // If it exited early, the exit code and stderr tell us more than the broken pipe
+        let output = output?;
+        if !output.status.success() {
+            return Err(crate::Error::Sendmail {
+                command: self.config.command.clone(),
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        written?;
+        Ok(self.config.save_sent.then_some(raw))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        sendmail::config::SendmailConfig,
+        smtp::{MailTransport, PartialMessage},
+    };
+
+    use super::Sendmail;
+
+    fn message() -> PartialMessage {
+        PartialMessage {
+            to: Some("friend@example.com".parse().unwrap()),
+            cc: vec![],
+            bcc: vec!["secret@example.com".parse().unwrap()],
+            subject: Some("Hello".to_string()),
+            body: Some("Hi!".to_string()),
+            attachments: vec![],
+        }
+    }
+
+    #[test]
+    fn message_is_piped() {
+        let output = std::env::temp_dir().join(format!("ectt-sendmail-{}", std::process::id()));
+        let mut sendmail = Sendmail::new(SendmailConfig {
+            from: "jose@example.com".to_string(),
+            command: format!("tee {}", output.display()),
+            save_sent: true,
+        });
+        let sent = sendmail.send(message()).unwrap();
+
+        let piped = std::fs::read_to_string(&output).unwrap();
+        assert_eq!(sent.as_deref(), Some(piped.as_bytes()));
+        assert!(piped.contains("To: friend@example.com"));
+        assert!(piped.contains("Bcc: secret@example.com"));
+        assert!(piped.ends_with("Hi!"));
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn chatty_commands_get_the_whole_message() {
+        let mut sendmail = Sendmail::new(SendmailConfig {
+            from: "jose@example.com".to_string(),
+            command: "tee /dev/stderr".to_string(),
+            save_sent: true,
+        });
+        // Well past what the stderr pipe holds before someone reads it
+        let body = "Hi!\n".repeat(100_000);
+        let sent = sendmail
+            .send(PartialMessage {
+                body: Some(body),
+                ..message()
+            })
+            .unwrap();
+        assert!(sent.is_some_and(|sent| sent.len() > 400_000));
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        let mut sendmail = Sendmail::new(SendmailConfig {
+            from: "jose@example.com".to_string(),
+            command: "ls /nonexistent-ectt-directory".to_string(),
+            save_sent: true,
+        });
+        let err = sendmail.send(message()).unwrap_err();
+        let crate::Error::Sendmail { status, stderr, .. } = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert!(!status.success());
+        assert!(stderr.contains("nonexistent-ectt-directory"));
+    }
+}
@@ -1,3 +1,7 @@
+use crate::{
+    file::config::FileConfig, jmap::config::JmapConfig, sendmail::config::SendmailConfig,
+    tls::TlsConfig,
+};
 use oauth2::{
     basic::BasicClient, AccessToken, AuthUrl, ClientId, ClientSecret, EndpointNotSet, EndpointSet,
     RefreshToken, TokenUrl,
@@ -8,6 +12,21 @@ use oauth2::{
 #[serde(rename_all = "lowercase")]
 pub enum SendBackend {
     Smtp(SmtpConfig),
+    Jmap(JmapConfig),
+    Sendmail(SendmailConfig),
+    File(FileConfig),
+}
+
+impl SendBackend {
+    /// Address the mail is sent from.
+    pub fn sender(&self) -> &str {
+        match self {
+            SendBackend::Smtp(config) => &config.login,
+            SendBackend::Jmap(config) => &config.login,
+            SendBackend::Sendmail(config) => &config.from,
+            SendBackend::File(config) => &config.from,
+        }
+    }
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -16,6 +35,56 @@ pub struct SmtpConfig {
     pub port: u16,
     pub login: String,
     pub auth: Auth,
+    /// Mechanisms to try in order, the first one the server supports is used.
+    /// Defaults to the ones suited for `auth`.
+    #[serde(default)]
+    pub mechanisms: Vec<Mechanism>,
+    #[serde(flatten)]
+    pub tls: TlsConfig,
+    /// Append a copy of every sent message to the Sent mailbox, turn it off for
+    /// providers like Gmail which already do it.
+    #[serde(default = "default_save_sent")]
+    pub save_sent: bool,
+}
+
+pub(crate) fn default_save_sent() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mechanism {
+    Plain,
+    Login,
+    Xoauth2,
+    OAuthBearer,
+}
+
+impl Mechanism {
+    /// Name used in the `AUTH` command and the server's EHLO reply.
+    pub fn name(self) -> &'static str {
+        match self {
+            Mechanism::Plain => "PLAIN",
+            Mechanism::Login => "LOGIN",
+            Mechanism::Xoauth2 => "XOAUTH2",
+            Mechanism::OAuthBearer => "OAUTHBEARER",
+        }
+    }
+
+    /// Whether the mechanism takes an OAuth access token instead of a password.
+    pub fn is_oauth(self) -> bool {
+        matches!(self, Mechanism::Xoauth2 | Mechanism::OAuthBearer)
+    }
+}
+
+impl Auth {
+    /// Mechanisms to try when the configuration doesn't list any.
+    pub fn default_mechanisms(&self) -> &'static [Mechanism] {
+        match self {
+            Auth::Password(_) => &[Mechanism::Plain, Mechanism::Login],
+            Auth::OAuth(_) => &[Mechanism::Xoauth2, Mechanism::OAuthBearer],
+        }
+    }
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -59,7 +128,8 @@ mod test {
     use oauth2::{AccessToken, AuthUrl, ClientId, ClientSecret, RefreshToken, TokenUrl};
     use serde_json::json;
 
-    use super::{Auth, OAuthConfig, PasswordConfig, SendBackend, SmtpConfig};
+    use super::{Auth, Mechanism, OAuthConfig, PasswordConfig, SendBackend, SmtpConfig};
+    use crate::tls::TlsMode;
 
     /// Compilation will fail if for some reason the types stop implementing serde::Deserialize
     #[test]
@@ -90,13 +160,19 @@ mod test {
             port,
             login,
             auth,
-        }) = serde_json::from_value(json).unwrap();
+            save_sent,
+            ..
+        }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
 
         assert_eq!(host, "smtp.example.com".to_string());
-        assert_eq!(port, 993);
+        assert_eq!(port, 465);
         assert_eq!(login, "jose@example.com");
         // Defer the auth to the other tests
         assert!(matches!(auth, Auth::Password { .. }));
+        assert!(save_sent);
     }
 
     #[test]
@@ -121,7 +197,11 @@ mod test {
             port,
             login,
             auth,
-        }) = serde_json::from_value(json).unwrap();
+            ..
+        }) = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
 
         assert_eq!(host, "smtp.example.com".to_string());
         assert_eq!(port, 993);
@@ -130,6 +210,60 @@ mod test {
         assert!(matches!(auth, Auth::OAuth { .. }));
     }
 
+    #[test]
+    fn ensure_smtp_tls_format() {
+        let json = json!({
+            "type": "smtp",
+            "host": "smtp.example.com",
+            "port": 587,
+            "login": "jose@example.com",
+            "auth": {
+                "type": "password",
+                "raw": "super-secret"
+            },
+            "tls": "starttls",
+            "client_cert": "/etc/ssl/client.pem",
+            "client_key": "/etc/ssl/client.key",
+            "save_sent": false
+        });
+        let SendBackend::Smtp(SmtpConfig { tls, save_sent, .. }) =
+            serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
+
+        assert_eq!(tls.mode, TlsMode::StartTls);
+        assert_eq!(tls.ca_file, None);
+        assert!(tls.client_cert.is_some() && tls.client_key.is_some());
+        assert!(!tls.danger_accept_invalid_certs);
+        assert!(!save_sent);
+    }
+
+    #[test]
+    fn ensure_smtp_mechanisms_format() {
+        let json = json!({
+            "type": "smtp",
+            "host": "smtp.example.com",
+            "port": 465,
+            "login": "jose@example.com",
+            "auth": {
+                "type": "password",
+                "raw": "super-secret"
+            },
+            "mechanisms": ["login", "plain"]
+        });
+        let SendBackend::Smtp(SmtpConfig { mechanisms, .. }) =
+            serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong format");
+        };
+        assert_eq!(mechanisms, [Mechanism::Login, Mechanism::Plain]);
+
+        let mechanisms =
+            serde_json::from_value::<Vec<Mechanism>>(json!(["xoauth2", "oauthbearer"])).unwrap();
+        assert_eq!(mechanisms, [Mechanism::Xoauth2, Mechanism::OAuthBearer]);
+    }
+
     #[test]
     fn ensure_auth_password_format() {
         let json = json!({
@@ -0,0 +1,189 @@
+//! Outgoing messages, along with the ones handed over on the command line (see `ectt send`).
+
+use std::{fs, io, path::PathBuf};
+
+use lettre::{
+    address::Envelope,
+    message::{header::ContentType, Attachment, Mailbox, MessageBuilder, MultiPart, SinglePart},
+    Address, Message,
+};
+use mail_parser::MessageParser;
+
+/// A message built from `ectt send`'s flags.
+pub struct OutgoingMessage {
+    pub to: Vec<Address>,
+    pub cc: Vec<Address>,
+    pub bcc: Vec<Address>,
+    pub subject: Option<String>,
+    pub body: String,
+    pub attachments: Vec<PathBuf>,
+}
+
+impl OutgoingMessage {
+    pub fn into_message(self, from: Address) -> Result<Message, crate::Error> {
+        let mut builder = Message::builder()
+            .from(Mailbox::new(None, from))
+            .subject(self.subject.unwrap_or_default());
+        for to in self.to {
+            builder = builder.to(to.into());
+        }
+        for cc in self.cc {
+            builder = builder.cc(cc.into());
+        }
+        for bcc in self.bcc {
+            builder = builder.bcc(bcc.into());
+        }
+
+        with_attachments(builder, self.body, self.attachments)
+    }
+}
+
+/// Finish `builder` with `body`, in a multipart message along with the files at `attachments`
+/// when there are any.
+pub fn with_attachments(
+    builder: MessageBuilder,
+    body: String,
+    attachments: Vec<PathBuf>,
+) -> Result<Message, crate::Error> {
+    if attachments.is_empty() {
+        return Ok(builder.header(ContentType::TEXT_PLAIN).body(body)?);
+    }
+    let mut parts = MultiPart::mixed().singlepart(SinglePart::plain(body));
+    for path in attachments {
+        let filename = path
+            .file_name()
+            .map(|filename| filename.to_string_lossy().to_string())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not a file", path.display()),
+                )
+            })?;
+        let content_type =
+            ContentType::parse("application/octet-stream").expect("the content type is valid");
+        parts = parts.singlepart(Attachment::new(filename).body(fs::read(&path)?, content_type));
+    }
+    Ok(builder.multipart(parts)?)
+}
+
+/// The envelope of a complete RFC 5322 message, taken from its headers, along with the
+/// message without its `Bcc` header. `from` is used when there's no `From` header.
+pub fn raw_envelope(raw: &[u8], from: Address) -> Result<(Envelope, Vec<u8>), crate::Error> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let parsed = MessageParser::default()
+        .parse_headers(raw)
+        .ok_or_else(|| invalid("the message has no headers"))?;
+
+    let addresses = |address: Option<&mail_parser::Address>| {
+        address
+            .into_iter()
+            .flat_map(|address| address.iter())
+            .filter_map(|addr| addr.address())
+            .map(str::parse::<Address>)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let from = addresses(parsed.from())?.into_iter().next().unwrap_or(from);
+    let recipients = [parsed.to(), parsed.cc(), parsed.bcc()]
+        .into_iter()
+        .map(addresses)
+        .collect::<Result<Vec<_>, _>>()?
+        .concat();
+    if recipients.is_empty() {
+        return Err(invalid("the message has no To, Cc or Bcc recipients").into());
+    }
+    Ok((Envelope::new(Some(from), recipients)?, strip_bcc(raw)))
+}
+
+/// Drop the `Bcc` header, blind recipients only belong in the envelope.
+fn strip_bcc(raw: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(raw.len());
+    let mut in_bcc = false;
+    let mut in_headers = true;
+    for line in raw.split_inclusive(|&byte| byte == b'\n') {
+        if in_headers {
+            if line == b"\r\n" || line == b"\n" {
+                in_headers = false;
+            } else if line.starts_with(b" ") || line.starts_with(b"\t") {
+                // Folded, belongs to the previous header
+                if in_bcc {
+                    continue;
+                }
+            } else {
+                in_bcc = line.len() >= 4 && line[..4].eq_ignore_ascii_case(b"bcc:");
+                if in_bcc {
+                    continue;
+                }
+            }
+        }
+        stripped.extend_from_slice(line);
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{raw_envelope, OutgoingMessage};
+
+    #[test]
+    fn raw_messages_keep_bcc_in_the_envelope() {
+        let raw = b"From: Jose <jose@example.com>\r\n\
+            To: duarte@example.com\r\n\
+            Bcc: hidden@example.com,\r\n \
+             other@example.com\r\n\
+            Subject: Hi\r\n\
+            \r\n\
+            Bcc: this is the body\r\n";
+        let (envelope, stripped) =
+            raw_envelope(raw, "fallback@example.com".parse().unwrap()).unwrap();
+        assert_eq!(envelope.from().unwrap().to_string(), "jose@example.com");
+        let recipients = envelope
+            .to()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            recipients,
+            [
+                "duarte@example.com",
+                "hidden@example.com",
+                "other@example.com"
+            ]
+        );
+        assert_eq!(
+            String::from_utf8(stripped).unwrap(),
+            "From: Jose <jose@example.com>\r\n\
+             To: duarte@example.com\r\n\
+             Subject: Hi\r\n\
+             \r\n\
+             Bcc: this is the body\r\n"
+        );
+
+        let no_recipients = b"Subject: Hi\r\n\r\nHello\r\n";
+        assert!(raw_envelope(no_recipients, "jose@example.com".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn attachments_are_included() {
+        let path = std::env::temp_dir().join(format!("ectt-attachment-{}.txt", std::process::id()));
+        std::fs::write(&path, "attached").unwrap();
+        let message = OutgoingMessage {
+            to: vec!["duarte@example.com".parse().unwrap()],
+            cc: vec![],
+            bcc: vec![],
+            subject: Some("Report".to_string()),
+            body: "See attached".to_string(),
+            attachments: vec![path.clone()],
+        }
+        .into_message("jose@example.com".parse().unwrap())
+        .unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(formatted.contains("Content-Type: multipart/mixed"));
+        assert!(formatted.contains("See attached"));
+        assert!(formatted.contains(&format!(
+            "filename=\"{}\"",
+            path.file_name().unwrap().to_string_lossy()
+        )));
+    }
+}
@@ -1,21 +1,46 @@
-use std::sync::mpsc::{Receiver, Sender};
+use std::{
+    collections::HashSet,
+    fs,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
 
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, Local};
 use lettre::{
-    message::{header::ContentType, Mailbox},
+    address::Envelope,
+    message::Mailbox,
     transport::smtp::{
         authentication::{Credentials, Mechanism},
+        client::{Certificate, Identity, SmtpConnection, TlsParameters},
+        commands::Ehlo,
+        extension::ClientId as HelloName,
         response::{Category, Code, Detail, Severity},
     },
-    Address, Message, SmtpTransport, Transport,
+    Address, Message,
 };
 use oauth2::{
     reqwest::{self},
     TokenResponse,
 };
+#[cfg(feature = "refresher")]
+use oauth2::{AccessToken, ClientId, RefreshToken};
 
-use crate::smtp::config::{Auth, SmtpConfig};
+use crate::{
+    smtp::{
+        config::{Auth, SmtpConfig},
+        outbox::{Outbox, QueuedMessage},
+    },
+    tls::TlsMode,
+};
 
 pub mod config;
+pub mod message;
+pub mod outbox;
+
+/// Same as lettre's `SmtpTransport`.
+const TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub struct PartialMessage {
@@ -24,14 +49,16 @@ pub struct PartialMessage {
     pub bcc: Vec<Address>,
     pub subject: Option<String>,
     pub body: Option<String>,
+    pub attachments: Vec<PathBuf>,
 }
 
 impl PartialMessage {
-    fn into_message(self, from: Address) -> Result<Message, crate::Error> {
+    /// Build the final message, `keep_bcc` is for transports which read the recipients from
+    /// the headers (e.g. `sendmail -t`) instead of the envelope.
+    pub fn into_message(self, from: Address, keep_bcc: bool) -> Result<Message, crate::Error> {
         let mut builder = Message::builder()
             .from(Mailbox::new(None, from))
-            .subject(self.subject.unwrap_or_default())
-            .header(ContentType::TEXT_PLAIN);
+            .subject(self.subject.unwrap_or_default());
 
         if let Some(to) = self.to {
             builder = builder.to(to.into());
@@ -45,92 +72,471 @@ impl PartialMessage {
             builder = builder.bcc(bcc.into());
         }
 
-        Ok(builder.body(self.body.unwrap_or_default())?)
+        if keep_bcc {
+            builder = builder.keep_bcc();
+        }
+
+        message::with_attachments(builder, self.body.unwrap_or_default(), self.attachments)
     }
 }
 
 pub enum Command {
+    /// Queue a message in the outbox and try sending it right away.
     SendMail(PartialMessage),
+    /// Queue a message in the outbox, to be sent once `at` comes.
+    Schedule {
+        message: PartialMessage,
+        at: DateTime<Local>,
+    },
+    /// Move a queued message to a new time.
+    Reschedule { id: String, at: DateTime<Local> },
+    /// Try sending a queued message now, even if it gave up on it.
+    Retry(String),
+    /// Drop a queued message without sending it.
+    Discard(String),
+    /// Stop sending a queued message while it's being edited.
+    Hold(String),
+    /// Put a held message back in the queue, replaced by the edited `message` if there's one.
+    Release {
+        id: String,
+        message: Option<PartialMessage>,
+    },
+    /// New OAuth tokens, only applied if the configuration uses the same client.
+    #[cfg(feature = "refresher")]
+    Login {
+        client_id: ClientId,
+        access_token: AccessToken,
+        refresh_token: RefreshToken,
+    },
 }
 
 pub enum Response {
-    SendMailSuccess,
+    /// `sent` holds the exact bytes sent when a copy should go to the Sent mailbox.
+    SendMailSuccess {
+        sent: Option<Vec<u8>>,
+    },
+    /// The message stays in the outbox, `next_attempt` is when it will be retried, if at all.
+    SendMailFailed {
+        error: crate::Error,
+        next_attempt: Option<DateTime<Local>>,
+    },
+    /// The outbox contents, sent whenever they change.
+    Outbox(Vec<QueuedMessage>),
     Error(crate::Error),
 }
 
-pub fn run(
-    config: SmtpConfig,
+/// Something mail can be sent through, the TUI only ever talks to one through [`run`].
+pub trait MailTransport {
+    /// Returns the formatted message when it should also be saved to the Sent mailbox,
+    /// transports whose server already keeps a copy return `None`.
+    fn send(&mut self, message: PartialMessage) -> Result<Option<Vec<u8>>, crate::Error>;
+
+    /// New OAuth tokens, transports which don't use OAuth can ignore them.
+    #[cfg(feature = "refresher")]
+    fn update_oauth_tokens(
+        &mut self,
+        _client_id: &ClientId,
+        _access_token: AccessToken,
+        _refresh_token: RefreshToken,
+    ) -> Result<(), crate::Error> {
+        Ok(())
+    }
+}
+
+/// Sends messages through the transport, keeping them in the outbox until they make it.
+struct Sender<T> {
+    transport: T,
+    outbox: Outbox,
+    tx: mpsc::Sender<Response>,
+    /// Queued messages being edited, only kept in memory so a crash doesn't strand them.
+    held: HashSet<String>,
+}
+
+impl<T> Sender<T>
+where
+    T: MailTransport,
+{
+    /// Returns whether the main thread is still listening.
+    fn respond(&self, response: Response) -> bool {
+        if let Err(err) = self.tx.send(response) {
+            tracing::error!("Failed to send message to main thread with error: {err}");
+            return false;
+        }
+        true
+    }
+
+    fn publish(&self) -> Result<bool, crate::Error> {
+        Ok(self.respond(Response::Outbox(self.outbox.list()?)))
+    }
+
+    /// Try sending `queued`, failures are only reported back if `report` is set,
+    /// background retries only update the outbox.
+    fn attempt(&mut self, mut queued: QueuedMessage, report: bool) -> Result<bool, crate::Error> {
+        let result = queued
+            .message()
+            .and_then(|message| self.transport.send(message));
+        let listening = match result {
+            Ok(sent) => {
+                tracing::info!("Sent message {}", queued.id);
+                self.outbox.remove(&queued.id)?;
+                self.respond(Response::SendMailSuccess { sent })
+            }
+            Err(error) => {
+                tracing::error!("Failed to send email with error: {error}");
+                queued.failed(&error);
+                self.outbox.save(&queued)?;
+                if let Some(next_attempt) = queued.next_attempt() {
+                    tracing::warn!("Retrying {} at {next_attempt}", queued.id);
+                }
+                !report
+                    || self.respond(Response::SendMailFailed {
+                        error,
+                        next_attempt: queued.next_attempt(),
+                    })
+            }
+        };
+        Ok(listening && self.publish()?)
+    }
+
+    /// How long until the next retry is due.
+    fn next_timeout(&self) -> Result<Option<Duration>, crate::Error> {
+        let next_attempt = self
+            .outbox
+            .list()?
+            .iter()
+            .filter(|queued| !self.held.contains(&queued.id))
+            .filter_map(|queued| queued.next_attempt)
+            .min();
+        Ok(next_attempt.map(|next_attempt| {
+            Duration::from_secs(next_attempt.saturating_sub(outbox::now()).max(0) as u64)
+        }))
+    }
+
+    /// Retry every message which is due.
+    fn retry_due(&mut self) -> Result<bool, crate::Error> {
+        let now = outbox::now();
+        for queued in self.outbox.list()? {
+            if queued.is_due(now)
+                && !self.held.contains(&queued.id)
+                && !self.attempt(queued, false)?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Serve the main thread's commands until it closes the channel, retrying queued messages
+/// in between.
+pub fn run<T>(
+    transport: T,
+    outbox: Outbox,
     rx: Receiver<Command>,
-    tx: Sender<Response>,
-) -> Result<(), crate::Error> {
-    let mut client = Client::new(config)?;
+    tx: mpsc::Sender<Response>,
+) -> Result<(), crate::Error>
+where
+    T: MailTransport,
+{
+    let mut sender = Sender {
+        transport,
+        outbox,
+        tx,
+        held: HashSet::new(),
+    };
+    if let Err(err) = serve(&mut sender, rx) {
+        // Without a working outbox there's nowhere to keep messages
+        tracing::error!("Outbox failed with error: {err}");
+        sender.respond(Response::Error(err));
+    }
+    Ok(())
+}
+
+fn serve<T>(sender: &mut Sender<T>, rx: Receiver<Command>) -> Result<(), crate::Error>
+where
+    T: MailTransport,
+{
+    if !sender.publish()? {
+        return Ok(());
+    }
 
     loop {
-        match rx.recv() {
+        let command = match sender.next_timeout()? {
+            Some(timeout) => rx.recv_timeout(timeout),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        // It's ok to just break and return when the main thread stops listening,
+        // it means the channel was closed and we're exiting
+        let listening = match command {
             Ok(Command::SendMail(message)) => {
-                match client.send(message) {
-                    Ok(_) => {
-                        if let Err(err) = tx.send(Response::SendMailSuccess) {
-                            tracing::error!(
-                                "Failed to send success message to main thread with error: {err}"
-                            );
-                            // It's ok to just break and return here because it means the main thread has closed the channel
-                            break;
-                        };
-                    }
-                    Err(err) => {
-                        tracing::error!("Failed to send email with error: {err}");
-                        if let Err(err) = tx.send(Response::Error(err)) {
-                            tracing::error!(
-                                "Failed to send error message to main thread with error: {err}"
-                            );
-                            // It's ok to just break and return here because it means the main thread has closed the channel
-                            break;
-                        }
-                    }
-                };
-            }
-            Err(err) => {
-                tracing::error!("Failed to receive message with error: {err}");
-                // It's ok to just break and return here because it means the main thread has closed the channel
-                break;
+                let queued = sender.outbox.push(message, None)?;
+                sender.attempt(queued, true)?
+            }
+            Ok(Command::Schedule { message, at }) => {
+                let queued = sender.outbox.push(message, Some(at.timestamp()))?;
+                tracing::info!("Scheduled message {} for {at}", queued.id);
+                sender.publish()?
+            }
+            Ok(Command::Reschedule { id, at }) => {
+                if let Some(mut queued) = sender.outbox.get(&id)? {
+                    queued.reschedule(at.timestamp());
+                    sender.outbox.save(&queued)?;
+                }
+                sender.publish()?
+            }
+            Ok(Command::Retry(id)) => match sender.outbox.get(&id)? {
+                Some(queued) => sender.attempt(queued, true)?,
+                None => sender.publish()?,
+            },
+            Ok(Command::Discard(id)) => {
+                sender.held.remove(&id);
+                sender.outbox.remove(&id)?;
+                sender.publish()?
+            }
+            Ok(Command::Hold(id)) => {
+                sender.held.insert(id);
+                true
+            }
+            Ok(Command::Release { id, message }) => {
+                sender.held.remove(&id);
+                if let (Some(mut queued), Some(message)) = (sender.outbox.get(&id)?, message) {
+                    queued.edit(message);
+                    sender.outbox.save(&queued)?;
+                }
+                sender.publish()?
+            }
+            #[cfg(feature = "refresher")]
+            Ok(Command::Login {
+                client_id,
+                access_token,
+                refresh_token,
+            }) => {
+                if let Err(err) =
+                    sender
+                        .transport
+                        .update_oauth_tokens(&client_id, access_token, refresh_token)
+                {
+                    tracing::error!("Failed to update the OAuth tokens with error: {err}");
+                }
+                true
             }
+            Err(RecvTimeoutError::Timeout) => sender.retry_due()?,
+            Err(RecvTimeoutError::Disconnected) => {
+                tracing::error!("Failed to receive message, the main thread closed the channel");
+                false
+            }
+        };
+        if !listening {
+            break;
         }
     }
     Ok(())
 }
 
+fn tls_parameters(config: &SmtpConfig) -> Result<TlsParameters, crate::Error> {
+    let tls = &config.tls;
+    let mut builder = TlsParameters::builder(config.host.clone())
+        .dangerous_accept_invalid_certs(tls.danger_accept_invalid_certs);
+    for certificate in tls.ca_certificates()? {
+        builder = builder.add_root_certificate(Certificate::from_der(certificate.to_vec())?);
+    }
+    // Validates the files before handing them over to lettre
+    if tls.client_identity()?.is_some() {
+        if let (Some(cert), Some(key)) = (&tls.client_cert, &tls.client_key) {
+            builder = builder.identify_with(Identity::from_pem(&fs::read(cert)?, &fs::read(key)?)?);
+        }
+    }
+    Ok(builder.build_rustls()?)
+}
+
+/// Mechanisms advertised on the `AUTH` line of an EHLO reply, or the legacy `AUTH=` one.
+fn advertised_mechanisms(ehlo: &lettre::transport::smtp::response::Response) -> Vec<String> {
+    ehlo.message()
+        .flat_map(|line| {
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default().to_ascii_uppercase();
+            let first = match keyword.strip_prefix("AUTH") {
+                Some("") => None,
+                Some(legacy) => match legacy.strip_prefix('=') {
+                    Some(first) => Some(first.to_string()),
+                    None => return vec![],
+                },
+                None => return vec![],
+            };
+            first
+                .into_iter()
+                .chain(words.map(str::to_ascii_uppercase))
+                .collect()
+        })
+        .collect()
+}
+
+/// The first configured mechanism the server supports, skipping those which don't fit `auth`.
+fn select_mechanism(config: &SmtpConfig, advertised: &[String]) -> Option<config::Mechanism> {
+    let candidates = if config.mechanisms.is_empty() {
+        config.auth.default_mechanisms()
+    } else {
+        &config.mechanisms
+    };
+    let oauth = matches!(config.auth, Auth::OAuth(_));
+    candidates.iter().copied().find(|mechanism| {
+        if mechanism.is_oauth() != oauth {
+            tracing::warn!(
+                "Ignoring {} since it doesn't fit the auth type",
+                mechanism.name()
+            );
+            return false;
+        }
+        advertised.iter().any(|name| name == mechanism.name())
+    })
+}
+
+/// [RFC 7628](https://www.rfc-editor.org/rfc/rfc7628) initial client response, lettre only
+/// knows about XOAUTH2.
+struct OAuthBearer<'a> {
+    config: &'a SmtpConfig,
+    token: &'a str,
+}
+
+impl std::fmt::Display for OAuthBearer<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The login is a SASL name in the GS2 header (RFC 5801), where ',' and '=' are escaped
+        let login = self.config.login.replace('=', "=3D").replace(',', "=2C");
+        let response = format!(
+            "n,a={login},\x01host={}\x01port={}\x01auth=Bearer {}\x01\x01",
+            self.config.host, self.config.port, self.token
+        );
+        write!(
+            f,
+            "AUTH OAUTHBEARER {}\r\n",
+            BASE64_STANDARD.encode(response)
+        )
+    }
+}
+
 pub struct Client {
     config: SmtpConfig,
-    transport: lettre::SmtpTransport,
 }
 
 impl Client {
     pub fn new(config: SmtpConfig) -> Result<Self, crate::Error> {
-        let credentials = match &config.auth {
-            config::Auth::Password(password_config) => {
-                Credentials::new(config.login.clone(), password_config.raw.clone())
-            }
-            config::Auth::OAuth(oauth_config) => Credentials::new(
-                config.login.clone(),
-                oauth_config.access_token.secret().clone(),
-            ),
+        // Catch broken certificate settings before the first message is sent
+        if config.tls.mode != TlsMode::None {
+            tls_parameters(&config)?;
+        }
+        Ok(Self { config })
+    }
+
+    /// Connect and greet the server, returning its EHLO reply.
+    fn hello(
+        &self,
+    ) -> Result<(SmtpConnection, lettre::transport::smtp::response::Response), crate::Error> {
+        let config = &self.config;
+        config.tls.warn_if_dangerous(&config.host);
+        let hello = HelloName::default();
+        let address = (config.host.as_str(), config.port);
+        let mut connection = match config.tls.mode {
+            TlsMode::Implicit => {
+                let tls = tls_parameters(config)?;
+                SmtpConnection::connect(address, Some(TIMEOUT), &hello, Some(&tls), None)?
+            }
+            TlsMode::StartTls => {
+                let mut connection =
+                    SmtpConnection::connect(address, Some(TIMEOUT), &hello, None, None)?;
+                if !connection.can_starttls() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Unsupported,
+                        format!("{} does not support STARTTLS", config.host),
+                    )
+                    .into());
+                }
+                connection.starttls(&tls_parameters(config)?, &hello)?;
+                connection
+            }
+            TlsMode::None => SmtpConnection::connect(address, Some(TIMEOUT), &hello, None, None)?,
+        };
+
+        // lettre keeps the EHLO reply to itself and drops the mechanisms it doesn't know
+        let ehlo = connection.command(Ehlo::new(hello))?;
+        Ok((connection, ehlo))
+    }
+
+    /// Connect and authenticate, everything comes from the config so refreshed tokens keep
+    /// the rest of the settings.
+    fn connect(&self) -> Result<(SmtpConnection, config::Mechanism), crate::Error> {
+        let config = &self.config;
+        let (mut connection, ehlo) = self.hello()?;
+        let advertised = advertised_mechanisms(&ehlo);
+        let Some(mechanism) = select_mechanism(config, &advertised) else {
+            connection.abort();
+            return Err(crate::Error::SmtpMechanism(advertised.join(" ")));
+        };
+        tracing::debug!("Authenticating with {}", mechanism.name());
+
+        let secret = match &config.auth {
+            Auth::Password(password_config) => password_config.raw.clone(),
+            Auth::OAuth(oauth_config) => oauth_config.access_token.secret().clone(),
+        };
+        let credentials = Credentials::new(config.login.clone(), secret.clone());
+        match mechanism {
+            config::Mechanism::Plain => connection.auth(&[Mechanism::Plain], &credentials)?,
+            config::Mechanism::Login => connection.auth(&[Mechanism::Login], &credentials)?,
+            config::Mechanism::Xoauth2 => connection.auth(&[Mechanism::Xoauth2], &credentials)?,
+            config::Mechanism::OAuthBearer => {
+                let response = connection.command(OAuthBearer {
+                    config,
+                    token: &secret,
+                })?;
+                // On failure, the server sends the details as a challenge and waits for a
+                // dummy response before replying with the actual error
+                if response.has_code(334) {
+                    connection.command("AQ==\r\n")?
+                } else {
+                    response
+                }
+            }
         };
+        Ok((connection, mechanism))
+    }
+
+    fn try_send(&self, envelope: &Envelope, raw: &[u8]) -> Result<(), crate::Error> {
+        let (mut connection, _) = self.connect()?;
+        connection.send(envelope, raw)?;
+        let _ = connection.quit();
+        Ok(())
+    }
 
-        let transport = SmtpTransport::relay(&config.host)?
-            .port(config.port)
-            .authentication(vec![Mechanism::Plain])
-            .credentials(credentials)
-            .build();
+    /// Send a formatted message, refreshing the OAuth access token once if it was rejected.
+    pub fn send_raw(&mut self, envelope: &Envelope, raw: &[u8]) -> Result<(), crate::Error> {
+        self.with_refresh(|client| client.try_send(envelope, raw))
+    }
 
-        Ok(Self { config, transport })
+    /// Extensions the server advertises before authenticating, one per line of its EHLO reply.
+    pub fn extensions(&self) -> Result<Vec<String>, crate::Error> {
+        let (mut connection, ehlo) = self.hello()?;
+        let _ = connection.quit();
+        // The first line is the server greeting us by name
+        Ok(ehlo.message().skip(1).map(str::to_string).collect())
     }
 
-    pub fn send(&mut self, message: PartialMessage) -> Result<(), crate::Error> {
-        let message = message.into_message(self.config.login.parse::<Address>()?)?;
+    /// Authenticate without sending anything, returning the mechanism which was used.
+    pub fn login(&mut self) -> Result<config::Mechanism, crate::Error> {
+        self.with_refresh(|client| {
+            let (mut connection, mechanism) = client.connect()?;
+            let _ = connection.quit();
+            Ok(mechanism)
+        })
+    }
 
-        let Err(err) = self.transport.send(&message) else {
-            return Ok(());
+    /// Run `attempt` again after refreshing the OAuth access token, if it was rejected.
+    fn with_refresh<R>(
+        &mut self,
+        attempt: impl Fn(&Self) -> Result<R, crate::Error>,
+    ) -> Result<R, crate::Error> {
+        let err = match attempt(self) {
+            Ok(result) => return Ok(result),
+            Err(crate::Error::Smtp(err)) => err,
+            Err(err) => return Err(err),
         };
 
         let Some(Code {
@@ -148,8 +554,7 @@ impl Client {
                 tracing::debug!("Trying to refresh OAuth token");
                 self.refresh_oauth_access_token()?;
                 tracing::debug!("Successfully refreshed OAuth token");
-                self.transport.send(&message)?;
-                Ok(())
+                attempt(self)
             }
         }
     }
@@ -172,15 +577,374 @@ impl Client {
             .request(&http_client)?;
         config.access_token = access_token.access_token().to_owned();
 
-        let updated_transport = SmtpTransport::relay(&self.config.host)?
-            .authentication(vec![Mechanism::Xoauth2])
-            .credentials(Credentials::new(
-                self.config.login.clone(),
-                config.access_token.secret().clone(),
-            ))
-            .build();
-        let _ = std::mem::replace(&mut self.transport, updated_transport);
+        Ok(())
+    }
+}
+
+impl MailTransport for Client {
+    fn send(&mut self, message: PartialMessage) -> Result<Option<Vec<u8>>, crate::Error> {
+        let message = message.into_message(self.config.login.parse::<Address>()?, false)?;
+        let raw = message.formatted();
+        self.send_raw(message.envelope(), &raw)?;
+        Ok(self.config.save_sent.then_some(raw))
+    }
+
+    #[cfg(feature = "refresher")]
+    fn update_oauth_tokens(
+        &mut self,
+        client_id: &ClientId,
+        access_token: AccessToken,
+        refresh_token: RefreshToken,
+    ) -> Result<(), crate::Error> {
+        let Auth::OAuth(ref mut config) = self.config.auth else {
+            return Ok(());
+        };
+        if config.client_id != *client_id {
+            tracing::debug!("SMTP uses a different OAuth client, not updating tokens");
+            return Ok(());
+        }
+        config.access_token = access_token;
+        config.refresh_token = refresh_token;
 
+        *self = Self::new(self.config.clone())?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc::{self, Receiver, RecvError, Sender},
+        },
+        thread::JoinHandle,
+    };
+
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use serde_json::json;
+
+    use super::{
+        advertised_mechanisms, config::Mechanism, select_mechanism, Client, Command, MailTransport,
+        OAuthBearer, PartialMessage, Response,
+    };
+    use crate::smtp::{
+        config::SmtpConfig,
+        outbox::{Outbox, QueuedMessage},
+    };
+
+    fn config(port: u16, auth: serde_json::Value, mechanisms: serde_json::Value) -> SmtpConfig {
+        serde_json::from_value(json!({
+            "host": "127.0.0.1",
+            "port": port,
+            "login": "jose@example.com",
+            "auth": auth,
+            "mechanisms": mechanisms,
+            "tls": "none",
+        }))
+        .unwrap()
+    }
+
+    fn password() -> serde_json::Value {
+        json!({ "type": "password", "raw": "super-secret" })
+    }
+
+    fn oauth() -> serde_json::Value {
+        json!({
+            "type": "oauth",
+            "client_id": "client-id",
+            "client_secret": "client-secret",
+            "auth_url": "https://localhost",
+            "token_url": "https://localhost",
+            "access_token": "access-token",
+            "refresh_token": "refresh-token",
+        })
+    }
+
+    #[test]
+    fn mechanisms_follow_the_server() {
+        let ehlo = "250-smtp.example.com\r\n250-AUTH LOGIN oauthbearer\r\n250 8BITMIME\r\n"
+            .parse()
+            .unwrap();
+        let advertised = advertised_mechanisms(&ehlo);
+        assert_eq!(advertised, ["LOGIN", "OAUTHBEARER"]);
+        let legacy = "250-smtp.example.com\r\n250-AUTHX FOO\r\n250 AUTH=PLAIN login\r\n"
+            .parse()
+            .unwrap();
+        assert_eq!(advertised_mechanisms(&legacy), ["PLAIN", "LOGIN"]);
+
+        let defaults = config(25, password(), json!([]));
+        assert_eq!(
+            select_mechanism(&defaults, &advertised),
+            Some(Mechanism::Login)
+        );
+        let oauth_defaults = config(25, oauth(), json!([]));
+        assert_eq!(
+            select_mechanism(&oauth_defaults, &advertised),
+            Some(Mechanism::OAuthBearer)
+        );
+        // Explicit mechanisms are kept to, even if the server supports others
+        let plain = config(25, password(), json!(["plain"]));
+        assert_eq!(select_mechanism(&plain, &advertised), None);
+        // Mechanisms which don't fit the auth type are skipped
+        let mismatched = config(25, password(), json!(["xoauth2", "login"]));
+        assert_eq!(
+            select_mechanism(&mismatched, &advertised),
+            Some(Mechanism::Login)
+        );
+    }
+
+    #[test]
+    fn oauthbearer_response() {
+        let config = config(587, oauth(), json!([]));
+        let command = OAuthBearer {
+            config: &config,
+            token: "access-token",
+        }
+        .to_string();
+        let encoded = command
+            .strip_prefix("AUTH OAUTHBEARER ")
+            .and_then(|rest| rest.strip_suffix("\r\n"))
+            .unwrap();
+        assert_eq!(
+            BASE64_STANDARD.decode(encoded).unwrap(),
+            b"n,a=jose@example.com,\x01host=127.0.0.1\x01port=587\x01auth=Bearer access-token\x01\x01"
+        );
+
+        let mut config = config;
+        config.login = "jose,home=1@example.com".to_string();
+        let command = OAuthBearer {
+            config: &config,
+            token: "access-token",
+        }
+        .to_string();
+        let encoded = command
+            .strip_prefix("AUTH OAUTHBEARER ")
+            .and_then(|rest| rest.strip_suffix("\r\n"))
+            .unwrap();
+        assert!(BASE64_STANDARD
+            .decode(encoded)
+            .unwrap()
+            .starts_with(b"n,a=jose=2Chome=3D1@example.com,\x01"));
+    }
+
+    #[test]
+    fn client_negotiates_login() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let user = format!("{}\r\n", BASE64_STANDARD.encode("jose@example.com"));
+        let pass = format!("{}\r\n", BASE64_STANDARD.encode("super-secret"));
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut read_line = || {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                line
+            };
+            stream.write_all(b"220 smtp.example.com ready\r\n").unwrap();
+            let ehlo = "250-smtp.example.com\r\n250 AUTH LOGIN XOAUTH2\r\n";
+            let script = [
+                ("EHLO", ehlo),
+                ("EHLO", ehlo),
+                ("AUTH LOGIN\r\n", "334 VXNlcm5hbWU6\r\n"),
+                (user.as_str(), "334 UGFzc3dvcmQ6\r\n"),
+                (pass.as_str(), "235 Authenticated\r\n"),
+                ("MAIL FROM:<jose@example.com>\r\n", "250 OK\r\n"),
+                ("RCPT TO:<duarte@example.com>\r\n", "250 OK\r\n"),
+                ("DATA\r\n", "354 Go ahead\r\n"),
+            ];
+            for (command, reply) in script {
+                assert!(read_line().starts_with(command));
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+            let mut data = String::new();
+            while !data.ends_with("\r\n.\r\n") {
+                data.push_str(&read_line());
+            }
+            stream.write_all(b"250 Queued\r\n").unwrap();
+            assert_eq!(read_line(), "QUIT\r\n");
+            stream.write_all(b"221 Bye\r\n").unwrap();
+            data
+        });
+
+        let mut client = Client::new(config(port, password(), json!([]))).unwrap();
+        client
+            .send(PartialMessage {
+                body: Some("Hello there".to_string()),
+                ..message("Hi")
+            })
+            .unwrap();
+        let data = server.join().unwrap();
+        assert!(data.contains("Subject: Hi\r\n"));
+        assert!(data.contains("Hello there"));
+    }
+
+    /// Fails the first time, like a server which is briefly down.
+    struct FlakyTransport {
+        failed: bool,
+    }
+
+    impl MailTransport for FlakyTransport {
+        fn send(&mut self, _: PartialMessage) -> Result<Option<Vec<u8>>, crate::Error> {
+            if !self.failed {
+                self.failed = true;
+                return Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into());
+            }
+            Ok(Some(b"sent".to_vec()))
+        }
+    }
+
+    /// Channels to and from the sending thread, the thread itself and its outbox directory.
+    type Spawned = (
+        Sender<Command>,
+        Receiver<Response>,
+        JoinHandle<Result<(), crate::Error>>,
+        PathBuf,
+    );
+
+    /// The sending thread over a fresh outbox, past the outbox it starts with.
+    fn spawn(failed: bool) -> Spawned {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "ectt-queue-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let (to_smtp, rx) = mpsc::channel();
+        let (tx, from_smtp) = mpsc::channel();
+        let outbox = Outbox::new(dir.clone());
+        let thread =
+            std::thread::spawn(move || super::run(FlakyTransport { failed }, outbox, rx, tx));
+        assert!(outbox_of(from_smtp.recv()).is_empty());
+        (to_smtp, from_smtp, thread, dir)
+    }
+
+    fn outbox_of(response: Result<Response, RecvError>) -> Vec<QueuedMessage> {
+        match response {
+            Ok(Response::Outbox(messages)) => messages,
+            _ => panic!("expected the outbox"),
+        }
+    }
+
+    fn message(subject: &str) -> PartialMessage {
+        PartialMessage {
+            to: Some("duarte@example.com".parse().unwrap()),
+            cc: vec![],
+            bcc: vec![],
+            subject: Some(subject.to_string()),
+            body: None,
+            attachments: vec![],
+        }
+    }
+
+    #[test]
+    fn failed_messages_stay_queued() {
+        let (to_smtp, from_smtp, thread, dir) = spawn(false);
+
+        to_smtp.send(Command::SendMail(message("Hi"))).unwrap();
+        assert!(matches!(
+            from_smtp.recv(),
+            Ok(Response::SendMailFailed {
+                next_attempt: Some(_),
+                ..
+            })
+        ));
+        let queued = outbox_of(from_smtp.recv());
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].attempts, 1);
+
+        to_smtp.send(Command::Retry(queued[0].id.clone())).unwrap();
+        assert!(matches!(
+            from_smtp.recv(),
+            Ok(Response::SendMailSuccess { sent: Some(sent) }) if sent == b"sent"
+        ));
+        assert!(outbox_of(from_smtp.recv()).is_empty());
+
+        drop(to_smtp);
+        thread.join().unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn scheduled_messages_are_sent_when_due() {
+        let (to_smtp, from_smtp, thread, dir) = spawn(true);
+
+        let now = chrono::Local::now();
+        to_smtp
+            .send(Command::Schedule {
+                message: message("Later"),
+                at: now + chrono::Duration::hours(1),
+            })
+            .unwrap();
+        let queued = outbox_of(from_smtp.recv());
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].attempts, 0);
+
+        to_smtp
+            .send(Command::Reschedule {
+                id: queued[0].id.clone(),
+                at: now,
+            })
+            .unwrap();
+        assert_eq!(
+            outbox_of(from_smtp.recv())[0].scheduled,
+            Some(now.timestamp())
+        );
+        assert!(matches!(
+            from_smtp.recv(),
+            Ok(Response::SendMailSuccess { .. })
+        ));
+        assert!(outbox_of(from_smtp.recv()).is_empty());
+
+        drop(to_smtp);
+        thread.join().unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn held_messages_wait_for_their_edits() {
+        let (to_smtp, from_smtp, thread, dir) = spawn(true);
+
+        let now = chrono::Local::now();
+        to_smtp
+            .send(Command::Schedule {
+                message: message("Later"),
+                at: now + chrono::Duration::hours(1),
+            })
+            .unwrap();
+        let id = outbox_of(from_smtp.recv())[0].id.clone();
+
+        // Due, but not sent while it's being edited
+        to_smtp.send(Command::Hold(id.clone())).unwrap();
+        to_smtp
+            .send(Command::Reschedule {
+                id: id.clone(),
+                at: now,
+            })
+            .unwrap();
+        assert_eq!(outbox_of(from_smtp.recv())[0].attempts, 0);
+
+        to_smtp
+            .send(Command::Release {
+                id,
+                message: Some(message("Edited")),
+            })
+            .unwrap();
+        let queued = outbox_of(from_smtp.recv());
+        assert_eq!(queued[0].subject.as_deref(), Some("Edited"));
+        assert_eq!(queued[0].scheduled, Some(now.timestamp()));
+        assert!(matches!(
+            from_smtp.recv(),
+            Ok(Response::SendMailSuccess { .. })
+        ));
+        assert!(outbox_of(from_smtp.recv()).is_empty());
+
+        drop(to_smtp);
+        thread.join().unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Local};
+use lettre::Address;
+
+use crate::{config::ectt_data_dir, smtp::PartialMessage};
+
+/// Wait before the first retry, doubled on every failed attempt.
+const FIRST_RETRY: Duration = Duration::from_secs(30);
+/// Longest wait between retries.
+const MAX_RETRY: Duration = Duration::from_secs(60 * 60);
+
+/// A message waiting to be sent, saved as JSON so it survives restarts.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct QueuedMessage {
+    pub id: String,
+    pub to: Option<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+    #[serde(default)]
+    pub attachments: Vec<PathBuf>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// Unix timestamp for the next automatic attempt, `None` after a permanent failure.
+    pub next_attempt: Option<i64>,
+    /// Unix timestamp the message was scheduled for, `None` if it was sent right away.
+    #[serde(default)]
+    pub scheduled: Option<i64>,
+}
+
+impl QueuedMessage {
+    fn new(id: String, message: PartialMessage, scheduled: Option<i64>) -> Self {
+        let mut queued = Self {
+            id,
+            to: None,
+            cc: vec![],
+            bcc: vec![],
+            subject: None,
+            body: None,
+            attachments: vec![],
+            attempts: 0,
+            last_error: None,
+            next_attempt: Some(scheduled.unwrap_or_else(now)),
+            scheduled,
+        };
+        queued.edit(message);
+        queued
+    }
+
+    /// Replace the contents, when and how often it was tried stays the same.
+    pub fn edit(&mut self, message: PartialMessage) {
+        let addresses =
+            |addresses: Vec<Address>| addresses.iter().map(Address::to_string).collect();
+        self.to = message.to.as_ref().map(Address::to_string);
+        self.cc = addresses(message.cc);
+        self.bcc = addresses(message.bcc);
+        self.subject = message.subject;
+        self.body = message.body;
+        self.attachments = message.attachments;
+    }
+
+    pub fn message(&self) -> Result<PartialMessage, crate::Error> {
+        let addresses = |addresses: &[String]| {
+            addresses
+                .iter()
+                .map(|address| address.parse())
+                .collect::<Result<Vec<Address>, _>>()
+        };
+        Ok(PartialMessage {
+            to: self.to.as_deref().map(str::parse).transpose()?,
+            cc: addresses(&self.cc)?,
+            bcc: addresses(&self.bcc)?,
+            subject: self.subject.clone(),
+            body: self.body.clone(),
+            attachments: self.attachments.clone(),
+        })
+    }
+
+    pub fn is_due(&self, now: i64) -> bool {
+        self.next_attempt
+            .is_some_and(|next_attempt| next_attempt <= now)
+    }
+
+    pub fn next_attempt(&self) -> Option<DateTime<Local>> {
+        self.next_attempt.and_then(local)
+    }
+
+    pub fn scheduled(&self) -> Option<DateTime<Local>> {
+        self.scheduled.and_then(local)
+    }
+
+    /// Move the message to a new time, it's no longer retried before that.
+    pub fn reschedule(&mut self, at: i64) {
+        self.scheduled = Some(at);
+        self.next_attempt = Some(at);
+    }
+
+    /// Record a failed attempt, scheduling another one if it makes sense.
+    pub fn failed(&mut self, err: &crate::Error) {
+        self.attempts += 1;
+        self.last_error = Some(err.to_string());
+        self.next_attempt = err
+            .is_transient()
+            .then(|| now() + retry_delay(self.attempts).as_secs() as i64);
+    }
+}
+
+/// Exponential backoff, `attempts` is the number of failed attempts so far.
+pub fn retry_delay(attempts: u32) -> Duration {
+    FIRST_RETRY
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .min(MAX_RETRY)
+}
+
+fn local(timestamp: i64) -> Option<DateTime<Local>> {
+    DateTime::from_timestamp(timestamp, 0).map(|date| date.with_timezone(&Local))
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Directory of queued messages, one file each.
+pub struct Outbox {
+    dir: PathBuf,
+}
+
+impl Outbox {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn default_dir() -> PathBuf {
+        ectt_data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("outbox")
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    /// Queued messages, oldest first.
+    pub fn list(&self) -> Result<Vec<QueuedMessage>, crate::Error> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let mut messages = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            match serde_json::from_slice(&fs::read(&path)?) {
+                Ok(message) => messages.push(message),
+                Err(err) => tracing::error!("Ignoring {} with error: {err}", path.display()),
+            }
+        }
+        messages.sort_by(|a: &QueuedMessage, b| a.id.cmp(&b.id));
+        Ok(messages)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<QueuedMessage>, crate::Error> {
+        match fs::read(self.path(id)) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Queue a message, to be sent right away or at the `scheduled` Unix timestamp.
+    pub fn push(
+        &self,
+        message: PartialMessage,
+        scheduled: Option<i64>,
+    ) -> Result<QueuedMessage, crate::Error> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let queued = QueuedMessage::new(
+            format!("{timestamp}.{}", std::process::id()),
+            message,
+            scheduled,
+        );
+        self.save(&queued)?;
+        Ok(queued)
+    }
+
+    pub fn save(&self, message: &QueuedMessage) -> Result<(), crate::Error> {
+        fs::create_dir_all(&self.dir)?;
+        // Write to the side first, a crash halfway through shouldn't lose the message
+        let path = self.path(&message.id);
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_vec_pretty(message)?)?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), crate::Error> {
+        match fs::remove_file(self.path(id)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::{retry_delay, Outbox};
+    use crate::smtp::PartialMessage;
+
+    #[test]
+    fn retries_back_off() {
+        assert_eq!(retry_delay(1), Duration::from_secs(30));
+        assert_eq!(retry_delay(2), Duration::from_secs(60));
+        assert_eq!(retry_delay(4), Duration::from_secs(240));
+        assert_eq!(retry_delay(20), Duration::from_secs(3600));
+        assert_eq!(retry_delay(u32::MAX), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn messages_are_kept() {
+        let dir = std::env::temp_dir().join(format!("ectt-outbox-{}", std::process::id()));
+        let outbox = Outbox::new(dir.clone());
+        assert!(outbox.list().unwrap().is_empty());
+
+        let mut queued = outbox
+            .push(
+                PartialMessage {
+                    to: Some("duarte@example.com".parse().unwrap()),
+                    cc: vec!["cc@example.com".parse().unwrap()],
+                    bcc: vec![],
+                    subject: Some("Hi".to_string()),
+                    body: Some("Hello there".to_string()),
+                    attachments: vec!["report.pdf".into()],
+                },
+                None,
+            )
+            .unwrap();
+        assert!(queued.is_due(super::now()));
+
+        let err = crate::Error::Io(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+        queued.failed(&err);
+        outbox.save(&queued).unwrap();
+        let listed = outbox.list().unwrap();
+        assert_eq!(listed, [queued.clone()]);
+        assert_eq!(listed[0].attempts, 1);
+        assert!(!listed[0].is_due(super::now()));
+
+        let err = crate::Error::Pop3("unused".to_string());
+        queued.failed(&err);
+        assert_eq!(queued.next_attempt, None);
+
+        let message = queued.message().unwrap();
+        assert_eq!(message.to.unwrap().to_string(), "duarte@example.com");
+        assert_eq!(message.cc.len(), 1);
+        assert_eq!(message.attachments, [PathBuf::from("report.pdf")]);
+
+        outbox.remove(&queued.id).unwrap();
+        assert!(outbox.get(&queued.id).unwrap().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn scheduled_messages_wait() {
+        let dir = std::env::temp_dir().join(format!("ectt-scheduled-{}", std::process::id()));
+        let outbox = Outbox::new(dir.clone());
+        let message = PartialMessage {
+            to: Some("duarte@example.com".parse().unwrap()),
+            cc: vec![],
+            bcc: vec![],
+            subject: None,
+            body: None,
+            attachments: vec![],
+        };
+        let now = super::now();
+        let mut queued = outbox.push(message, Some(now + 3600)).unwrap();
+        assert!(!queued.is_due(now));
+        assert_eq!(outbox.get(&queued.id).unwrap(), Some(queued.clone()));
+
+        queued.reschedule(now - 1);
+        assert!(queued.is_due(now));
+        assert_eq!(queued.scheduled, Some(now - 1));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
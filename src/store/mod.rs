@@ -0,0 +1,532 @@
+//! Backend agnostic access to the mail, the TUI only ever talks to a [`MailStore`] through [`run`].
+
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, Sender};
+
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use mail_parser::MessageParser;
+#[cfg(feature = "refresher")]
+use oauth2::{AccessToken, RefreshToken};
+
+#[cfg(feature = "refresher")]
+use crate::imap::config::OAuthConfig;
+
+pub const INBOX: &str = "INBOX";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum Flag {
+    Seen,
+    Answered,
+    Flagged,
+    Deleted,
+    Draft,
+}
+
+/// Special uses as defined by [RFC 6154](https://www.rfc-editor.org/rfc/rfc6154).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialUse {
+    All,
+    Archive,
+    Drafts,
+    Flagged,
+    Junk,
+    Sent,
+    Trash,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mailbox {
+    pub name: String,
+    pub special_use: Option<SpecialUse>,
+}
+
+/// Name of the mailbox marked with `special_use`.
+pub fn special_use_mailbox(
+    mailboxes: &[Mailbox],
+    special_use: SpecialUse,
+) -> Result<&str, crate::Error> {
+    mailboxes
+        .iter()
+        .find(|mailbox| mailbox.special_use == Some(special_use))
+        .map(|mailbox| mailbox.name.as_str())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No mailbox is marked as {special_use:?}"),
+            )
+            .into()
+        })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ParsedEmail {
+    pub uid: u32,
+    pub date: DateTime<Utc>,
+    pub from: String,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub subject: String,
+    /// Empty if only the headers were fetched.
+    pub body: String,
+    pub flags: Vec<Flag>,
+}
+
+impl ParsedEmail {
+    /// Parse a raw RFC 5322 message (or just its headers).
+    ///
+    /// `date` should be the date the backend received the message at, if it knows it,
+    /// otherwise the `Date` header is used.
+    pub fn parse(
+        uid: u32,
+        raw: &[u8],
+        date: Option<DateTime<Utc>>,
+        flags: Vec<Flag>,
+    ) -> Option<Self> {
+        let parsed = MessageParser::new().parse(raw)?;
+
+        let date = match date {
+            Some(date) => date,
+            None => match parsed.date() {
+                Some(parsed_date) => {
+                    DateTime::parse_from_rfc3339(parsed_date.to_rfc3339().as_str())
+                        .expect("one of the libraries messed up RFC3339")
+                        .to_utc()
+                }
+                None => {
+                    tracing::warn!("No date was found, defaulting to UNIX_EPOCH");
+                    DateTime::<Utc>::UNIX_EPOCH
+                }
+            },
+        };
+
+        Some(ParsedEmail {
+            uid,
+            date,
+            from: Self::get_from(&parsed),
+            cc: Self::get_cc(&parsed),
+            bcc: Self::get_bcc(&parsed),
+            subject: parsed.subject().unwrap_or("No subject").to_string(),
+            body: (0..parsed.text_body_count())
+                .map(|idx| parsed.body_text(idx).unwrap_or_default().to_string())
+                .join(""),
+            flags,
+        })
+    }
+
+    pub fn is_seen(&self) -> bool {
+        self.flags.contains(&Flag::Seen)
+    }
+
+    pub fn get_from(parsed: &mail_parser::Message) -> String {
+        let from = match parsed.from() {
+            Some(from) => from,
+            None => return "No sender".to_string(),
+        };
+
+        let sender = match from.first() {
+            Some(sender) => sender,
+            None => return "No sender".to_string(),
+        };
+
+        match (&sender.name, &sender.address) {
+            (None, None) => "Unknown sender".to_string(),
+            (None, Some(address)) => address.to_string(),
+            (Some(name), None) => name.to_string(),
+            (Some(name), Some(address)) => format!("{name} ({address})"),
+        }
+    }
+
+    pub fn get_cc(parsed: &mail_parser::Message) -> Vec<String> {
+        let cc = match parsed.cc() {
+            Some(cc) => cc,
+            None => return vec![],
+        };
+
+        // I could parse the groups manually and probably get slightly better perf here but this works
+        cc.clone()
+            .into_list()
+            .iter()
+            .map(|addr| match (&addr.name, &addr.address) {
+                (None, None) => "Unknown CC".to_string(),
+                (None, Some(address)) => address.to_string(),
+                (Some(name), None) => name.to_string(),
+                (Some(name), Some(address)) => format!("{name} ({address})"),
+            })
+            .collect::<Vec<_>>()
+    }
+
+    pub fn get_bcc(parsed: &mail_parser::Message) -> Vec<String> {
+        let bcc = match parsed.bcc() {
+            Some(bcc) => bcc,
+            None => return vec![],
+        };
+
+        // I could parse the groups manually and probably get slightly better perf here but this works
+        bcc.clone()
+            .into_list()
+            .iter()
+            .map(|addr| match (&addr.name, &addr.address) {
+                (None, None) => "Unknown BCC".to_string(),
+                (None, Some(address)) => address.to_string(),
+                (Some(name), None) => name.to_string(),
+                (Some(name), Some(address)) => format!("{name} ({address})"),
+            })
+            .collect::<Vec<_>>()
+    }
+}
+
+/// Hands out stable `u32` UIDs for backends which identify messages some other way.
+#[derive(Debug, Default)]
+pub struct IdMap {
+    ids: HashMap<String, u32>,
+    keys: Vec<String>,
+}
+
+impl IdMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the UID for `key`, assigning a new one if it was never seen before.
+    pub fn id(&mut self, key: &str) -> u32 {
+        if let Some(id) = self.ids.get(key) {
+            return *id;
+        }
+        self.keys.push(key.to_string());
+        // Starting at 1 like IMAP does, 0 is a suspicious UID
+        let id = self.keys.len() as u32;
+        self.ids.insert(key.to_string(), id);
+        id
+    }
+
+    pub fn key(&self, id: u32) -> Option<&str> {
+        self.keys
+            .get((id as usize).checked_sub(1)?)
+            .map(String::as_str)
+    }
+}
+
+/// A place where mail is read from.
+///
+/// Messages are identified by their mailbox and a `uid`, backends without a notion of UIDs
+/// must keep them stable for as long as the store lives.
+pub trait MailStore {
+    fn list_mailboxes(&mut self) -> Result<Vec<Mailbox>, crate::Error>;
+
+    /// List messages from newest to oldest, skipping the first `offset`.
+    ///
+    /// Only the headers are required to be loaded, use [`MailStore::fetch_message`] for the rest.
+    fn list_messages(
+        &mut self,
+        mailbox: &str,
+        count: u32,
+        offset: u32,
+    ) -> Result<Vec<ParsedEmail>, crate::Error>;
+
+    /// Fetch the full RFC 5322 message.
+    fn fetch_message(&mut self, mailbox: &str, uid: u32) -> Result<Vec<u8>, crate::Error>;
+
+    /// Add (or remove, if `enable` is false) the given flags.
+    fn set_flags(
+        &mut self,
+        mailbox: &str,
+        uid: u32,
+        flags: &[Flag],
+        enable: bool,
+    ) -> Result<(), crate::Error>;
+
+    fn move_message(
+        &mut self,
+        mailbox: &str,
+        uid: u32,
+        destination: &str,
+    ) -> Result<(), crate::Error>;
+
+    /// Store a message in the mailbox with the given special use, e.g. a copy of a sent one.
+    fn append(
+        &mut self,
+        special_use: SpecialUse,
+        _raw: &[u8],
+        _flags: &[Flag],
+    ) -> Result<(), crate::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("This store can't keep messages in {special_use:?}"),
+        ))?
+    }
+
+    /// Keep a copy of draft `id` in the Drafts mailbox, replacing the previous one.
+    fn save_draft(&mut self, _id: &str, _raw: &[u8]) -> Result<(), crate::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "This store doesn't sync drafts",
+        ))?
+    }
+
+    /// Drop the copy of draft `id`, once it's sent or discarded.
+    fn remove_draft(&mut self, _id: &str) -> Result<(), crate::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "This store doesn't sync drafts",
+        ))?
+    }
+}
+
+pub enum Command {
+    ListMailboxes,
+    ListMessages {
+        mailbox: String,
+        count: u32,
+        offset: u32,
+    },
+    FetchMessage {
+        mailbox: String,
+        uid: u32,
+    },
+    SetFlags {
+        mailbox: String,
+        uid: u32,
+        flags: Vec<Flag>,
+        enable: bool,
+    },
+    Move {
+        mailbox: String,
+        uid: u32,
+        destination: String,
+    },
+    Append {
+        special_use: SpecialUse,
+        raw: Vec<u8>,
+        flags: Vec<Flag>,
+    },
+    SaveDraft {
+        id: String,
+        raw: Vec<u8>,
+    },
+    RemoveDraft(String),
+    /// New OAuth tokens, obtained after a [`Response::LoginRequired`].
+    #[cfg(feature = "refresher")]
+    Login {
+        access_token: AccessToken,
+        refresh_token: RefreshToken,
+    },
+}
+
+pub enum Response {
+    Mailboxes(Vec<Mailbox>),
+    Messages {
+        mailbox: String,
+        emails: Vec<ParsedEmail>,
+    },
+    Message {
+        mailbox: String,
+        uid: u32,
+        raw: Vec<u8>,
+    },
+    /// The refresh token is no longer valid, the user needs to go through the OAuth flow again.
+    #[cfg(feature = "refresher")]
+    LoginRequired(OAuthConfig),
+    /// An operation failed, the store is still usable.
+    Failed(crate::Error),
+    Error(crate::Error),
+}
+
+/// Report failures of an operation not every store supports, the unsupported ones are fine.
+fn optional(result: Result<(), crate::Error>) -> Option<Response> {
+    match result {
+        Ok(()) => None,
+        Err(crate::Error::Io(err)) if err.kind() == std::io::ErrorKind::Unsupported => {
+            tracing::warn!("Skipping store operation: {err}");
+            None
+        }
+        Err(err) => Some(Response::Failed(err)),
+    }
+}
+
+/// Serve the main thread's commands until it closes the channel.
+#[tracing::instrument(skip_all)]
+pub fn run<S>(mut store: S, rx: Receiver<Command>, tx: Sender<Response>) -> Result<(), crate::Error>
+where
+    S: MailStore,
+{
+    loop {
+        let message = match rx.recv() {
+            Ok(message) => message,
+            Err(err) => {
+                tracing::error!("Error while receiving a message from main thread: {err}");
+                // It's ok to just break and return here because it means the main thread has closed the channel
+                break;
+            }
+        };
+
+        let response = match message {
+            Command::ListMailboxes => match store.list_mailboxes() {
+                Ok(mailboxes) => Some(Response::Mailboxes(mailboxes)),
+                Err(err) => Some(Response::Failed(err)),
+            },
+            Command::ListMessages {
+                mailbox,
+                count,
+                offset,
+            } => match store.list_messages(&mailbox, count, offset) {
+                Ok(emails) => Some(Response::Messages { mailbox, emails }),
+                // e.g. a mailbox which is gone or we can't read, the others may still work
+                Err(err) => Some(Response::Failed(err)),
+            },
+            Command::FetchMessage { mailbox, uid } => match store.fetch_message(&mailbox, uid) {
+                Ok(raw) => Some(Response::Message { mailbox, uid, raw }),
+                Err(err) => Some(Response::Failed(err)),
+            },
+            Command::SetFlags {
+                mailbox,
+                uid,
+                flags,
+                enable,
+            } => store
+                .set_flags(&mailbox, uid, &flags, enable)
+                .err()
+                .map(Response::Failed),
+            Command::Move {
+                mailbox,
+                uid,
+                destination,
+            } => store
+                .move_message(&mailbox, uid, &destination)
+                .err()
+                .map(Response::Failed),
+            Command::Append {
+                special_use,
+                raw,
+                flags,
+            } => optional(store.append(special_use, &raw, &flags)),
+            Command::SaveDraft { id, raw } => optional(store.save_draft(&id, &raw)),
+            Command::RemoveDraft(id) => optional(store.remove_draft(&id)),
+            #[cfg(feature = "refresher")]
+            Command::Login { .. } => {
+                tracing::debug!("Already authenticated, ignoring login");
+                None
+            }
+        };
+
+        if let Some(response) = response {
+            if let Err(err) = tx.send(response) {
+                tracing::error!("Failed to send response to main thread with error: {err}");
+                // It's ok to just break and return here because it means the main thread has closed the channel
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::channel;
+
+    use super::{run, Command, Flag, MailStore, Mailbox, ParsedEmail, Response, INBOX};
+
+    /// In-memory store, the worker shouldn't care about where the mail comes from.
+    struct Fixture {
+        emails: Vec<ParsedEmail>,
+    }
+
+    impl MailStore for Fixture {
+        fn list_mailboxes(&mut self) -> Result<Vec<Mailbox>, crate::Error> {
+            Ok(vec![Mailbox {
+                name: INBOX.to_string(),
+                special_use: None,
+            }])
+        }
+
+        fn list_messages(
+            &mut self,
+            mailbox: &str,
+            count: u32,
+            offset: u32,
+        ) -> Result<Vec<ParsedEmail>, crate::Error> {
+            if mailbox != INBOX {
+                Err(std::io::Error::other("no such mailbox"))?
+            }
+            Ok(self
+                .emails
+                .iter()
+                .rev()
+                .skip(offset as usize)
+                .take(count as usize)
+                .cloned()
+                .collect())
+        }
+
+        fn fetch_message(&mut self, _: &str, _: u32) -> Result<Vec<u8>, crate::Error> {
+            Err(std::io::Error::other("not found"))?
+        }
+
+        fn set_flags(
+            &mut self,
+            _: &str,
+            uid: u32,
+            flags: &[Flag],
+            _: bool,
+        ) -> Result<(), crate::Error> {
+            let email = self.emails.iter_mut().find(|e| e.uid == uid).unwrap();
+            email.flags.extend_from_slice(flags);
+            Ok(())
+        }
+
+        fn move_message(&mut self, _: &str, _: u32, _: &str) -> Result<(), crate::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn run_serves_any_store() {
+        let raw = b"From: Jose <jose@example.com>\r\nSubject: Hello\r\nDate: Sat, 20 Nov 2021 14:22:01 -0800\r\n\r\nHi!\r\n";
+        let emails = (1..=3)
+            .map(|uid| ParsedEmail::parse(uid, raw, None, vec![]).unwrap())
+            .collect();
+
+        let (main_tx, store_rx) = channel();
+        let (store_tx, main_rx) = channel();
+        let handle = std::thread::spawn(move || run(Fixture { emails }, store_rx, store_tx));
+
+        main_tx
+            .send(Command::ListMessages {
+                mailbox: INBOX.to_string(),
+                count: 2,
+                offset: 0,
+            })
+            .unwrap();
+        let Response::Messages { mailbox, emails } = main_rx.recv().unwrap() else {
+            panic!("wrong response");
+        };
+        assert_eq!(mailbox, INBOX);
+        assert_eq!(emails.iter().map(|e| e.uid).collect::<Vec<_>>(), [3, 2]);
+        assert_eq!(emails[0].from, "Jose (jose@example.com)");
+        assert_eq!(emails[0].subject, "Hello");
+
+        main_tx
+            .send(Command::FetchMessage {
+                mailbox: INBOX.to_string(),
+                uid: 1,
+            })
+            .unwrap();
+        assert!(matches!(main_rx.recv().unwrap(), Response::Failed(_)));
+
+        main_tx
+            .send(Command::ListMessages {
+                mailbox: "Gone".to_string(),
+                count: 2,
+                offset: 0,
+            })
+            .unwrap();
+        assert!(
+            matches!(main_rx.recv().unwrap(), Response::Failed(_)),
+            "a mailbox failing doesn't stop the store"
+        );
+
+        drop(main_tx);
+        handle.join().unwrap().unwrap();
+    }
+}
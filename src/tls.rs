@@ -0,0 +1,245 @@
+//! TLS settings shared by the IMAP, POP3 and SMTP backends.
+
+use std::{io, net::TcpStream, path::PathBuf, sync::Arc};
+
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    StreamOwned,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsMode {
+    /// TLS from the first byte, usually on ports 993 (IMAP), 995 (POP3) and 465 (SMTP).
+    #[default]
+    Implicit,
+    /// Upgrade a plain connection with `STARTTLS` (`STLS` for POP3), usually on ports 143 (IMAP),
+    /// 110 (POP3) and 587 (SMTP).
+    StartTls,
+    /// No encryption at all, only meant for local servers.
+    None,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct TlsConfig {
+    #[serde(rename = "tls", default)]
+    pub mode: TlsMode,
+    /// PEM bundle with extra certificate authorities to trust, for self-signed servers.
+    #[serde(default)]
+    pub ca_file: Option<PathBuf>,
+    /// PEM certificate chain presented to servers requiring client certificates.
+    #[serde(default)]
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key for `client_cert`.
+    #[serde(default)]
+    pub client_key: Option<PathBuf>,
+    /// Skip verifying the server's certificate, anyone on the network can read the connection.
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+}
+
+impl TlsConfig {
+    /// Whether the settings need more than the system's default certificate verification.
+    pub fn has_custom_certs(&self) -> bool {
+        self.ca_file.is_some() || self.client_cert.is_some() || self.client_key.is_some()
+    }
+
+    /// Why connecting to `host` this way is unsafe, when the server's certificate (and so its
+    /// name) won't be checked.
+    pub fn danger(&self, host: &str) -> Option<String> {
+        (self.danger_accept_invalid_certs && self.mode != TlsMode::None).then(|| {
+            format!(
+                "Certificate verification for {host} is DISABLED (danger_accept_invalid_certs), \
+                 the connection is not protected against impersonation"
+            )
+        })
+    }
+
+    /// Log a warning when the server's certificate will not be checked, on every connection.
+    pub fn warn_if_dangerous(&self, host: &str) {
+        if let Some(danger) = self.danger(host) {
+            tracing::warn!("{danger}");
+        }
+    }
+
+    pub fn ca_certificates(&self) -> Result<Vec<CertificateDer<'static>>, crate::Error> {
+        match &self.ca_file {
+            Some(path) => read_certificates(path),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// The client certificate chain and its key, if both are set.
+    pub fn client_identity(
+        &self,
+    ) -> Result<Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>, crate::Error> {
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => Ok(Some((
+                read_certificates(cert)?,
+                PrivateKeyDer::from_pem_file(key).map_err(|err| pem_error(key, err))?,
+            ))),
+            (None, None) => Ok(None),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "client_cert and client_key must be set together",
+            ))?,
+        }
+    }
+
+    pub fn client_config(&self) -> Result<Arc<ClientConfig>, crate::Error> {
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        for certificate in self.ca_certificates()? {
+            roots.add(certificate)?;
+        }
+        let roots = Arc::new(roots);
+
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots.clone());
+        let mut config = match self.client_identity()? {
+            Some((chain, key)) => builder.with_client_auth_cert(chain, key)?,
+            None => builder.with_no_client_auth(),
+        };
+
+        if self.danger_accept_invalid_certs {
+            let verifier = WebPkiServerVerifier::builder_with_provider(roots, provider.clone())
+                .build()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(AcceptInvalidCerts { verifier }));
+        }
+        Ok(Arc::new(config))
+    }
+
+    /// Start a TLS session for `host` over an established connection.
+    pub fn connect(
+        &self,
+        host: &str,
+        stream: TcpStream,
+    ) -> Result<StreamOwned<ClientConnection, TcpStream>, crate::Error> {
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let connection = ClientConnection::new(self.client_config()?, server_name)?;
+        Ok(StreamOwned::new(connection, stream))
+    }
+}
+
+fn pem_error(path: &std::path::Path, err: rustls::pki_types::pem::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Failed to read {}: {err}", path.display()),
+    )
+}
+
+fn read_certificates(path: &std::path::Path) -> Result<Vec<CertificateDer<'static>>, crate::Error> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| pem_error(path, err))?;
+    if certificates.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No certificates found in {}", path.display()),
+        )
+        .into());
+    }
+    Ok(certificates)
+}
+
+/// Accepts any server certificate, the handshake signatures are still checked.
+#[derive(Debug)]
+struct AcceptInvalidCerts {
+    verifier: Arc<WebPkiServerVerifier>,
+}
+
+impl ServerCertVerifier for AcceptInvalidCerts {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.verifier.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{TlsConfig, TlsMode};
+
+    #[test]
+    fn ensure_tls_format() {
+        let json = json!({
+            "tls": "starttls",
+            "ca_file": "/etc/ssl/local-ca.pem",
+            "danger_accept_invalid_certs": true,
+        });
+        let config = serde_json::from_value::<TlsConfig>(json).unwrap();
+        assert_eq!(config.mode, TlsMode::StartTls);
+        assert_eq!(
+            config.ca_file.unwrap().to_str(),
+            Some("/etc/ssl/local-ca.pem")
+        );
+        assert_eq!(config.client_cert, None);
+        assert!(config.danger_accept_invalid_certs);
+
+        let config = serde_json::from_value::<TlsConfig>(json!({})).unwrap();
+        assert_eq!(config.mode, TlsMode::Implicit);
+        assert!(!config.has_custom_certs());
+        assert!(!config.danger_accept_invalid_certs);
+    }
+
+    #[test]
+    fn client_cert_needs_a_key() {
+        let config = TlsConfig {
+            client_cert: Some("/etc/ssl/client.pem".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.client_config(),
+            Err(crate::Error::Io(err)) if err.kind() == std::io::ErrorKind::InvalidInput
+        ));
+    }
+
+    #[test]
+    fn missing_ca_file_is_reported() {
+        let config = TlsConfig {
+            ca_file: Some(std::env::temp_dir().join("ectt-missing-ca.pem")),
+            ..Default::default()
+        };
+        assert!(config.client_config().is_err());
+    }
+}
@@ -1,10 +1,13 @@
 use ratatui::{
-    style::{Color, Style, Stylize},
+    style::Style,
     widgets::{Block, Borders, Widget},
 };
 use tui_textarea::TextArea;
 
-use crate::tui::focus::FocusStyle;
+use crate::tui::{
+    focus::{focused_block, unfocused_block, FocusStyle},
+    theme::Theme,
+};
 
 pub struct BodyWidget<'w> {
     textarea: TextArea<'w>,
@@ -56,17 +59,17 @@ impl<'w> AsMut<TextArea<'w>> for BodyWidget<'w> {
 }
 
 impl<'w> FocusStyle for BodyWidget<'w> {
-    fn unfocused(&mut self) {
+    fn unfocused(&mut self, theme: &Theme) {
         let Some(block) = self.textarea.block() else {
             return;
         };
-        self.textarea.set_block(block.clone().fg(Color::default()));
+        self.textarea.set_block(unfocused_block(block, theme));
     }
 
-    fn focused(&mut self) {
+    fn focused(&mut self, theme: &Theme) {
         let Some(block) = self.textarea.block() else {
             return;
         };
-        self.textarea.set_block(block.clone().fg(Color::Blue));
+        self.textarea.set_block(focused_block(block, theme));
     }
 }
@@ -1,49 +1,152 @@
-use std::fmt::{Display, Write};
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
 
-use crossterm::event::{KeyCode, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use itertools::Itertools;
+use serde::{de, Deserialize, Deserializer};
 
-pub struct KeyCombo {
-    codes: Vec<KeyCode>,
+/// A single key press, with the modifiers held down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Stroke {
+    code: KeyCode,
     modifiers: KeyModifiers,
 }
 
-impl KeyCombo {
-    pub fn new() -> Self {
-        Self {
-            codes: vec![],
-            modifiers: KeyModifiers::empty(),
-        }
+impl Stroke {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Shift is already in the character (`G`) or the key (`BackTab`), terminals don't agree
+        // on whether to report it too
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers.difference(KeyModifiers::SHIFT),
+            _ => modifiers,
+        };
+        Self { code, modifiers }
     }
+}
 
-    pub fn with_code(mut self, code: KeyCode) -> Self {
-        self.codes.push(code);
-        self
+impl From<KeyEvent> for Stroke {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
     }
+}
+
+impl FromStr for Stroke {
+    type Err = String;
+
+    /// `ctrl+s`, `alt+<`, `shift+tab`, `G`, `enter`...
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut key = s;
+        while let Some((modifier, rest)) = key.split_once('+').filter(|(_, rest)| !rest.is_empty())
+        {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier `{modifier}` in `{s}`")),
+            };
+            key = rest;
+        }
 
-    pub fn with_modifier(mut self, modifier: KeyModifiers) -> Self {
-        self.modifiers = self.modifiers.union(modifier);
-        self
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "space" => KeyCode::Char(' '),
+                name => match name.strip_prefix('f').map(str::parse::<u8>) {
+                    Some(Ok(n @ 1..=12)) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key `{key}` in `{s}`")),
+                },
+            },
+        };
+        Ok(Self::new(code, modifiers))
     }
 }
 
-impl Display for KeyCombo {
+impl Display for Stroke {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("[")?;
-        f.write_str(&self.modifiers.to_string())?;
-        let mut first = true;
-        for code in &self.codes {
-            if first {
-                if !self.modifiers.is_empty() {
-                    f.write_char('+')?;
-                }
-                first = false;
-                f.write_str(&code.to_string())?;
-                continue;
+        // Not crossterm's names, which differ between platforms
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl"),
+            (KeyModifiers::ALT, "Alt"),
+            (KeyModifiers::SHIFT, "Shift"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{name}+")?;
             }
-            f.write_str("+")?;
-            f.write_str(&code.to_string())?;
         }
-        f.write_str("]")?;
-        Ok(())
+        match self.code {
+            KeyCode::BackTab => f.write_str("Shift+Tab"),
+            KeyCode::Backspace => f.write_str("Backspace"),
+            KeyCode::Delete => f.write_str("Del"),
+            KeyCode::Enter => f.write_str("Enter"),
+            code => write!(f, "{code}"),
+        }
+    }
+}
+
+/// Keys pressed one after the other to trigger an action, usually just one (`Ctrl+S`) but
+/// sequences like vim's `gg` or emacs' `Ctrl+X Ctrl+C` work too.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    strokes: Vec<Stroke>,
+}
+
+impl KeyCombo {
+    pub fn strokes(&self) -> &[Stroke] {
+        &self.strokes
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    /// Strokes separated by spaces, e.g. `ctrl+x ctrl+c` or `g g`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let strokes = s
+            .split_whitespace()
+            .map(Stroke::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if strokes.is_empty() {
+            return Err("empty key".to_string());
+        }
+        Ok(Self { strokes })
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyCombo {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Display for KeyCombo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_char('[')?;
+        f.write_str(&self.strokes.iter().join(" "))?;
+        f.write_char(']')
     }
 }
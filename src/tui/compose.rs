@@ -1,6 +1,7 @@
-use std::str::FromStr;
+use std::{io, path::PathBuf, str::FromStr};
 
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use chrono::{DateTime, Local};
+use crossterm::event::KeyEvent;
 use lettre::{address::AddressError, Address};
 use ratatui::{
     layout::{Constraint, Direction, Layout},
@@ -8,26 +9,38 @@ use ratatui::{
 };
 
 use crate::{
-    smtp::PartialMessage,
+    drafts::{self, Draft},
+    smtp::{
+        outbox::{self, QueuedMessage},
+        PartialMessage,
+    },
     tui::{
-        body::BodyWidget,
-        combo::KeyCombo,
-        focus::FocusStyle,
-        help::{HasHelp, HelpWidget},
-        line::LineWidget,
+        body::BodyWidget, focus::FocusStyle, keymap::KeyAction, line::LineWidget, theme::Theme,
         Action, Page,
     },
 };
 
 pub struct ComposeWidget<'w> {
-    focused: usize, // 0: to, 1: cc, 2: bcc, 3: body
+    focused: usize, // 0: to, 1: cc, 2: bcc, 3: subject, 4: attachments, 5: body
 
     to: LineWidget<'w>,
     cc: LineWidget<'w>,
     bcc: LineWidget<'w>,
     subject: LineWidget<'w>,
+    attachments: LineWidget<'w>,
     body: BodyWidget<'w>,
-    help: HelpWidget<'w>,
+    theme: Theme,
+
+    /// The outbox message being edited, it's discarded once this one is sent.
+    replaces: Option<String>,
+    /// When the outbox message was scheduled for, it's kept when sending.
+    scheduled: Option<DateTime<Local>>,
+    /// Id of the draft this is saved as.
+    draft: String,
+    /// Edited since the draft was last saved locally.
+    unsaved: bool,
+    /// Edited since the draft was last synced to the store.
+    unsynced: bool,
 }
 
 impl<'w> Default for ComposeWidget<'w> {
@@ -37,34 +50,19 @@ impl<'w> Default for ComposeWidget<'w> {
             cc: LineWidget::new("Cc"),
             bcc: LineWidget::new("Bcc"),
             subject: LineWidget::new("Subject"),
+            attachments: LineWidget::new("Attachments"),
             body: BodyWidget::new(),
-            help: Self::help(),
             focused: Default::default(),
+            theme: Theme::default(),
+            replaces: None,
+            scheduled: None,
+            draft: drafts::new_id(),
+            unsaved: false,
+            unsynced: false,
         }
     }
 }
 
-impl<'w> HasHelp for ComposeWidget<'w> {
-    fn help<'h>() -> super::help::HelpWidget<'h> {
-        HelpWidget::new(vec![
-            (
-                KeyCombo::new()
-                    .with_code(KeyCode::Char('S'))
-                    .with_modifier(KeyModifiers::CONTROL),
-                "Send",
-            ),
-            (KeyCombo::new().with_code(KeyCode::Tab), "Next"),
-            (
-                KeyCombo::new()
-                    .with_code(KeyCode::Tab)
-                    .with_modifier(KeyModifiers::SHIFT),
-                "Prev",
-            ),
-            (KeyCombo::new().with_code(KeyCode::Esc), "Cancel"),
-        ])
-    }
-}
-
 fn parse_addresses(s: &str) -> Result<Vec<Address>, AddressError> {
     if s.is_empty() {
         return Ok(vec![]);
@@ -75,7 +73,135 @@ fn parse_addresses(s: &str) -> Result<Vec<Address>, AddressError> {
         .collect::<Result<Vec<Address>, AddressError>>()
 }
 
+fn parse_paths(s: &str) -> Vec<PathBuf> {
+    s.split(",")
+        .map(&str::trim)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn join_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl<'w> From<&QueuedMessage> for ComposeWidget<'w> {
+    fn from(queued: &QueuedMessage) -> Self {
+        let line = |contents: Option<String>| contents.into_iter().collect();
+        Self {
+            to: LineWidget::with_contents("To", line(queued.to.clone())),
+            cc: LineWidget::with_contents("Cc", vec![queued.cc.join(", ")]),
+            bcc: LineWidget::with_contents("Bcc", vec![queued.bcc.join(", ")]),
+            subject: LineWidget::with_contents("Subject", line(queued.subject.clone())),
+            attachments: LineWidget::with_contents(
+                "Attachments",
+                vec![join_paths(&queued.attachments)],
+            ),
+            body: BodyWidget::with_contents(
+                queued
+                    .body
+                    .as_deref()
+                    .unwrap_or_default()
+                    .lines()
+                    .map(ToString::to_string)
+                    .collect(),
+            ),
+            focused: Default::default(),
+            theme: Theme::default(),
+            replaces: Some(queued.id.clone()),
+            scheduled: queued.scheduled(),
+            draft: drafts::new_id(),
+            unsaved: false,
+            unsynced: false,
+        }
+    }
+}
+
+impl<'w> From<&Draft> for ComposeWidget<'w> {
+    fn from(draft: &Draft) -> Self {
+        Self {
+            to: LineWidget::with_contents("To", vec![draft.to.clone()]),
+            cc: LineWidget::with_contents("Cc", vec![draft.cc.clone()]),
+            bcc: LineWidget::with_contents("Bcc", vec![draft.bcc.clone()]),
+            subject: LineWidget::with_contents("Subject", vec![draft.subject.clone()]),
+            attachments: LineWidget::with_contents(
+                "Attachments",
+                vec![join_paths(&draft.attachments)],
+            ),
+            body: BodyWidget::with_contents(draft.body.lines().map(ToString::to_string).collect()),
+            focused: Default::default(),
+            theme: Theme::default(),
+            replaces: None,
+            scheduled: None,
+            draft: draft.id.clone(),
+            unsaved: false,
+            unsynced: false,
+        }
+    }
+}
+
 impl<'w> ComposeWidget<'w> {
+    /// A new message, with the signature below the place to start typing.
+    pub fn with_signature(signature: Option<&str>) -> Self {
+        let Some(signature) = signature.filter(|signature| !signature.is_empty()) else {
+            return Self::default();
+        };
+        let body = ["", "-- "]
+            .into_iter()
+            .chain(signature.lines())
+            .map(ToString::to_string)
+            .collect();
+        Self {
+            body: BodyWidget::with_contents(body),
+            ..Self::default()
+        }
+    }
+
+    pub fn replaces(&self) -> Option<&str> {
+        self.replaces.as_deref()
+    }
+
+    /// When it should go out if it's sent as is, `None` for right away.
+    pub fn scheduled(&self) -> Option<DateTime<Local>> {
+        self.scheduled.filter(|at| *at > Local::now())
+    }
+
+    /// What's been typed so far, as it should be saved.
+    pub fn draft(&self) -> Draft {
+        let line = |widget: &LineWidget| widget.as_ref().lines().join("");
+        Draft {
+            id: self.draft.clone(),
+            to: line(&self.to),
+            cc: line(&self.cc),
+            bcc: line(&self.bcc),
+            subject: line(&self.subject),
+            body: self.body.as_ref().lines().join("\n"),
+            attachments: parse_paths(&line(&self.attachments)),
+            saved: outbox::now(),
+        }
+    }
+
+    pub fn draft_id(&self) -> &str {
+        &self.draft
+    }
+
+    pub fn needs_saving(&self) -> bool {
+        self.unsaved
+    }
+
+    pub fn needs_syncing(&self) -> bool {
+        self.unsynced
+    }
+
+    pub fn mark_saved(&mut self, synced: bool) {
+        self.unsaved = false;
+        self.unsynced &= !synced;
+    }
+
     pub fn get_partial_message(&self) -> Result<PartialMessage, crate::Error> {
         let to = self
             .to
@@ -105,7 +231,15 @@ impl<'w> ComposeWidget<'w> {
 
         let subject = self.subject.as_ref().lines().first().cloned();
 
-        let body = self.body.as_ref().lines().first().cloned();
+        let attachments = parse_paths(&self.attachments.as_ref().lines().join(""));
+        if let Some(missing) = attachments.iter().find(|path| !path.is_file()) {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No file to attach at {}", missing.display()),
+            ))?;
+        }
+
+        let body = Some(self.body.as_ref().lines().join("\n"));
 
         Ok(PartialMessage {
             to,
@@ -113,61 +247,61 @@ impl<'w> ComposeWidget<'w> {
             bcc,
             subject,
             body,
+            attachments,
         })
     }
 
-    pub fn handle_event(&mut self, event: Event) -> Action {
-        match event {
-            Event::Key(key_event) => self.handle_key_event(key_event),
-            _ => Action::Tick,
-        }
-    }
-
-    fn handle_key_event(
-        &mut self,
-        event @ KeyEvent {
-            code, modifiers, ..
-        }: KeyEvent,
-    ) -> Action {
-        match (code, modifiers) {
-            (crossterm::event::KeyCode::Esc, _) => Action::GoTo(Page::Inbox),
-            (crossterm::event::KeyCode::Tab, _) => {
-                self.focused = (self.focused + 1) % 5;
+    /// Handle a key press, `action` being what the keymap binds it to.
+    pub fn handle_key(&mut self, event: KeyEvent, action: Option<KeyAction>) -> Action {
+        match action {
+            Some(KeyAction::Back) => Action::GoTo(Page::Inbox),
+            Some(KeyAction::NextField) => {
+                self.focused = (self.focused + 1) % 6;
                 self.update_focused();
                 Action::Tick
             }
-            (crossterm::event::KeyCode::BackTab, _) => {
-                self.focused = (self.focused + 4) % 5;
+            Some(KeyAction::PreviousField) => {
+                self.focused = (self.focused + 5) % 6;
                 self.update_focused();
                 Action::Tick
             }
             _ => {
-                match self.focused {
+                let changed = match self.focused {
                     0 => self.to.input(event),
                     1 => self.cc.input(event),
                     2 => self.bcc.input(event),
                     3 => self.subject.input(event),
-                    4 => self.body.as_mut().input(event),
+                    4 => self.attachments.input(event),
+                    5 => self.body.as_mut().input(event),
                     _ => unreachable!(),
                 };
+                self.unsaved |= changed;
+                self.unsynced |= changed;
                 Action::Tick
             }
         }
     }
 
+    /// Style the fields with `theme`, the focused one stands out.
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = *theme;
+        self.update_focused();
+    }
+
     fn update_focused(&mut self) {
-        let parts: [&mut dyn FocusStyle; 5] = [
+        let parts: [&mut dyn FocusStyle; 6] = [
             &mut self.to,
             &mut self.cc,
             &mut self.bcc,
             &mut self.subject,
+            &mut self.attachments,
             &mut self.body,
         ];
         for (idx, focusable) in parts.into_iter().enumerate() {
             if idx == self.focused {
-                focusable.focused();
+                focusable.focused(&self.theme);
             } else {
-                focusable.unfocused();
+                focusable.unfocused(&self.theme);
             }
         }
     }
@@ -185,8 +319,8 @@ impl<'w> Widget for &ComposeWidget<'w> {
                 Constraint::Length(3),
                 Constraint::Length(3),
                 Constraint::Length(3),
+                Constraint::Length(3),
                 Constraint::Min(5),
-                Constraint::Length(1),
             ])
             .split(area);
 
@@ -194,7 +328,7 @@ impl<'w> Widget for &ComposeWidget<'w> {
         self.cc.render(chunks[1], buf);
         self.bcc.render(chunks[2], buf);
         self.subject.render(chunks[3], buf);
-        self.body.render(chunks[4], buf);
-        self.help.render(chunks[5], buf);
+        self.attachments.render(chunks[4], buf);
+        self.body.render(chunks[5], buf);
     }
 }
@@ -0,0 +1,107 @@
+use ratatui::{
+    layout::Constraint,
+    widgets::{Block, Borders, Cell, Row, StatefulWidget, Table, TableState},
+};
+
+use crate::{
+    drafts::Draft,
+    tui::{keymap::KeyAction, theme::Theme, Action, Page},
+};
+
+pub struct DraftsState {
+    pub drafts: Vec<Draft>,
+    pub table: TableState,
+}
+
+impl DraftsState {
+    pub fn new() -> Self {
+        Self {
+            drafts: vec![],
+            table: TableState::default().with_selected(0),
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Draft> {
+        self.table
+            .selected()
+            .and_then(|selected| self.drafts.get(selected))
+    }
+}
+
+pub struct DraftsWidget {
+    theme: Theme,
+}
+
+impl DraftsWidget {
+    pub fn new() -> Self {
+        Self {
+            theme: Theme::default(),
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = *theme;
+    }
+
+    pub fn handle_action(&mut self, action: KeyAction, state: &mut DraftsState) -> Action {
+        match action {
+            KeyAction::Back => Action::GoTo(Page::Inbox),
+            KeyAction::Down => {
+                state.table.select_next();
+                Action::Tick
+            }
+            KeyAction::Up => {
+                state.table.select_previous();
+                Action::Tick
+            }
+            KeyAction::Top => {
+                state.table.select_first();
+                Action::Tick
+            }
+            KeyAction::Bottom => {
+                state.table.select_last();
+                Action::Tick
+            }
+            _ => Action::Tick,
+        }
+    }
+}
+
+impl StatefulWidget for &mut DraftsWidget {
+    type State = DraftsState;
+
+    fn render(
+        self,
+        area: ratatui::prelude::Rect,
+        buf: &mut ratatui::prelude::Buffer,
+        state: &mut Self::State,
+    ) {
+        let header = Row::new([Cell::from("Saved"), Cell::from("To"), Cell::from("Subject")]);
+        let rows = state.drafts.iter().map(|draft| {
+            let saved = draft
+                .saved()
+                .map(|saved| saved.format("%Y-%m-%d %H:%M").to_string());
+            Row::new(vec![
+                Cell::from(saved.unwrap_or_default()),
+                Cell::from(draft.to.clone()),
+                Cell::from(draft.subject.clone()),
+            ])
+        });
+        let widths = [
+            Constraint::Length(16),
+            Constraint::Fill(2),
+            Constraint::Fill(3),
+        ];
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(self.theme.border)
+                    .title("Drafts"),
+            )
+            .row_highlight_style(self.theme.selected);
+
+        StatefulWidget::render(table, area, buf, &mut state.table);
+    }
+}
@@ -1,5 +1,30 @@
+use ratatui::{
+    style::{Style, Stylize},
+    widgets::{Block, BorderType},
+};
+
+use crate::tui::theme::Theme;
+
 pub trait FocusStyle {
-    fn focused(&mut self);
+    fn focused(&mut self, theme: &Theme);
+
+    fn unfocused(&mut self, theme: &Theme);
+}
+
+/// `block` as it's drawn around the field being typed in. Its bold title and thick border set it
+/// apart without relying on colors.
+pub fn focused_block<'b>(block: &Block<'b>, theme: &Theme) -> Block<'b> {
+    block
+        .clone()
+        .border_style(theme.focused)
+        .border_type(BorderType::Thick)
+        .title_style(Style::new().bold())
+}
 
-    fn unfocused(&mut self);
+pub fn unfocused_block<'b>(block: &Block<'b>, theme: &Theme) -> Block<'b> {
+    block
+        .clone()
+        .border_style(theme.border)
+        .border_type(BorderType::Plain)
+        .title_style(Style::new())
 }
@@ -1,41 +1,74 @@
+use std::fmt::Display;
+
 use itertools::Itertools;
 use ratatui::{
-    layout::Rect,
-    style::{Color, Style},
+    layout::{Constraint, Layout, Rect},
+    style::Style,
     widgets::{Paragraph, Widget},
 };
 
-use crate::tui::combo::KeyCombo;
+use crate::tui::{combo::KeyCombo, theme::Theme};
 
 pub struct HelpWidget<'w> {
     /// Vector of available keys and a string for their actions.
     // NOTE: There is definitely better ways of representing this
     actions: Vec<(KeyCombo, &'w str)>,
+    /// Shown on the right whatever the width, usually how to see every key.
+    more: Option<(KeyCombo, &'w str)>,
+    style: Style,
 }
 
 impl<'w> HelpWidget<'w> {
     pub fn new(actions: Vec<(KeyCombo, &'w str)>) -> Self {
-        Self { actions }
+        Self {
+            actions,
+            more: None,
+            style: Theme::default().help,
+        }
+    }
+
+    pub fn with_more(mut self, key: KeyCombo, action: &'w str) -> Self {
+        self.more = Some((key, action));
+        self
+    }
+
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
     }
 }
 
-impl<'w> Widget for &HelpWidget<'w> {
-    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer)
-    where
-        Self: Sized,
-    {
+impl Display for HelpWidget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let text = self
             .actions
             .iter()
             .map(|(combo, action)| format!("{combo}: {action}"))
             .join(" | ");
-
-        Paragraph::new(text)
-            .style(Style::default().fg(Color::White).bg(Color::DarkGray))
-            .render(area, buf);
+        f.write_str(&text)
     }
 }
 
-pub trait HasHelp {
-    fn help<'w>() -> HelpWidget<'w>;
+impl<'w> Widget for &HelpWidget<'w> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let more = self
+            .more
+            .as_ref()
+            .map(|(combo, action)| format!(" {combo}: {action}"))
+            .unwrap_or_default();
+        let [actions_area, more_area] = Layout::horizontal([
+            Constraint::Min(0),
+            Constraint::Length(more.chars().count() as u16),
+        ])
+        .areas(area);
+        Paragraph::new(self.to_string())
+            .style(self.style)
+            .render(actions_area, buf);
+        Paragraph::new(more)
+            .style(self.style)
+            .render(more_area, buf);
+    }
 }
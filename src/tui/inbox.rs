@@ -1,37 +1,57 @@
 use std::iter::empty;
 
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Style},
-    widgets::{Block, Borders, Cell, Row, StatefulWidget, Table, TableState, Widget},
+    layout::Constraint,
+    widgets::{Block, Borders, Cell, Row, StatefulWidget, Table, TableState},
 };
 
 use crate::{
-    imap::ParsedEmail,
-    tui::{
-        combo::KeyCombo,
-        help::{HasHelp, HelpWidget},
-        Action, Page,
-    },
+    store::{ParsedEmail, INBOX},
+    tui::{keymap::KeyAction, theme::Theme, Action, Page},
 };
 
 pub struct InboxState {
+    pub mailbox: String,
     pub inbox: Vec<ParsedEmail>,
     pub table: TableState,
+    /// What was last searched for, to find the next match.
+    pub query: Option<String>,
 }
 impl InboxState {
     pub fn new() -> Self {
         Self {
+            mailbox: INBOX.to_string(),
             inbox: vec![],
             table: TableState::default().with_selected(0),
+            query: None,
         }
     }
+
+    /// Select the first message after the selected one whose author or subject contains
+    /// `query`, ignoring case and wrapping around. Returns whether there was one.
+    ///
+    /// Only the messages loaded so far are searched.
+    pub fn find_next(&mut self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let start = self.table.selected().map_or(0, |selected| selected + 1);
+        let count = self.inbox.len();
+        let found = (0..count)
+            .map(|offset| (start + offset) % count)
+            .find(|&index| {
+                let email = &self.inbox[index];
+                email.from.to_lowercase().contains(&query)
+                    || email.subject.to_lowercase().contains(&query)
+            });
+        if found.is_some() {
+            self.table.select(found);
+        }
+        found.is_some()
+    }
 }
 
 pub struct InboxWidget<'w> {
     table: Table<'w>,
-    help: HelpWidget<'w>,
+    theme: Theme,
 }
 impl<'w> InboxWidget<'w> {
     pub fn new() -> Self {
@@ -47,68 +67,47 @@ impl<'w> InboxWidget<'w> {
         ];
         let table = Table::new(empty::<Row>(), widths)
             .header(header)
-            .block(Block::default().borders(Borders::ALL).title("Posts"))
-            .row_highlight_style(Style::default().bg(Color::Blue).fg(Color::White));
+            .block(Block::default().borders(Borders::ALL));
 
         Self {
             table,
-            help: Self::help(),
+            theme: Theme::default(),
         }
     }
 
-    pub fn handle_event(&mut self, event: Event, state: &mut InboxState) -> Action {
-        match event {
-            Event::Key(key_event) => self.handle_key_event(key_event, state),
-            _ => Action::Tick,
-        }
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = *theme;
     }
 
-    fn handle_key_event(
-        &mut self,
-        KeyEvent {
-            code, modifiers, ..
-        }: KeyEvent,
-        state: &mut InboxState,
-    ) -> Action {
-        match (code, modifiers) {
-            (crossterm::event::KeyCode::Char('w'), KeyModifiers::CONTROL) => Action::Quit,
-            (crossterm::event::KeyCode::Char('n'), KeyModifiers::CONTROL) => {
-                Action::GoTo(Page::Compose)
-            }
-            (crossterm::event::KeyCode::Down, _) => {
+    pub fn handle_action(&mut self, action: KeyAction, state: &mut InboxState) -> Action {
+        match action {
+            KeyAction::Quit => Action::Quit,
+            KeyAction::Compose => Action::GoTo(Page::Compose),
+            KeyAction::Mailboxes => Action::GoTo(Page::Mailboxes),
+            KeyAction::Outbox => Action::GoTo(Page::Outbox),
+            KeyAction::Drafts => Action::GoTo(Page::Drafts),
+            KeyAction::Scheduled => Action::GoTo(Page::Scheduled),
+            KeyAction::Down => {
                 state.table.select_next();
                 Action::Tick
             }
-            (crossterm::event::KeyCode::Up, _) => {
+            KeyAction::Up => {
                 state.table.select_previous();
                 Action::Tick
             }
+            KeyAction::Top => {
+                state.table.select_first();
+                Action::Tick
+            }
+            KeyAction::Bottom => {
+                state.table.select(state.inbox.len().checked_sub(1));
+                Action::Tick
+            }
             _ => Action::Tick,
         }
     }
 }
 
-impl<'w> HasHelp for InboxWidget<'w> {
-    fn help<'h>() -> HelpWidget<'h> {
-        HelpWidget::new(vec![
-            (KeyCombo::new().with_code(KeyCode::Enter), "Read email"),
-            (KeyCombo::new().with_code(KeyCode::Down), "Load more"),
-            (
-                KeyCombo::new()
-                    .with_code(KeyCode::Char('n'))
-                    .with_modifier(KeyModifiers::CONTROL),
-                "New email",
-            ),
-            (
-                KeyCombo::new()
-                    .with_code(KeyCode::Char('w'))
-                    .with_modifier(KeyModifiers::CONTROL),
-                "Quit",
-            ),
-        ])
-    }
-}
-
 impl StatefulWidget for &mut InboxWidget<'_> {
     type State = InboxState;
 
@@ -118,22 +117,29 @@ impl StatefulWidget for &mut InboxWidget<'_> {
         buf: &mut ratatui::prelude::Buffer,
         state: &mut Self::State,
     ) {
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Min(1), Constraint::Length(1)])
-            .split(area);
-
         let table = std::mem::take(&mut self.table);
-        let table = table.rows(state.inbox.iter().map(|parsed| {
-            Row::new(vec![
-                Cell::from(parsed.date.clone().to_string()),
-                Cell::from(parsed.from.clone()),
-                Cell::from(parsed.subject.clone()),
-            ])
-        }));
+        let table = table
+            .rows(state.inbox.iter().map(|parsed| {
+                let row = Row::new(vec![
+                    Cell::from(parsed.date.clone().to_string()),
+                    Cell::from(parsed.from.clone()),
+                    Cell::from(parsed.subject.clone()),
+                ]);
+                if parsed.is_seen() {
+                    row
+                } else {
+                    row.style(self.theme.unread)
+                }
+            }))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(self.theme.border)
+                    .title(state.mailbox.clone()),
+            )
+            .row_highlight_style(self.theme.selected);
         let _ = std::mem::replace(&mut self.table, table);
 
-        StatefulWidget::render(&self.table, chunks[0], buf, &mut state.table);
-        Widget::render(&self.help, chunks[1], buf);
+        StatefulWidget::render(&self.table, area, buf, &mut state.table);
     }
 }
@@ -0,0 +1,778 @@
+//! What the keys do on every screen: a preset, adjusted by the `keys` section of the configuration.
+
+use std::{collections::HashMap, fmt};
+
+use itertools::Itertools;
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::tui::{
+    combo::{KeyCombo, Stroke},
+    help::HelpWidget,
+};
+
+/// Everything a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyAction {
+    Quit,
+    Compose,
+    Mailboxes,
+    Outbox,
+    Drafts,
+    Scheduled,
+    Up,
+    Down,
+    Top,
+    Bottom,
+    /// Read, select, edit or resume, whatever the screen lists.
+    Open,
+    Back,
+    Search,
+    SearchNext,
+    Send,
+    SendLater,
+    NextField,
+    PreviousField,
+    Move,
+    Retry,
+    Reschedule,
+    Discard,
+    UndoSend,
+    /// Every key of the screen, in a scrollable overlay.
+    Help,
+    /// Run any action by name.
+    Palette,
+}
+
+impl KeyAction {
+    /// The snake case name it's configured with.
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyAction::Quit => "quit",
+            KeyAction::Compose => "compose",
+            KeyAction::Mailboxes => "mailboxes",
+            KeyAction::Outbox => "outbox",
+            KeyAction::Drafts => "drafts",
+            KeyAction::Scheduled => "scheduled",
+            KeyAction::Up => "up",
+            KeyAction::Down => "down",
+            KeyAction::Top => "top",
+            KeyAction::Bottom => "bottom",
+            KeyAction::Open => "open",
+            KeyAction::Back => "back",
+            KeyAction::Search => "search",
+            KeyAction::SearchNext => "search_next",
+            KeyAction::Send => "send",
+            KeyAction::SendLater => "send_later",
+            KeyAction::NextField => "next_field",
+            KeyAction::PreviousField => "previous_field",
+            KeyAction::Move => "move",
+            KeyAction::Retry => "retry",
+            KeyAction::Reschedule => "reschedule",
+            KeyAction::Discard => "discard",
+            KeyAction::UndoSend => "undo_send",
+            KeyAction::Help => "help",
+            KeyAction::Palette => "palette",
+        }
+    }
+
+    /// Moving around, obvious enough to be left out of the help line.
+    fn is_motion(self) -> bool {
+        matches!(
+            self,
+            KeyAction::Up
+                | KeyAction::Down
+                | KeyAction::Top
+                | KeyAction::Bottom
+                | KeyAction::SearchNext
+        )
+    }
+}
+
+/// Where keys are looked up, every screen has its own bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Context {
+    /// Looked up after the screen's own bindings, wherever we are.
+    Global,
+    Inbox,
+    Reading,
+    Compose,
+    Mailboxes,
+    Outbox,
+    Drafts,
+    Login,
+}
+
+impl Context {
+    const ALL: [Context; 8] = [
+        Context::Global,
+        Context::Inbox,
+        Context::Reading,
+        Context::Compose,
+        Context::Mailboxes,
+        Context::Outbox,
+        Context::Drafts,
+        Context::Login,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Context::Global => "global",
+            Context::Inbox => "inbox",
+            Context::Reading => "reading",
+            Context::Compose => "compose",
+            Context::Mailboxes => "mailboxes",
+            Context::Outbox => "outbox",
+            Context::Drafts => "drafts",
+            Context::Login => "login",
+        }
+    }
+
+    /// What can be done here, as the help describes it, in the order it's listed.
+    pub fn actions(self) -> &'static [(KeyAction, &'static str)] {
+        use KeyAction::*;
+        match self {
+            Context::Global => &[
+                (UndoSend, "Undo send"),
+                (Help, "All keys"),
+                (Palette, "Run a command"),
+            ],
+            Context::Inbox => &[
+                (Open, "Read email"),
+                (Down, "Next"),
+                (Up, "Previous"),
+                (Top, "First"),
+                (Bottom, "Last"),
+                (Search, "Search"),
+                (SearchNext, "Next match"),
+                (Compose, "New email"),
+                (Mailboxes, "Mailboxes"),
+                (Outbox, "Outbox"),
+                (Drafts, "Drafts"),
+                (Scheduled, "Scheduled"),
+                (Quit, "Quit"),
+                (Help, "All keys"),
+            ],
+            Context::Reading => &[
+                (NextField, "Next"),
+                (PreviousField, "Prev"),
+                (Down, "Down"),
+                (Up, "Up"),
+                (Top, "Top"),
+                (Bottom, "Bottom"),
+                (Move, "Move"),
+                (Back, "Cancel"),
+                (Help, "All keys"),
+            ],
+            Context::Compose => &[
+                (Send, "Send"),
+                (SendLater, "Send later"),
+                (NextField, "Next"),
+                (PreviousField, "Prev"),
+                (Back, "Save draft"),
+            ],
+            Context::Mailboxes => &[
+                (Open, "Select"),
+                (Down, "Next"),
+                (Up, "Previous"),
+                (Top, "First"),
+                (Bottom, "Last"),
+                (Back, "Cancel"),
+                (Help, "All keys"),
+            ],
+            Context::Outbox => &[
+                (Open, "Edit"),
+                (Retry, "Send now"),
+                (Reschedule, "Reschedule"),
+                (Discard, "Discard"),
+                (Down, "Next"),
+                (Up, "Previous"),
+                (Top, "First"),
+                (Bottom, "Last"),
+                (Back, "Back"),
+                (Help, "All keys"),
+            ],
+            Context::Drafts => &[
+                (Open, "Resume"),
+                (Discard, "Discard"),
+                (Down, "Next"),
+                (Up, "Previous"),
+                (Top, "First"),
+                (Bottom, "Last"),
+                (Back, "Back"),
+                (Help, "All keys"),
+            ],
+            Context::Login => &[(Retry, "Retry"), (Quit, "Exit"), (Help, "All keys")],
+        }
+    }
+}
+
+/// The bindings a keymap starts from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    /// Arrows and `Ctrl` shortcuts.
+    #[default]
+    Default,
+    /// `j`/`k` and `gg`/`G` on top of the defaults.
+    Vim,
+    /// `Ctrl+N`/`Ctrl+P`, `Alt+<`/`Alt+>`, `Ctrl+S` and `Ctrl+X` prefixes.
+    Emacs,
+}
+
+type Bindings = &'static [(Context, KeyAction, &'static [&'static str])];
+
+/// Screens showing something to move around in.
+const LISTS: [Context; 5] = [
+    Context::Inbox,
+    Context::Reading,
+    Context::Mailboxes,
+    Context::Outbox,
+    Context::Drafts,
+];
+
+const DEFAULT: Bindings = &[
+    (Context::Global, KeyAction::UndoSend, &["ctrl+z"]),
+    (Context::Global, KeyAction::Help, &["f1"]),
+    (Context::Global, KeyAction::Palette, &["ctrl+p"]),
+    (Context::Inbox, KeyAction::Open, &["enter"]),
+    (Context::Inbox, KeyAction::Search, &["/"]),
+    (Context::Inbox, KeyAction::SearchNext, &["n"]),
+    (Context::Inbox, KeyAction::Compose, &["ctrl+n"]),
+    (Context::Inbox, KeyAction::Mailboxes, &["ctrl+o"]),
+    (Context::Inbox, KeyAction::Outbox, &["ctrl+b"]),
+    (Context::Inbox, KeyAction::Drafts, &["ctrl+d"]),
+    (Context::Inbox, KeyAction::Scheduled, &["ctrl+l"]),
+    // Ctrl+W is often taken by the terminal or tmux, it's only kept for habit's sake
+    (Context::Inbox, KeyAction::Quit, &["ctrl+q", "ctrl+w"]),
+    (Context::Reading, KeyAction::NextField, &["tab"]),
+    (Context::Reading, KeyAction::PreviousField, &["shift+tab"]),
+    (Context::Reading, KeyAction::Move, &["m"]),
+    (Context::Reading, KeyAction::Back, &["esc"]),
+    (Context::Compose, KeyAction::Send, &["ctrl+s"]),
+    (Context::Compose, KeyAction::SendLater, &["ctrl+l"]),
+    (Context::Compose, KeyAction::NextField, &["tab"]),
+    (Context::Compose, KeyAction::PreviousField, &["shift+tab"]),
+    (Context::Compose, KeyAction::Back, &["esc"]),
+    (Context::Mailboxes, KeyAction::Open, &["enter"]),
+    (Context::Mailboxes, KeyAction::Back, &["esc"]),
+    (Context::Outbox, KeyAction::Open, &["enter"]),
+    (Context::Outbox, KeyAction::Retry, &["r"]),
+    (Context::Outbox, KeyAction::Reschedule, &["s"]),
+    (Context::Outbox, KeyAction::Discard, &["d"]),
+    (Context::Outbox, KeyAction::Back, &["esc"]),
+    (Context::Drafts, KeyAction::Open, &["enter"]),
+    (Context::Drafts, KeyAction::Discard, &["d"]),
+    (Context::Drafts, KeyAction::Back, &["esc"]),
+    (Context::Login, KeyAction::Retry, &["enter"]),
+    (Context::Login, KeyAction::Quit, &["esc"]),
+];
+
+/// `?` is typed when composing, so the other screens bind it on their own.
+const HELP_SCREENS: [Context; 6] = [
+    Context::Inbox,
+    Context::Reading,
+    Context::Mailboxes,
+    Context::Outbox,
+    Context::Drafts,
+    Context::Login,
+];
+
+/// Bound on every one of [`LISTS`], the reading screen moves the cursor instead.
+const DEFAULT_MOTIONS: &[(KeyAction, &[&str])] = &[
+    (KeyAction::Down, &["down"]),
+    (KeyAction::Up, &["up"]),
+    (KeyAction::Top, &["home"]),
+    (KeyAction::Bottom, &["end"]),
+];
+
+const VIM_MOTIONS: &[(KeyAction, &[&str])] = &[
+    (KeyAction::Down, &["j", "down"]),
+    (KeyAction::Up, &["k", "up"]),
+    (KeyAction::Top, &["g g", "home"]),
+    (KeyAction::Bottom, &["G", "end"]),
+];
+
+const EMACS_MOTIONS: &[(KeyAction, &[&str])] = &[
+    (KeyAction::Down, &["ctrl+n", "down"]),
+    (KeyAction::Up, &["ctrl+p", "up"]),
+    (KeyAction::Top, &["alt+<", "home"]),
+    (KeyAction::Bottom, &["alt+>", "end"]),
+];
+
+const EMACS: Bindings = &[
+    // Ctrl+P moves up, M-x is where emacs runs commands by name
+    (Context::Global, KeyAction::Palette, &["alt+x"]),
+    (Context::Inbox, KeyAction::Compose, &["ctrl+x m"]),
+    (Context::Inbox, KeyAction::Search, &["ctrl+s"]),
+    (Context::Inbox, KeyAction::Quit, &["ctrl+x ctrl+c"]),
+    (Context::Reading, KeyAction::Back, &["esc", "ctrl+g"]),
+    (Context::Compose, KeyAction::Send, &["ctrl+c ctrl+c"]),
+    (Context::Compose, KeyAction::SendLater, &["ctrl+c ctrl+j"]),
+    (Context::Compose, KeyAction::Back, &["esc", "ctrl+g"]),
+    (Context::Mailboxes, KeyAction::Back, &["esc", "ctrl+g"]),
+    (Context::Outbox, KeyAction::Back, &["esc", "ctrl+g"]),
+    (Context::Drafts, KeyAction::Back, &["esc", "ctrl+g"]),
+];
+
+/// What a key press amounts to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Resolved {
+    Action(KeyAction),
+    /// The start of a longer sequence, the next keys will tell.
+    Pending,
+    /// Left to the screen, e.g. typed into the message being composed.
+    Unbound,
+}
+
+/// An action as the help overlay and the command palette list it.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub action: KeyAction,
+    pub label: &'static str,
+    /// On the screen first, then the ones working everywhere.
+    pub keys: Vec<KeyCombo>,
+}
+
+/// The keys bound to every action, per screen.
+#[derive(Debug)]
+pub struct Keymap {
+    bindings: HashMap<Context, Vec<(KeyAction, Vec<KeyCombo>)>>,
+}
+
+impl Keymap {
+    pub fn new(preset: Preset) -> Self {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        let parse = |keys: &[&str]| {
+            keys.iter()
+                .map(|key| key.parse().expect("preset keys are valid"))
+                .collect::<Vec<KeyCombo>>()
+        };
+        let motions = match preset {
+            Preset::Default => DEFAULT_MOTIONS,
+            Preset::Vim => VIM_MOTIONS,
+            Preset::Emacs => EMACS_MOTIONS,
+        };
+        for &(context, action, keys) in DEFAULT {
+            keymap.bind(context, action, parse(keys));
+        }
+        for context in HELP_SCREENS {
+            keymap.bind(context, KeyAction::Help, parse(&["?"]));
+        }
+        for context in LISTS {
+            for &(action, keys) in motions {
+                keymap.bind(context, action, parse(keys));
+            }
+        }
+        if preset == Preset::Emacs {
+            for &(context, action, keys) in EMACS {
+                keymap.bind(context, action, parse(keys));
+            }
+        }
+        keymap
+    }
+
+    /// Replace the keys `action` is bound to on `context`'s screen.
+    fn bind(&mut self, context: Context, action: KeyAction, keys: Vec<KeyCombo>) {
+        let bindings = self.bindings.entry(context).or_default();
+        match bindings.iter_mut().find(|(bound, _)| *bound == action) {
+            Some((_, bound)) => *bound = keys,
+            None => bindings.push((action, keys)),
+        }
+    }
+
+    /// The keys bound to `action`, the first one is the one shown in the help.
+    pub fn keys(&self, context: Context, action: KeyAction) -> &[KeyCombo] {
+        self.bindings
+            .get(&context)
+            .and_then(|bindings| bindings.iter().find(|(bound, _)| *bound == action))
+            .map(|(_, keys)| keys.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn bound(&self, context: Context) -> impl Iterator<Item = (KeyAction, &KeyCombo)> {
+        self.bindings
+            .get(&context)
+            .into_iter()
+            .flatten()
+            .flat_map(|(action, keys)| keys.iter().map(move |key| (*action, key)))
+    }
+
+    /// Work out what `stroke` does on `context`'s screen, following up on the keys of an
+    /// unfinished sequence in `pending`.
+    pub fn resolve(&self, context: Context, pending: &mut Vec<Stroke>, stroke: Stroke) -> Resolved {
+        pending.push(stroke);
+        let mut partial = false;
+        for (action, key) in self.bound(context).chain(self.bound(Context::Global)) {
+            if key.strokes() == pending.as_slice() {
+                pending.clear();
+                return Resolved::Action(action);
+            }
+            partial |= key.strokes().starts_with(pending);
+        }
+        if partial {
+            return Resolved::Pending;
+        }
+        // A broken sequence is forgotten, but the last key may start a new one
+        let retry = pending.len() > 1;
+        pending.clear();
+        if retry {
+            self.resolve(context, pending, stroke)
+        } else {
+            Resolved::Unbound
+        }
+    }
+
+    /// The help line of `context`'s screen, with the first key of every action bound there but
+    /// the `unavailable` ones which wouldn't do anything right now.
+    pub fn help_without(&self, context: Context, unavailable: &[KeyAction]) -> HelpWidget<'static> {
+        let help = HelpWidget::new(
+            context
+                .actions()
+                .iter()
+                .filter(|(action, _)| !action.is_motion() && *action != KeyAction::Help)
+                .filter(|(action, _)| !unavailable.contains(action))
+                .filter_map(|&(action, label)| {
+                    let key = self.keys(context, action).first()?;
+                    Some((key.clone(), label))
+                })
+                .collect(),
+        );
+        // Kept apart so it's still there when the rest doesn't fit
+        match self
+            .entry(context, KeyAction::Help, "All keys")
+            .keys
+            .first()
+        {
+            Some(key) => help.with_more(key.clone(), "All keys"),
+            None => help,
+        }
+    }
+
+    fn entry(&self, context: Context, action: KeyAction, label: &'static str) -> Entry {
+        let keys = self
+            .keys(context, action)
+            .iter()
+            .chain(self.keys(Context::Global, action))
+            .cloned()
+            .collect();
+        Entry {
+            action,
+            label,
+            keys,
+        }
+    }
+
+    /// Everything that can be done on `context`'s screen, in titled groups: its own actions,
+    /// moving around, then what works everywhere.
+    pub fn overview(&self, context: Context) -> Vec<(&'static str, Vec<Entry>)> {
+        let global = Context::Global.actions();
+        let (motions, actions): (Vec<_>, Vec<_>) = context
+            .actions()
+            .iter()
+            .filter(|(action, _)| !global.iter().any(|(known, _)| known == action))
+            .partition(|(action, _)| action.is_motion());
+        [
+            ("Actions", actions),
+            ("Moving around", motions),
+            ("Everywhere", global.iter().collect()),
+        ]
+        .into_iter()
+        .map(|(title, actions)| {
+            let entries = actions
+                .into_iter()
+                .map(|&(action, label)| self.entry(context, action, label))
+                .collect::<Vec<_>>();
+            (title, entries)
+        })
+        .filter(|(_, entries)| !entries.is_empty())
+        .collect()
+    }
+
+    /// Two actions sharing a key on the same screen can't both work.
+    fn check(&self) -> Result<(), String> {
+        for context in Context::ALL {
+            let bound = self.bound(context).collect::<Vec<_>>();
+            for (i, (action, key)) in bound.iter().enumerate() {
+                if let Some((other, _)) = bound[i + 1..].iter().find(|(_, other)| other == key) {
+                    if other != action {
+                        return Err(format!(
+                            "{key} is bound to both `{}` and `{}` in `{}`",
+                            action.name(),
+                            other.name(),
+                            context.name()
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(Preset::Default)
+    }
+}
+
+/// One key or a list of them.
+struct Keys(Vec<KeyCombo>);
+
+impl<'de> Deserialize<'de> for Keys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeysVisitor;
+
+        impl<'de> Visitor<'de> for KeysVisitor {
+            type Value = Keys;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a key like \"ctrl+s\" or a list of them")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Keys, E> {
+                value.parse().map(|key| Keys(vec![key])).map_err(E::custom)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Keys, A::Error> {
+                let mut keys = vec![];
+                while let Some(key) = seq.next_element()? {
+                    keys.push(key);
+                }
+                Ok(Keys(keys))
+            }
+        }
+
+        deserializer.deserialize_any(KeysVisitor)
+    }
+}
+
+/// The `keys` section of the configuration, e.g. `{ "preset": "vim", "inbox": { "quit": "q" } }`.
+#[derive(Deserialize)]
+struct KeysConfig {
+    #[serde(default)]
+    preset: Preset,
+    #[serde(flatten)]
+    screens: HashMap<Context, HashMap<KeyAction, Keys>>,
+}
+
+impl TryFrom<KeysConfig> for Keymap {
+    type Error = String;
+
+    fn try_from(config: KeysConfig) -> Result<Self, Self::Error> {
+        let mut keymap = Keymap::new(config.preset);
+        // Sorted so the same mistake is always reported first
+        let screens = config
+            .screens
+            .into_iter()
+            .sorted_by_key(|(context, _)| context.name());
+        for (context, bindings) in screens {
+            let actions = context.actions();
+            let bindings = bindings
+                .into_iter()
+                .sorted_by_key(|(action, _)| action.name());
+            for (action, Keys(keys)) in bindings {
+                if !actions.iter().any(|(known, _)| *known == action) {
+                    return Err(format!(
+                        "`{}` can't be bound in `{}`, only {}",
+                        action.name(),
+                        context.name(),
+                        actions
+                            .iter()
+                            .map(|(action, _)| format!("`{}`", action.name()))
+                            .join(", ")
+                    ));
+                }
+                keymap.bind(context, action, keys);
+            }
+        }
+        keymap.check()?;
+        Ok(keymap)
+    }
+}
+
+impl<'de> Deserialize<'de> for Keymap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        KeysConfig::deserialize(deserializer)?
+            .try_into()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::{KeyCode, KeyModifiers};
+    use serde_json::json;
+
+    use super::{Context, KeyAction, Keymap, Preset, Resolved};
+    use crate::tui::combo::Stroke;
+
+    fn press(keymap: &Keymap, context: Context, keys: &[Stroke]) -> Vec<Resolved> {
+        let mut pending = vec![];
+        keys.iter()
+            .map(|stroke| keymap.resolve(context, &mut pending, *stroke))
+            .collect()
+    }
+
+    fn key(code: char) -> Stroke {
+        Stroke::new(KeyCode::Char(code), KeyModifiers::NONE)
+    }
+
+    fn ctrl(code: char) -> Stroke {
+        Stroke::new(KeyCode::Char(code), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn keys_are_resolved() {
+        let default = Keymap::default();
+        assert_eq!(
+            press(&default, Context::Inbox, &[ctrl('w'), ctrl('q'), key('j')]),
+            [
+                Resolved::Action(KeyAction::Quit),
+                Resolved::Action(KeyAction::Quit),
+                Resolved::Unbound
+            ]
+        );
+        assert_eq!(
+            press(&default, Context::Compose, &[ctrl('z')]),
+            [Resolved::Action(KeyAction::UndoSend)],
+            "global bindings work everywhere"
+        );
+        assert_eq!(
+            default
+                .help_without(Context::Inbox, &[])
+                .to_string()
+                .split(" | ")
+                .next(),
+            Some("[Enter]: Read email")
+        );
+        assert_eq!(
+            default
+                .help_without(Context::Login, &[KeyAction::Retry])
+                .to_string(),
+            "[Esc]: Exit"
+        );
+
+        let vim = Keymap::new(Preset::Vim);
+        let shifted = Stroke::new(KeyCode::Char('G'), KeyModifiers::SHIFT);
+        assert_eq!(
+            press(
+                &vim,
+                Context::Inbox,
+                &[key('g'), key('g'), shifted, key('g'), key('j')]
+            ),
+            [
+                Resolved::Pending,
+                Resolved::Action(KeyAction::Top),
+                Resolved::Action(KeyAction::Bottom),
+                Resolved::Pending,
+                Resolved::Action(KeyAction::Down),
+            ]
+        );
+        assert_eq!(
+            press(&vim, Context::Compose, &[key('j')]),
+            [Resolved::Unbound],
+            "letters are typed when composing"
+        );
+
+        let emacs = Keymap::new(Preset::Emacs);
+        assert_eq!(
+            press(&emacs, Context::Inbox, &[ctrl('x'), ctrl('c'), ctrl('n')]),
+            [
+                Resolved::Pending,
+                Resolved::Action(KeyAction::Quit),
+                Resolved::Action(KeyAction::Down),
+            ]
+        );
+        assert_eq!(
+            press(&emacs, Context::Compose, &[ctrl('c'), ctrl('c')]),
+            [Resolved::Pending, Resolved::Action(KeyAction::Send)]
+        );
+        for keymap in [default, vim, emacs] {
+            assert_eq!(keymap.check(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn keys_are_configured() {
+        let keymap: Keymap = serde_json::from_value(json!({
+            "preset": "vim",
+            "inbox": { "quit": "q", "compose": ["c", "ctrl+n"] },
+        }))
+        .unwrap();
+        assert_eq!(
+            press(
+                &keymap,
+                Context::Inbox,
+                &[key('q'), ctrl('w'), key('c'), key('k')]
+            ),
+            [
+                Resolved::Action(KeyAction::Quit),
+                Resolved::Unbound,
+                Resolved::Action(KeyAction::Compose),
+                Resolved::Action(KeyAction::Up),
+            ]
+        );
+
+        let error = |value| {
+            serde_json::from_value::<Keymap>(value)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error(json!({ "inbox": { "quit": "hyper+q" } })),
+            "unknown modifier `hyper` in `hyper+q`"
+        );
+        assert_eq!(
+            error(json!({ "inbox": { "search": "n" } })),
+            "[n] is bound to both `search` and `search_next` in `inbox`"
+        );
+        assert!(error(json!({ "inbox": { "send": "ctrl+s" } }))
+            .starts_with("`send` can't be bound in `inbox`, only `open`"));
+    }
+
+    #[test]
+    fn every_key_is_listed() {
+        let keymap = Keymap::default();
+        assert_eq!(
+            press(&keymap, Context::Inbox, &[key('?')]),
+            [Resolved::Action(KeyAction::Help)]
+        );
+        assert_eq!(
+            press(&keymap, Context::Compose, &[key('?')]),
+            [Resolved::Unbound],
+            "`?` is typed when composing"
+        );
+
+        let overview = keymap.overview(Context::Outbox);
+        assert_eq!(
+            overview.iter().map(|(title, _)| *title).collect::<Vec<_>>(),
+            ["Actions", "Moving around", "Everywhere"]
+        );
+        let (_, everywhere) = &overview[2];
+        let help = everywhere
+            .iter()
+            .find(|entry| entry.action == KeyAction::Help)
+            .unwrap();
+        assert_eq!(
+            help.keys
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["[?]", "[F1]"]
+        );
+        assert!(
+            !overview[0]
+                .1
+                .iter()
+                .any(|entry| entry.action == KeyAction::Help),
+            "listed once"
+        );
+    }
+}
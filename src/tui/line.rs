@@ -1,11 +1,14 @@
 use crossterm::event::KeyEvent;
 use ratatui::{
-    style::{Color, Style, Stylize},
+    style::Style,
     widgets::{block::Title, Block, Borders, Widget},
 };
 use tui_textarea::TextArea;
 
-use crate::tui::focus::FocusStyle;
+use crate::tui::{
+    focus::{focused_block, unfocused_block, FocusStyle},
+    theme::Theme,
+};
 
 pub struct LineWidget<'w> {
     textarea: TextArea<'w>,
@@ -68,17 +71,17 @@ impl<'w> AsMut<TextArea<'w>> for LineWidget<'w> {
 }
 
 impl<'w> FocusStyle for LineWidget<'w> {
-    fn unfocused(&mut self) {
+    fn unfocused(&mut self, theme: &Theme) {
         let Some(block) = self.textarea.block() else {
             return;
         };
-        self.textarea.set_block(block.clone().fg(Color::default()));
+        self.textarea.set_block(unfocused_block(block, theme));
     }
 
-    fn focused(&mut self) {
+    fn focused(&mut self, theme: &Theme) {
         let Some(block) = self.textarea.block() else {
             return;
         };
-        self.textarea.set_block(block.clone().fg(Color::Blue));
+        self.textarea.set_block(focused_block(block, theme));
     }
 }
@@ -1,79 +1,86 @@
-use crossterm::event::{Event, KeyCode, KeyEvent};
 use ratatui::{
-    layout::{Constraint, Direction, Layout},
-    widgets::{Block, Borders, Paragraph, Widget},
+    text::Line,
+    widgets::{Block, Borders, Paragraph, Widget, Wrap},
 };
 
-use crate::tui::{
-    combo::KeyCombo,
-    help::{HasHelp, HelpWidget},
-    Action, Page,
-};
+use crate::tui::{keymap::KeyAction, theme::Theme, Action};
 
-pub struct LoginWidget<'w> {
-    url: String,
+pub enum LoginStatus {
+    /// Waiting for the redirect server to start.
+    Starting,
+    /// Waiting for the user to authorize us in the browser, on this machine since that's where
+    /// the redirect goes.
+    Waiting(String),
+    Failed(String),
+}
 
-    help: HelpWidget<'w>,
+pub struct LoginWidget {
+    status: LoginStatus,
+    theme: Theme,
 }
 
-impl<'w> LoginWidget<'w> {
-    pub fn new(url: String) -> LoginWidget<'w> {
+impl LoginWidget {
+    pub fn new() -> LoginWidget {
         Self {
-            url,
-            help: Self::help(),
+            status: LoginStatus::Starting,
+            theme: Theme::default(),
         }
     }
 
-    pub fn handle_event(&mut self, event: Event) -> Action {
-        match event {
-            Event::Key(key_event) => self.handle_key_event(key_event),
-            _ => Action::Tick,
-        }
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = *theme;
     }
 
-    fn handle_key_event(
-        &mut self,
-        KeyEvent {
-            code, modifiers, ..
-        }: KeyEvent,
-    ) -> Action {
-        match (code, modifiers) {
-            (KeyCode::Esc, _) => Action::Quit,
-            (KeyCode::Enter, _) => Action::GoTo(Page::Inbox),
-            _ => {
-                // TODO: solve this later, we need to advance the status
-                Action::Tick
-            }
-        }
+    pub fn set_url(&mut self, url: String) {
+        self.status = LoginStatus::Waiting(url);
+    }
+
+    pub fn set_failed(&mut self, reason: String) {
+        self.status = LoginStatus::Failed(reason);
     }
-}
 
-impl<'w> HasHelp for LoginWidget<'w> {
-    fn help<'h>() -> super::help::HelpWidget<'h> {
-        HelpWidget::new(vec![(
-            KeyCombo::new().with_code(crossterm::event::KeyCode::Esc),
-            "Exit",
-        )])
+    pub fn has_failed(&self) -> bool {
+        matches!(self.status, LoginStatus::Failed(_))
+    }
+
+    pub fn handle_action(&mut self, action: KeyAction) -> Action {
+        match action {
+            KeyAction::Quit => Action::Quit,
+            // Retrying is handled by the main loop since it owns the login flow,
+            // everything else is up to the browser
+            _ => Action::Tick,
+        }
     }
 }
 
-impl<'w> Widget for &LoginWidget<'w> {
+impl Widget for &LoginWidget {
     fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
     where
         Self: Sized,
     {
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Min(1), Constraint::Length(1)])
-            .split(area);
-        let block = Block::default().borders(Borders::ALL).title("Login");
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(self.theme.border)
+            .title("Login");
+
+        let mut text = vec![
+            Line::from("Your session has expired, please login again."),
+            Line::from(""),
+        ];
+        match &self.status {
+            LoginStatus::Starting => text.push(Line::from("Starting login...")),
+            LoginStatus::Waiting(url) => {
+                text.push(Line::from(format!("Open URL on this computer: {url}")));
+            }
+            LoginStatus::Failed(reason) => {
+                text.push(Line::from(format!("Login failed: {reason}")));
+            }
+        }
 
-        let text = format!("Open URL: {}", self.url);
         let paragraph = Paragraph::new(text)
             .block(block)
+            .wrap(Wrap { trim: false })
             .alignment(ratatui::layout::Alignment::Center);
-        paragraph.render(chunks[0], buf);
-
-        self.help.render(chunks[1], buf);
+        paragraph.render(area, buf);
     }
 }
@@ -0,0 +1,112 @@
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, StatefulWidget};
+
+use crate::{
+    store::{Mailbox, SpecialUse},
+    tui::{keymap::KeyAction, theme::Theme, Action, Page},
+};
+
+/// What picking a mailbox will do.
+pub enum MailboxesPurpose {
+    Open,
+    Move { uid: u32 },
+}
+
+pub struct MailboxesState {
+    pub mailboxes: Vec<Mailbox>,
+    pub list: ListState,
+    pub purpose: MailboxesPurpose,
+}
+
+impl MailboxesState {
+    pub fn new() -> Self {
+        Self {
+            mailboxes: vec![],
+            list: ListState::default().with_selected(Some(0)),
+            purpose: MailboxesPurpose::Open,
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Mailbox> {
+        self.list
+            .selected()
+            .and_then(|selected| self.mailboxes.get(selected))
+    }
+}
+
+pub struct MailboxesWidget {
+    theme: Theme,
+}
+
+impl MailboxesWidget {
+    pub fn new() -> Self {
+        Self {
+            theme: Theme::default(),
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = *theme;
+    }
+
+    pub fn handle_action(&mut self, action: KeyAction, state: &mut MailboxesState) -> Action {
+        match action {
+            KeyAction::Back => Action::GoTo(Page::Inbox),
+            KeyAction::Down => {
+                state.list.select_next();
+                Action::Tick
+            }
+            KeyAction::Up => {
+                state.list.select_previous();
+                Action::Tick
+            }
+            KeyAction::Top => {
+                state.list.select_first();
+                Action::Tick
+            }
+            KeyAction::Bottom => {
+                state.list.select_last();
+                Action::Tick
+            }
+            _ => Action::Tick,
+        }
+    }
+}
+
+impl StatefulWidget for &mut MailboxesWidget {
+    type State = MailboxesState;
+
+    fn render(
+        self,
+        area: ratatui::prelude::Rect,
+        buf: &mut ratatui::prelude::Buffer,
+        state: &mut Self::State,
+    ) {
+        let title = match state.purpose {
+            MailboxesPurpose::Open => "Mailboxes",
+            MailboxesPurpose::Move { .. } => "Move to",
+        };
+        let items = state.mailboxes.iter().map(|mailbox| {
+            let special_use = match mailbox.special_use {
+                Some(SpecialUse::All) => " (All)",
+                Some(SpecialUse::Archive) => " (Archive)",
+                Some(SpecialUse::Drafts) => " (Drafts)",
+                Some(SpecialUse::Flagged) => " (Flagged)",
+                Some(SpecialUse::Junk) => " (Junk)",
+                Some(SpecialUse::Sent) => " (Sent)",
+                Some(SpecialUse::Trash) => " (Trash)",
+                None => "",
+            };
+            ListItem::new(format!("{}{special_use}", mailbox.name))
+        });
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(self.theme.border)
+                    .title(title),
+            )
+            .highlight_style(self.theme.selected);
+
+        StatefulWidget::render(list, area, buf, &mut state.list);
+    }
+}
@@ -1,40 +1,130 @@
 pub mod body;
 pub mod combo;
 pub mod compose;
+pub mod drafts;
 pub mod focus;
 pub mod help;
 pub mod inbox;
+pub mod keymap;
 pub mod line;
 pub mod login;
+pub mod mailboxes;
+pub mod outbox;
+pub mod overlay;
+pub mod palette;
 pub mod popup;
 pub mod reading;
+pub mod schedule;
+pub mod search;
+pub mod theme;
 
 use std::io::{self};
-use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::path::PathBuf;
+#[cfg(feature = "refresher")]
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Sender, TryRecvError};
+use std::time::{Duration, Instant};
 
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use chrono::{DateTime, Local};
+use crossterm::event::{self, Event, KeyCode, KeyEvent};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Span;
 use ratatui::DefaultTerminal;
+use serde_json::Value;
 use std::sync::mpsc::SendError;
 
-use crate::imap::{Command, Response};
+#[cfg(feature = "refresher")]
+use crate::config;
+use crate::config::{Config, Watcher};
+use crate::drafts::Drafts;
+#[cfg(feature = "refresher")]
+use crate::imap::config::OAuthConfig;
+#[cfg(feature = "refresher")]
+use crate::oauth::LoginEvent;
+use crate::store::{Command, Flag, ParsedEmail, Response, SpecialUse};
+use crate::tui::combo::Stroke;
 use crate::tui::compose::ComposeWidget;
+use crate::tui::drafts::{DraftsState, DraftsWidget};
 use crate::tui::inbox::{InboxState, InboxWidget};
-use crate::tui::popup::Popup;
+use crate::tui::keymap::{Context, KeyAction, Keymap, Resolved};
+#[cfg(feature = "refresher")]
+use crate::tui::login::LoginWidget;
+use crate::tui::mailboxes::{MailboxesPurpose, MailboxesState, MailboxesWidget};
+use crate::tui::outbox::{OutboxState, OutboxWidget};
+use crate::tui::overlay::HelpOverlay;
+use crate::tui::palette::PaletteWidget;
+use crate::tui::popup::{Notice, Popup};
 use crate::tui::reading::ReadingWidget;
+use crate::tui::schedule::{SchedulePurpose, ScheduleWidget};
+use crate::tui::search::SearchWidget;
+use crate::tui::theme::{ColorDepth, Theme};
+use crate::workers::{self, SendWorker, StoreWorker};
 use crate::{smtp, Error};
 
 const EMAILS_TO_LOAD: u32 = 20;
+/// How often the message being composed is saved as a draft.
+const AUTOSAVE: Duration = Duration::from_secs(5);
+/// How often the configuration file is checked for changes.
+const CONFIG_POLL: Duration = Duration::from_secs(1);
 
 enum Screen<'w> {
     Inbox(InboxWidget<'w>),
     Compose(ComposeWidget<'w>),
     Reading(ReadingWidget<'w>),
+    Mailboxes(MailboxesWidget),
+    Outbox(OutboxWidget),
+    Drafts(DraftsWidget),
+    #[cfg(feature = "refresher")]
+    Login(LoginWidget),
+}
+
+impl Screen<'_> {
+    /// Where the keys pressed on this screen are looked up.
+    fn context(&self) -> Context {
+        match self {
+            Screen::Inbox(_) => Context::Inbox,
+            Screen::Compose(_) => Context::Compose,
+            Screen::Reading(_) => Context::Reading,
+            Screen::Mailboxes(_) => Context::Mailboxes,
+            Screen::Outbox(_) => Context::Outbox,
+            Screen::Drafts(_) => Context::Drafts,
+            #[cfg(feature = "refresher")]
+            Screen::Login(_) => Context::Login,
+        }
+    }
+
+    /// Actions of the screen which wouldn't do anything right now, left out of the help.
+    fn unavailable(&self) -> &'static [KeyAction] {
+        match self {
+            #[cfg(feature = "refresher")]
+            Screen::Login(widget) if !widget.has_failed() => &[KeyAction::Retry],
+            _ => &[],
+        }
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        match self {
+            Screen::Inbox(widget) => widget.set_theme(theme),
+            Screen::Compose(widget) => widget.set_theme(theme),
+            Screen::Reading(widget) => widget.set_theme(theme),
+            Screen::Mailboxes(widget) => widget.set_theme(theme),
+            Screen::Outbox(widget) => widget.set_theme(theme),
+            Screen::Drafts(widget) => widget.set_theme(theme),
+            #[cfg(feature = "refresher")]
+            Screen::Login(widget) => widget.set_theme(theme),
+        }
+    }
 }
 
 pub enum Page {
     Inbox,
     Compose,
     Reading,
+    Mailboxes,
+    Outbox,
+    Scheduled,
+    Drafts,
 }
 
 impl<'w> From<Page> for Screen<'w> {
@@ -43,6 +133,9 @@ impl<'w> From<Page> for Screen<'w> {
             Page::Inbox => Screen::Inbox(InboxWidget::new()),
             Page::Compose => Screen::Compose(ComposeWidget::default()),
             Page::Reading => unreachable!("This should be handled in a different way"),
+            Page::Mailboxes => Screen::Mailboxes(MailboxesWidget::new()),
+            Page::Outbox | Page::Scheduled => Screen::Outbox(OutboxWidget::new()),
+            Page::Drafts => Screen::Drafts(DraftsWidget::new()),
         }
     }
 }
@@ -53,31 +146,109 @@ pub enum Action {
     GoTo(Page),
 }
 
+/// A message waiting out the undo delay before it's handed to the SMTP thread.
+struct PendingSend {
+    widget: ComposeWidget<'static>,
+    deadline: Instant,
+}
+
+/// A login flow running in the background.
+#[cfg(feature = "refresher")]
+struct LoginState {
+    config: OAuthConfig,
+    events: Receiver<LoginEvent>,
+}
+
+#[cfg(feature = "refresher")]
+impl LoginState {
+    fn start(config: OAuthConfig) -> Self {
+        let scopes = config.scopes.clone();
+        let events = crate::oauth::spawn_login(config.clone().get_client(), scopes);
+        Self { config, events }
+    }
+}
+
+/// What the TUI needs from the configuration, besides the workers it configures.
+pub struct Settings {
+    /// Watched for changes, and where the tokens obtained from a new login are saved.
+    pub config_path: PathBuf,
+    /// The configuration as it was loaded, to tell which parts of it change.
+    pub value: Value,
+    /// Where outgoing mail is written instead of sent, it outlives configuration changes.
+    pub dry_run: Option<PathBuf>,
+    /// How long a sent message can still be taken back.
+    pub undo_send: Duration,
+    pub signature: Option<String>,
+    pub keymap: Keymap,
+    /// Why the connections to the servers can't be trusted, flagged for as long as it's true.
+    pub tls_dangers: Vec<String>,
+    /// Already brought down to the colors the terminal can show.
+    pub theme: Theme,
+}
+
 struct ScreenState {
     inbox_state: InboxState,
+    mailboxes_state: MailboxesState,
+    outbox_state: OutboxState,
+    drafts_state: DraftsState,
     request_inflight: bool,
 
-    to_imap: Sender<Command>,
-    from_imap: Receiver<Response>,
+    drafts: Drafts,
+    /// When the message being composed was last saved.
+    autosaved: Instant,
+
+    store: StoreWorker,
 
     popup: Option<String>,
+    /// Asking when to send a message, shown on top of the current screen.
+    schedule: Option<ScheduleWidget<'static>>,
+    /// Asking what to look for, shown on top of the inbox.
+    search: Option<SearchWidget<'static>>,
+    /// Every key of the current screen, on top of it.
+    help: Option<HelpOverlay>,
+    /// Picking an action of the current screen to run by name.
+    palette: Option<PaletteWidget<'static>>,
+    pending: Option<PendingSend>,
+    /// The keys typed so far of a sequence like `gg`.
+    keys: Vec<Stroke>,
+
+    settings: Settings,
+    watcher: Watcher,
+    #[cfg(feature = "refresher")]
+    login: Option<LoginState>,
 }
 
 impl ScreenState {
-    fn new(to_imap: Sender<Command>, from_imap: Receiver<Response>) -> Self {
+    fn new(store: StoreWorker, settings: Settings) -> Self {
         Self {
             inbox_state: InboxState::new(),
+            mailboxes_state: MailboxesState::new(),
+            outbox_state: OutboxState::new(),
+            drafts_state: DraftsState::new(),
             request_inflight: false,
-            to_imap,
-            from_imap,
-            popup: None,
+            drafts: Drafts::new(Drafts::default_dir()),
+            autosaved: Instant::now(),
+            store,
+            // Also printed before the TUI started, but that's gone as soon as it does
+            popup: (!settings.tls_dangers.is_empty()).then(|| settings.tls_dangers.join("; ")),
+            schedule: None,
+            search: None,
+            help: None,
+            palette: None,
+            pending: None,
+            keys: vec![],
+            watcher: Watcher::new(settings.config_path.clone(), CONFIG_POLL),
+            settings,
+            #[cfg(feature = "refresher")]
+            login: None,
         }
     }
 }
 
 impl ScreenState {
     fn load(&mut self) -> Result<(), SendError<Command>> {
-        self.to_imap.send(Command::ReadInbox {
+        self.store.tx.send(Command::ListMessages {
+            mailbox: self.inbox_state.mailbox.clone(),
             count: EMAILS_TO_LOAD,
             offset: 0,
         })?;
@@ -88,8 +259,9 @@ impl ScreenState {
     fn load_more(&mut self, count: u32) -> Result<(), SendError<Command>> {
         if !self.request_inflight {
             if let Some(selected) = self.inbox_state.table.selected() {
-                if selected == self.inbox_state.inbox.len() - 1 {
-                    self.to_imap.send(Command::ReadInbox {
+                if selected + 1 == self.inbox_state.inbox.len() {
+                    self.store.tx.send(Command::ListMessages {
+                        mailbox: self.inbox_state.mailbox.clone(),
                         count,
                         offset: self.inbox_state.inbox.len() as u32,
                     })?;
@@ -99,50 +271,429 @@ impl ScreenState {
         };
         Ok(())
     }
+
+    /// Open the message in the reading screen, its body will only be available after it's fetched.
+    fn open(&mut self, parsed_email: &ParsedEmail) -> Result<(), SendError<Command>> {
+        let mailbox = self.inbox_state.mailbox.clone();
+        self.store.tx.send(Command::FetchMessage {
+            mailbox: mailbox.clone(),
+            uid: parsed_email.uid,
+        })?;
+        if !parsed_email.is_seen() {
+            self.store.tx.send(Command::SetFlags {
+                mailbox,
+                uid: parsed_email.uid,
+                flags: vec![Flag::Seen],
+                enable: true,
+            })?;
+            if let Some(email) = self
+                .inbox_state
+                .inbox
+                .iter_mut()
+                .find(|email| email.uid == parsed_email.uid)
+            {
+                email.flags.push(Flag::Seen);
+            }
+        }
+        Ok(())
+    }
+
+    fn open_mailboxes(&mut self, purpose: MailboxesPurpose) -> Result<(), SendError<Command>> {
+        if self.mailboxes_state.mailboxes.is_empty() {
+            self.store.tx.send(Command::ListMailboxes)?;
+        }
+        self.mailboxes_state.purpose = purpose;
+        Ok(())
+    }
+
+    /// Act on the selected mailbox, returns whether something was done.
+    fn select_mailbox(&mut self) -> Result<bool, SendError<Command>> {
+        let Some(selected) = self.mailboxes_state.selected().map(|m| m.name.clone()) else {
+            return Ok(false);
+        };
+        match self.mailboxes_state.purpose {
+            MailboxesPurpose::Open => {
+                self.inbox_state = InboxState::new();
+                self.inbox_state.mailbox = selected;
+                self.load()?;
+            }
+            MailboxesPurpose::Move { uid } => {
+                self.store.tx.send(Command::Move {
+                    mailbox: self.inbox_state.mailbox.clone(),
+                    uid,
+                    destination: selected,
+                })?;
+                self.inbox_state.inbox.retain(|email| email.uid != uid);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Select the next message in the inbox matching `query`, which is kept for the next search.
+    fn search(&mut self, query: String) {
+        if !self.inbox_state.find_next(&query) {
+            self.popup = Some(format!("No message matches \"{query}\""));
+        }
+        self.inbox_state.query = Some(query);
+    }
+
+    fn load_drafts(&mut self) {
+        match self.drafts.list() {
+            Ok(drafts) => self.drafts_state.drafts = drafts,
+            Err(err) => {
+                tracing::error!("Failed to list drafts with error: {err}");
+                self.popup = Some(format!("Failed to list drafts: {err}"));
+            }
+        }
+        if self.drafts_state.selected().is_none() {
+            let last = self.drafts_state.drafts.len().checked_sub(1);
+            self.drafts_state.table.select(last.or(Some(0)));
+        }
+    }
+
+    /// Save the message being composed if it changed, `sync` also sends it to the store.
+    fn save_draft(
+        &mut self,
+        widget: &mut ComposeWidget,
+        sync: bool,
+    ) -> Result<(), SendError<Command>> {
+        self.autosaved = Instant::now();
+        let sync = sync && widget.needs_syncing();
+        if !widget.needs_saving() && !sync {
+            return Ok(());
+        }
+
+        let draft = widget.draft();
+        // Everything was deleted, there's nothing worth keeping
+        if draft.is_empty() {
+            widget.mark_saved(sync);
+            return self.discard_draft(&draft.id, sync);
+        }
+        if let Err(err) = self.drafts.save(&draft) {
+            tracing::error!("Failed to save draft with error: {err}");
+            self.popup = Some(format!("Failed to save the draft: {err}"));
+            return Ok(());
+        }
+        if sync {
+            self.store.tx.send(Command::SaveDraft {
+                raw: draft.formatted(),
+                id: draft.id,
+            })?;
+        }
+        widget.mark_saved(sync);
+        Ok(())
+    }
+
+    /// Hand the composed message over to the SMTP thread, `at` schedules it for later.
+    ///
+    /// Returns whether it was queued, invalid messages are reported in a popup.
+    fn queue_message(
+        &mut self,
+        to_smtp: &Sender<smtp::Command>,
+        widget: &ComposeWidget,
+        at: Option<DateTime<Local>>,
+    ) -> Result<bool, Error> {
+        let message = match widget.get_partial_message() {
+            Ok(message) => message,
+            Err(err) => {
+                self.popup = Some(err.to_string());
+                return Ok(false);
+            }
+        };
+        // The new message is queued before the one it replaces is dropped
+        let mut commands = vec![match at.or_else(|| widget.scheduled()) {
+            Some(at) => smtp::Command::Schedule { message, at },
+            None => smtp::Command::SendMail(message),
+        }];
+        if let Some(id) = widget.replaces() {
+            commands.push(smtp::Command::Discard(id.to_string()));
+        }
+        commands
+            .into_iter()
+            .try_for_each(|command| to_smtp.send(command))
+            .map_err(|_| io::Error::other("SMTP channel got disconnected"))?;
+        // It's in the outbox now, the draft isn't needed anymore
+        self.discard_draft(widget.draft_id(), true)
+            .map_err(|_| io::Error::other("Store channel got disconnected"))?;
+        Ok(true)
+    }
+
+    /// Put the outbox message being edited back in the queue, with the changes if they can be
+    /// sent. Otherwise they're kept as a draft, like any other message being composed.
+    fn release(
+        &mut self,
+        to_smtp: &Sender<smtp::Command>,
+        widget: &mut ComposeWidget,
+    ) -> Result<(), Error> {
+        let Some(id) = widget.replaces() else {
+            return Ok(());
+        };
+        let message = match widget.get_partial_message() {
+            Ok(message) => Some(message),
+            Err(err) => {
+                self.popup = Some(format!("The changes were kept as a draft: {err}"));
+                None
+            }
+        };
+        let requeued = message.is_some();
+        to_smtp
+            .send(smtp::Command::Release {
+                id: id.to_string(),
+                message,
+            })
+            .map_err(|_| io::Error::other("SMTP channel got disconnected"))?;
+        if requeued {
+            widget.mark_saved(true);
+            self.discard_draft(widget.draft_id(), true)
+                .map_err(|_| io::Error::other("Store channel got disconnected"))?;
+        }
+        Ok(())
+    }
+
+    /// Keep what was typed when the compose screen goes away, as a draft or back in the outbox.
+    fn leave_compose(
+        &mut self,
+        to_smtp: &Sender<smtp::Command>,
+        widget: &mut ComposeWidget,
+    ) -> Result<(), Error> {
+        self.release(to_smtp, widget)?;
+        self.save_draft(widget, true)
+            .map_err(|_| io::Error::other("Store channel got disconnected"))?;
+        Ok(())
+    }
+
+    /// Send the composed message once the undo delay is over, right away if there's none.
+    fn send(
+        &mut self,
+        to_smtp: &Sender<smtp::Command>,
+        mut widget: ComposeWidget<'static>,
+    ) -> Result<(), Error> {
+        // Only one message can be taken back at a time
+        self.flush_pending(to_smtp)?;
+        if self.settings.undo_send.is_zero() {
+            self.queue_message(to_smtp, &widget, None)?;
+            return Ok(());
+        }
+        // Until it's queued, the draft is all there is
+        self.save_draft(&mut widget, false)
+            .map_err(|_| io::Error::other("Store channel got disconnected"))?;
+        self.pending = Some(PendingSend {
+            widget,
+            deadline: Instant::now() + self.settings.undo_send,
+        });
+        Ok(())
+    }
+
+    /// Send the message waiting for the undo delay, without waiting any longer.
+    fn flush_pending(&mut self, to_smtp: &Sender<smtp::Command>) -> Result<(), Error> {
+        if let Some(pending) = self.pending.take() {
+            self.queue_message(to_smtp, &pending.widget, None)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the configuration after it was edited, the workers whose settings changed are
+    /// restarted. Nothing changes if any of it can't be used.
+    ///
+    /// Returns whether the store was restarted, what's on screen might not exist anymore.
+    fn reload(&mut self, value: Value, sender: &mut SendWorker) -> Result<bool, Error> {
+        let config = match Config::from_value(&value) {
+            Ok(config) => config,
+            Err(err) => {
+                tracing::error!("Failed to reload the configuration with error: {err}");
+                self.popup = Some(format!("{err}, keeping the current configuration"));
+                return Ok(false);
+            }
+        };
+        let tls_dangers = config.tls_dangers();
+        let changed = |key: &str| value.get(key) != self.settings.value.get(key);
+        // Built first so a failure leaves the running workers alone
+        let store = changed("read")
+            .then(|| workers::store(config.read))
+            .transpose();
+        let send = changed("send")
+            .then(|| workers::sender(config.send, self.settings.dry_run.as_deref()))
+            .transpose();
+        let (store, send) = match (store, send) {
+            (Ok(store), Ok(send)) => (store, send),
+            (Err(err), _) | (_, Err(err)) => {
+                tracing::error!("Failed to apply the new configuration with error: {err}");
+                self.popup = Some(format!(
+                    "Failed to apply the new configuration, keeping the current one: {err}"
+                ));
+                return Ok(false);
+            }
+        };
+
+        self.settings.value = value;
+        self.settings.undo_send = Duration::from_secs(config.undo_send);
+        self.settings.signature = config.signature;
+        self.settings.keymap = config.keys;
+        if tls_dangers.len() > self.settings.tls_dangers.len() {
+            self.popup = Some(tls_dangers.join("; "));
+        }
+        self.settings.tls_dangers = tls_dangers;
+        self.settings.theme = config
+            .theme
+            .adapt(config.colors.unwrap_or_else(ColorDepth::detect));
+        self.keys.clear();
+        let mut restarted = vec![];
+        if let Some(send) = send {
+            // The old one has to let go of the outbox first, or a message could go out twice
+            sender.restart(send, true);
+            restarted.push("sending");
+        }
+        let reconnected = store.is_some();
+        if let Some(store) = store {
+            self.store.restart(store, false);
+            self.inbox_state = InboxState::new();
+            self.mailboxes_state = MailboxesState::new();
+            self.request_inflight = false;
+            self.load()
+                .map_err(|_| io::Error::other("Store channel got disconnected"))?;
+            restarted.push("reading");
+        }
+        tracing::info!("Reloaded the configuration");
+        if !restarted.is_empty() {
+            self.popup = Some(format!(
+                "Configuration reloaded, reconnected for {}",
+                restarted.join(" and ")
+            ));
+        }
+        Ok(reconnected)
+    }
+
+    /// Drop a draft which was sent or isn't wanted anymore, `sync` also drops the store's copy.
+    fn discard_draft(&mut self, id: &str, sync: bool) -> Result<(), SendError<Command>> {
+        if let Err(err) = self.drafts.remove(id) {
+            tracing::error!("Failed to remove draft with error: {err}");
+            self.popup = Some(format!("Failed to remove the draft: {err}"));
+        }
+        if sync {
+            self.store.tx.send(Command::RemoveDraft(id.to_string()))?;
+        }
+        Ok(())
+    }
 }
 
-#[tracing::instrument(skip_all)]
+/// Run the TUI until it's quit, handing the workers back so they can be joined.
 pub fn run(
+    terminal: DefaultTerminal,
+    settings: Settings,
+    store: StoreWorker,
+    mut sender: SendWorker,
+) -> (Result<(), Error>, StoreWorker, SendWorker) {
+    let mut state = ScreenState::new(store, settings);
+    let result = serve(terminal, &mut state, &mut sender);
+    (result, state.store, sender)
+}
+
+#[tracing::instrument(skip_all)]
+fn serve(
     mut terminal: DefaultTerminal,
-    to_imap: Sender<crate::imap::Command>,
-    from_imap: Receiver<crate::imap::Response>,
-    to_smtp: Sender<smtp::Command>,
-    from_smtp: Receiver<smtp::Response>,
+    state: &mut ScreenState,
+    sender: &mut SendWorker,
 ) -> Result<(), Error> {
     let mut screen = Screen::from(Page::Inbox);
 
-    let mut state = ScreenState::new(to_imap, from_imap);
-
     state
         .load()
-        .map_err(|_| io::Error::other("IMAP channel got disconnected"))?;
+        .map_err(|_| io::Error::other("Store channel got disconnected"))?;
 
     loop {
-        match state.from_imap.try_recv() {
-            Ok(Response::Inbox(inbox)) => {
-                state.inbox_state.inbox.extend(inbox);
+        match state.store.rx.try_recv() {
+            Ok(Response::Messages { mailbox, emails }) => {
+                // Responses for a mailbox we already left are useless
+                if mailbox == state.inbox_state.mailbox {
+                    state.inbox_state.inbox.extend(emails);
+                    state.request_inflight = false;
+                }
+            }
+            Ok(Response::Mailboxes(mailboxes)) => {
+                state.mailboxes_state.mailboxes = mailboxes;
+            }
+            Ok(Response::Message { mailbox, uid, raw }) => {
+                if let Screen::Reading(widget) = &mut screen {
+                    if mailbox == state.inbox_state.mailbox && widget.uid() == uid {
+                        let flags = state
+                            .inbox_state
+                            .inbox
+                            .iter()
+                            .find(|email| email.uid == uid)
+                            .map(|email| email.flags.clone())
+                            .unwrap_or_default();
+                        match ParsedEmail::parse(uid, &raw, None, flags) {
+                            Some(parsed) => *widget = ReadingWidget::from(parsed),
+                            None => {
+                                tracing::error!("Failed to parse email message {uid}");
+                                state.popup = Some("Failed to parse the email".to_string());
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(Response::Failed(err)) => {
+                tracing::error!("Store operation failed with error: {err}");
+                // It may have been the messages being loaded
+                state.request_inflight = false;
+                state.popup = Some(err.to_string());
+            }
+            #[cfg(feature = "refresher")]
+            Ok(Response::LoginRequired(config)) => {
+                tracing::info!("Starting a new login");
                 state.request_inflight = false;
+                state.login = Some(LoginState::start(config));
+                screen = Screen::Login(LoginWidget::new());
             }
             Ok(Response::Error(err)) => {
-                tracing::error!("IMAP thread failed with error: {err}");
+                tracing::error!("Store thread failed with error: {err}");
                 tracing::error!("Exiting...");
                 return Err(err);
             }
             Err(TryRecvError::Empty) => { /* no-op */ }
             Err(TryRecvError::Disconnected) => {
-                tracing::error!("IMAP channel disconnected");
+                tracing::error!("Store channel disconnected");
                 tracing::error!("Exiting...");
                 return Err(Error::Io(std::io::Error::other(
-                    "IMAP channel got disconnected",
+                    "Store channel got disconnected",
                 )));
             }
         }
 
-        match from_smtp.try_recv() {
-            Ok(smtp::Response::SendMailSuccess) => { /* todo: popup */ }
-            Ok(smtp::Response::Error(crate::Error::Smtp(err))) if err.is_transient() => {
-                tracing::error!("SMTP thread failed with transient error: {err}");
-                tracing::warn!("Not exiting (yet)");
+        match sender.rx.try_recv() {
+            Ok(smtp::Response::SendMailSuccess { sent }) => {
+                state.popup = Some("Successfully sent email!".to_string());
+                if let Some(raw) = sent {
+                    let append = Command::Append {
+                        special_use: SpecialUse::Sent,
+                        raw,
+                        flags: vec![Flag::Seen],
+                    };
+                    if let Err(err) = state.store.tx.send(append) {
+                        tracing::error!("Failed to send message to store thread: {err}");
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(smtp::Response::SendMailFailed {
+                error,
+                next_attempt,
+            }) => {
+                // The message is still in the outbox, no need to exit
+                state.popup = Some(match next_attempt {
+                    Some(next_attempt) => format!(
+                        "Sending failed, retrying at {}: {error}",
+                        next_attempt.format("%H:%M:%S")
+                    ),
+                    None => format!("Sending failed, the message was kept in the Outbox: {error}"),
+                });
+            }
+            Ok(smtp::Response::Outbox(messages)) => {
+                state.outbox_state.messages = messages;
+                if state.outbox_state.selected().is_none() {
+                    let last = state.outbox_state.visible_count().checked_sub(1);
+                    state.outbox_state.table.select(last.or(Some(0)));
+                }
             }
             Ok(smtp::Response::Error(err)) => {
                 tracing::error!("SMTP thread failed with error: {err}");
@@ -159,24 +710,223 @@ pub fn run(
             }
         }
 
+        #[cfg(feature = "refresher")]
+        if let Some(login) = &state.login {
+            match login.events.try_recv() {
+                Ok(LoginEvent::Url(url)) => {
+                    if let Screen::Login(widget) = &mut screen {
+                        widget.set_url(url);
+                    }
+                }
+                Ok(LoginEvent::Success {
+                    access_token,
+                    refresh_token,
+                }) => {
+                    let refresh_token = refresh_token.unwrap_or_else(|| {
+                        tracing::warn!(
+                            "Provider did not send a new refresh token, keeping the old one"
+                        );
+                        login.config.refresh_token.clone()
+                    });
+                    let client_id = login.config.client_id.clone();
+                    state.login = None;
+
+                    let saved = config::tokens_path()
+                        .ok_or_else(|| io::Error::other("no data folder to keep them in").into())
+                        .and_then(|path| {
+                            Config::save_oauth_tokens(
+                                path,
+                                &client_id,
+                                &access_token,
+                                &refresh_token,
+                            )
+                        });
+                    if let Err(err) = saved {
+                        tracing::error!("Failed to save the new tokens with error: {err}");
+                        state.popup = Some(format!("Failed to save the new tokens: {err}"));
+                    } else if let Ok(value) = config::read_value(&state.settings.config_path) {
+                        // The backends already have the new tokens, there's nothing to reload
+                        state.settings.value = value;
+                    }
+
+                    if let Err(err) = sender.tx.send(smtp::Command::Login {
+                        client_id,
+                        access_token: access_token.clone(),
+                        refresh_token: refresh_token.clone(),
+                    }) {
+                        tracing::error!("Failed to send message to SMTP thread with error: {err}");
+                        break Ok(());
+                    }
+                    state
+                        .store
+                        .tx
+                        .send(Command::Login {
+                            access_token,
+                            refresh_token,
+                        })
+                        .map_err(|_| io::Error::other("Store channel got disconnected"))?;
+                    state
+                        .load()
+                        .map_err(|_| io::Error::other("Store channel got disconnected"))?;
+                    screen = Screen::Inbox(InboxWidget::new());
+                }
+                Ok(LoginEvent::Failed(err)) => {
+                    tracing::error!("Login failed with error: {err}");
+                    if let Screen::Login(widget) = &mut screen {
+                        widget.set_failed(err.to_string());
+                    }
+                }
+                Err(TryRecvError::Empty) => { /* no-op */ }
+                Err(TryRecvError::Disconnected) => {
+                    // The flow is over, either it failed (and we already know) or it panicked
+                    if let Screen::Login(widget) = &mut screen {
+                        if !widget.has_failed() {
+                            widget.set_failed("login flow stopped unexpectedly".to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        match state.watcher.poll() {
+            Some(Ok(value)) => {
+                let reconnected = state.reload(value, sender).inspect_err(|err| {
+                    tracing::error!("Failed to restart a worker with error: {err}");
+                })?;
+                // The messages on screen may be from the previous account
+                if reconnected {
+                    #[cfg(feature = "refresher")]
+                    {
+                        state.login = None;
+                    }
+                    if !matches!(
+                        screen,
+                        Screen::Compose(_) | Screen::Outbox(_) | Screen::Drafts(_)
+                    ) {
+                        screen = Screen::Inbox(InboxWidget::new());
+                    }
+                }
+            }
+            Some(Err(err)) => {
+                tracing::error!("Failed to read the configuration with error: {err}");
+                state.popup = Some(format!(
+                    "Failed to read the configuration, keeping the current one: {err}"
+                ));
+            }
+            None => {}
+        }
+
+        if state
+            .pending
+            .as_ref()
+            .is_some_and(|pending| Instant::now() >= pending.deadline)
+        {
+            if let Err(err) = state.flush_pending(&sender.tx) {
+                tracing::error!("Failed to queue the message with error: {err}");
+                return Ok(());
+            }
+        }
+
+        if let Screen::Compose(widget) = &mut screen {
+            if state.autosaved.elapsed() >= AUTOSAVE {
+                if let Err(err) = state.save_draft(widget, false) {
+                    tracing::error!("Failed to send message to store thread: {err}");
+                    return Ok(());
+                }
+            }
+        }
+
+        // Styled right before drawing, so a new theme shows at once
+        let theme = state.settings.theme;
+        screen.set_theme(&theme);
         terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(1)])
+                .split(f.area());
+            let area = chunks[0];
+
             match &mut screen {
                 Screen::Inbox(widget) => {
-                    f.render_stateful_widget(widget, f.area(), &mut state.inbox_state);
+                    f.render_stateful_widget(widget, area, &mut state.inbox_state);
 
                     if state.request_inflight {
                         f.render_widget(
-                            Popup::new("Loading more emails!".to_string(), false),
-                            f.area(),
+                            Popup::new("Loading more emails!".to_string(), false, theme),
+                            area,
                         );
                     }
                 }
-                Screen::Compose(widget) => f.render_widget(&*widget, f.area()),
-                Screen::Reading(widget) => f.render_widget(&*widget, f.area()),
+                Screen::Compose(widget) => f.render_widget(&*widget, area),
+                Screen::Reading(widget) => f.render_widget(&*widget, area),
+                Screen::Mailboxes(widget) => {
+                    f.render_stateful_widget(widget, area, &mut state.mailboxes_state)
+                }
+                Screen::Outbox(widget) => {
+                    f.render_stateful_widget(widget, area, &mut state.outbox_state)
+                }
+                Screen::Drafts(widget) => {
+                    f.render_stateful_widget(widget, area, &mut state.drafts_state)
+                }
+                #[cfg(feature = "refresher")]
+                Screen::Login(widget) => f.render_widget(&*widget, area),
+            }
+            // Stays up for as long as the connections can't be trusted
+            let marker = if state.settings.tls_dangers.is_empty() {
+                ""
+            } else {
+                " INSECURE TLS "
+            };
+            let [marker_area, help_area] =
+                Layout::horizontal([Constraint::Length(marker.len() as u16), Constraint::Min(0)])
+                    .areas(chunks[1]);
+            f.render_widget(
+                Span::styled(marker, Style::new().reversed().bold()),
+                marker_area,
+            );
+            f.render_widget(
+                &state
+                    .settings
+                    .keymap
+                    .help_without(screen.context(), screen.unavailable())
+                    .with_style(theme.help),
+                help_area,
+            );
+
+            if let Some(pending) = &state.pending {
+                let left = pending.deadline.saturating_duration_since(Instant::now());
+                let undo = state
+                    .settings
+                    .keymap
+                    .keys(Context::Global, KeyAction::UndoSend)
+                    .first()
+                    .map(|key| format!(", {key} to undo"))
+                    .unwrap_or_default();
+                let message = format!(
+                    "Sending in {}s{undo}",
+                    left.as_secs() + u64::from(left.subsec_nanos() > 0)
+                );
+                f.render_widget(Notice::new(message, theme), f.area());
+            }
+
+            if let Some(schedule) = &state.schedule {
+                f.render_widget(schedule, f.area());
+            }
+
+            if let Some(search) = &state.search {
+                f.render_widget(search, f.area());
+            }
+
+            if let Some(help) = &mut state.help {
+                f.render_widget(help, f.area());
+            }
+
+            if let Some(palette) = &state.palette {
+                f.render_widget(palette, f.area());
             }
 
             if let Some(error) = &state.popup {
-                f.render_widget(Popup::new(error.to_string(), true), f.area());
+                f.render_widget(Popup::new(error.to_string(), true, theme), f.area());
             }
         })?;
 
@@ -194,16 +944,183 @@ pub fn run(
                 continue;
             }
 
+            if let Some(schedule) = &mut state.schedule {
+                let Event::Key(key_event) = event else {
+                    continue;
+                };
+                let at = match key_event.code {
+                    KeyCode::Esc => {
+                        state.schedule = None;
+                        continue;
+                    }
+                    KeyCode::Enter => schedule.when(Local::now()),
+                    _ => {
+                        schedule.input(key_event);
+                        continue;
+                    }
+                };
+                let Some(at) = at else {
+                    state.popup = Some(format!(
+                        "Can't send at \"{}\", try \"tomorrow 9:00\" or \"in 2h\"",
+                        schedule.value()
+                    ));
+                    continue;
+                };
+                let Some(schedule) = state.schedule.take() else {
+                    continue;
+                };
+                match schedule.purpose {
+                    SchedulePurpose::Compose => {
+                        let Screen::Compose(widget) = &screen else {
+                            continue;
+                        };
+                        match state.queue_message(&sender.tx, widget, Some(at)) {
+                            Ok(true) => {
+                                screen = Screen::Inbox(InboxWidget::new());
+                                state.popup =
+                                    Some(format!("Scheduled for {}", at.format("%a %d %b %H:%M")));
+                            }
+                            Ok(false) => { /* invalid, the popup says why */ }
+                            Err(err) => {
+                                tracing::error!("Failed to queue the message with error: {err}");
+                                break Ok(());
+                            }
+                        }
+                    }
+                    SchedulePurpose::Reschedule { id } => {
+                        if let Err(err) = sender.tx.send(smtp::Command::Reschedule { id, at }) {
+                            tracing::error!(
+                                "Failed to send message to SMTP thread with error: {err}"
+                            );
+                            break Ok(());
+                        }
+                    }
+                }
+                continue;
+            }
+
+            if let Some(search) = &mut state.search {
+                let Event::Key(key_event) = event else {
+                    continue;
+                };
+                match key_event.code {
+                    KeyCode::Esc => state.search = None,
+                    KeyCode::Enter => {
+                        let query = Some(search.value())
+                            .filter(|query| !query.is_empty())
+                            .or_else(|| state.inbox_state.query.clone());
+                        state.search = None;
+                        if let Some(query) = query {
+                            state.search(query);
+                        }
+                    }
+                    _ => search.input(key_event),
+                }
+                continue;
+            }
+
+            if let Some(help) = &mut state.help {
+                let Event::Key(key_event) = event else {
+                    continue;
+                };
+                match key_event.code {
+                    KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q' | '?') | KeyCode::F(1) => {
+                        state.help = None
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => help.scroll(1),
+                    KeyCode::Up | KeyCode::Char('k') => help.scroll(-1),
+                    KeyCode::PageDown | KeyCode::Char(' ') => help.scroll_page(true),
+                    KeyCode::PageUp => help.scroll_page(false),
+                    KeyCode::Home => help.scroll_to(true),
+                    KeyCode::End => help.scroll_to(false),
+                    _ => { /* no-op */ }
+                }
+                continue;
+            }
+
+            // Only keys do something, anything else just redraws
+            let Event::Key(key_event) = event else {
+                continue;
+            };
+            let (key_event, resolved) = match &mut state.palette {
+                Some(palette) => match key_event.code {
+                    KeyCode::Esc => {
+                        state.palette = None;
+                        continue;
+                    }
+                    KeyCode::Enter => {
+                        let action = palette.selected();
+                        state.palette = None;
+                        let Some(action) = action else {
+                            continue;
+                        };
+                        // As if its key was pressed, with nothing for the screen to type
+                        (KeyEvent::from(KeyCode::Null), Resolved::Action(action))
+                    }
+                    KeyCode::Down | KeyCode::Tab => {
+                        palette.select_next();
+                        continue;
+                    }
+                    KeyCode::Up | KeyCode::BackTab => {
+                        palette.select_previous();
+                        continue;
+                    }
+                    _ => {
+                        palette.input(key_event);
+                        continue;
+                    }
+                },
+                None => {
+                    let resolved = state.settings.keymap.resolve(
+                        screen.context(),
+                        &mut state.keys,
+                        key_event.into(),
+                    );
+                    (key_event, resolved)
+                }
+            };
+            let key = match resolved {
+                Resolved::Pending => continue,
+                Resolved::Action(KeyAction::UndoSend) => {
+                    if let Some(pending) = state.pending.take() {
+                        tracing::info!("Undoing send");
+                        // Whatever is being written meanwhile isn't lost
+                        if let Screen::Compose(widget) = &mut screen {
+                            if let Err(err) = state.leave_compose(&sender.tx, widget) {
+                                tracing::error!("Failed to keep the message being composed: {err}");
+                                break Ok(());
+                            }
+                        }
+                        screen = Screen::Compose(pending.widget);
+                        continue;
+                    }
+                    // Nothing to take back, e.g. the compose screen undoes typing instead
+                    None
+                }
+                Resolved::Action(KeyAction::Help) => {
+                    let help = HelpOverlay::new(&state.settings.keymap, screen.context(), &theme);
+                    state.help = Some(help);
+                    continue;
+                }
+                Resolved::Action(KeyAction::Palette) => {
+                    let palette =
+                        PaletteWidget::new(&state.settings.keymap, screen.context(), &theme);
+                    state.palette = Some(palette);
+                    continue;
+                }
+                Resolved::Action(action) => Some(action),
+                Resolved::Unbound => None,
+            };
+
             let action = match &mut screen {
                 Screen::Inbox(widget) => {
-                    // Special "pre-events"
-                    match event {
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Down,
-                            ..
-                        }) => {
+                    let Some(key) = key else {
+                        continue;
+                    };
+                    match key {
+                        KeyAction::Down => {
                             if let Err(err) = state.load_more(EMAILS_TO_LOAD) {
-                                tracing::error!("Failed to send message to IMAP thread: {err}");
+                                tracing::error!("Failed to send message to store thread: {err}");
                                 if cfg!(debug_assertions) {
                                     panic!("Channel was closed with pending messages");
                                 } else {
@@ -213,65 +1130,231 @@ pub fn run(
                                 }
                             };
                         }
-
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Enter,
-                            ..
-                        }) => {
+                        KeyAction::Open => {
                             if let Some(selected) = state.inbox_state.table.selected() {
-                                let Some(parsed_email) = state.inbox_state.inbox.get(selected)
+                                let Some(parsed_email) =
+                                    state.inbox_state.inbox.get(selected).cloned()
                                 else {
                                     tracing::warn!("Selected non-existing email, ignoring command");
                                     continue;
                                 };
                                 tracing::debug!("Parsed: {parsed_email:?}");
-                                screen = Screen::Reading(ReadingWidget::from(parsed_email.clone()));
-                                // We've handled what there is to handle, don't handle at the widget level
-                                continue;
+                                if let Err(err) = state.open(&parsed_email) {
+                                    tracing::error!(
+                                        "Failed to send message to store thread: {err}"
+                                    );
+                                    return Ok(());
+                                }
+                                let mut parsed_email = parsed_email;
+                                parsed_email.body = "Loading...".to_string();
+                                screen = Screen::Reading(ReadingWidget::from(parsed_email));
+                            }
+                            continue;
+                        }
+                        KeyAction::Search => {
+                            state.search = Some(SearchWidget::new(&state.settings.theme));
+                            continue;
+                        }
+                        KeyAction::SearchNext => {
+                            if let Some(query) = state.inbox_state.query.clone() {
+                                state.search(query);
                             }
+                            continue;
                         }
                         _ => { /* no-op */ }
                     }
 
-                    widget.handle_event(event, &mut state.inbox_state)
+                    widget.handle_action(key, &mut state.inbox_state)
                 }
                 Screen::Compose(widget) => {
-                    if let Event::Key(KeyEvent {
-                        code: KeyCode::Char('s'),
-                        modifiers: KeyModifiers::CONTROL,
-                        ..
-                    }) = event
-                    {
-                        match widget.get_partial_message() {
-                            Ok(message) => {
-                                match to_smtp.send(smtp::Command::SendMail(message)) {
-                                    Ok(_) => {
-                                        screen = Screen::Inbox(InboxWidget::new());
-                                        state.popup = Some("Successfully sent email!".to_string());
-                                    }
-                                    Err(err) => {
-                                        tracing::error!("Failed to send message to SMTP thread with error: {err}");
-                                        break Ok(());
-                                    }
-                                };
-                                // Do not pass command to the widget
-                            }
+                    if key == Some(KeyAction::Send) {
+                        if let Err(err) = widget.get_partial_message() {
+                            state.popup = Some(err.to_string());
+                            continue;
+                        }
+                        let Screen::Compose(widget) =
+                            std::mem::replace(&mut screen, Screen::Inbox(InboxWidget::new()))
+                        else {
+                            unreachable!("the compose screen is being handled");
+                        };
+                        if let Some(at) = widget.scheduled() {
+                            state.popup =
+                                Some(format!("Scheduled for {}", at.format("%a %d %b %H:%M")));
+                        }
+                        if let Err(err) = state.send(&sender.tx, widget) {
+                            tracing::error!("Failed to queue the message with error: {err}");
+                            break Ok(());
+                        }
+                        // Do not pass command to the widget
+                        continue;
+                    }
+
+                    if key == Some(KeyAction::SendLater) {
+                        state.schedule = Some(ScheduleWidget::new(
+                            SchedulePurpose::Compose,
+                            &state.settings.theme,
+                        ));
+                        continue;
+                    }
+
+                    widget.handle_key(key_event, key)
+                }
+                Screen::Reading(widget) => {
+                    if key == Some(KeyAction::Move) {
+                        let purpose = MailboxesPurpose::Move { uid: widget.uid() };
+                        if let Err(err) = state.open_mailboxes(purpose) {
+                            tracing::error!("Failed to send message to store thread: {err}");
+                            return Ok(());
+                        }
+                        screen = Screen::from(Page::Mailboxes);
+                        continue;
+                    }
+
+                    widget.handle_key(key_event, key)
+                }
+                Screen::Mailboxes(widget) => {
+                    let Some(key) = key else {
+                        continue;
+                    };
+                    if key == KeyAction::Open {
+                        match state.select_mailbox() {
+                            Ok(true) => screen = Screen::from(Page::Inbox),
+                            Ok(false) => { /* nothing selected (yet) */ }
                             Err(err) => {
-                                state.popup = Some(err.to_string());
+                                tracing::error!("Failed to send message to store thread: {err}");
+                                return Ok(());
                             }
                         }
+                        continue;
+                    }
 
+                    widget.handle_action(key, &mut state.mailboxes_state)
+                }
+                Screen::Outbox(widget) => {
+                    let Some(key) = key else {
+                        continue;
+                    };
+                    let selected = state.outbox_state.selected();
+                    let command = match (key, selected) {
+                        (KeyAction::Open, Some(queued)) => {
+                            // Not sent from under the user while it's being edited
+                            let widget = ComposeWidget::from(queued);
+                            if let Err(err) = sender.tx.send(smtp::Command::Hold(queued.id.clone()))
+                            {
+                                tracing::error!(
+                                    "Failed to send message to SMTP thread with error: {err}"
+                                );
+                                break Ok(());
+                            }
+                            screen = Screen::Compose(widget);
+                            continue;
+                        }
+                        (KeyAction::Retry, Some(queued)) => {
+                            Some(smtp::Command::Retry(queued.id.clone()))
+                        }
+                        (KeyAction::Discard, Some(queued)) => {
+                            Some(smtp::Command::Discard(queued.id.clone()))
+                        }
+                        (KeyAction::Reschedule, Some(queued)) => {
+                            let purpose = SchedulePurpose::Reschedule {
+                                id: queued.id.clone(),
+                            };
+                            state.schedule =
+                                Some(ScheduleWidget::new(purpose, &state.settings.theme));
+                            continue;
+                        }
+                        _ => None,
+                    };
+                    if let Some(command) = command {
+                        if let Err(err) = sender.tx.send(command) {
+                            tracing::error!(
+                                "Failed to send message to SMTP thread with error: {err}"
+                            );
+                            break Ok(());
+                        }
+                        continue;
+                    }
+
+                    widget.handle_action(key, &mut state.outbox_state)
+                }
+                Screen::Drafts(widget) => {
+                    let Some(key) = key else {
+                        continue;
+                    };
+                    match (key, state.drafts_state.selected()) {
+                        (KeyAction::Open, Some(draft)) => {
+                            screen = Screen::Compose(ComposeWidget::from(draft));
+                            continue;
+                        }
+                        (KeyAction::Discard, Some(draft)) => {
+                            let id = draft.id.clone();
+                            if let Err(err) = state.discard_draft(&id, true) {
+                                tracing::error!("Failed to send message to store thread: {err}");
+                                return Ok(());
+                            }
+                            state.load_drafts();
+                            continue;
+                        }
+                        _ => { /* no-op */ }
+                    }
+
+                    widget.handle_action(key, &mut state.drafts_state)
+                }
+                #[cfg(feature = "refresher")]
+                Screen::Login(widget) => {
+                    let Some(key) = key else {
+                        continue;
+                    };
+                    if key == KeyAction::Retry {
+                        if widget.has_failed() {
+                            if let Some(login) = state.login.take() {
+                                tracing::info!("Retrying login");
+                                state.login = Some(LoginState::start(login.config));
+                                *widget = LoginWidget::new();
+                            }
+                        }
                         continue;
                     }
 
-                    widget.handle_event(event)
+                    widget.handle_action(key)
                 }
-                Screen::Reading(widget) => widget.handle_event(event),
             };
 
+            if let (Screen::Compose(widget), Action::GoTo(_)) = (&mut screen, &action) {
+                if let Err(err) = state.leave_compose(&sender.tx, widget) {
+                    tracing::error!("Failed to keep the message being composed: {err}");
+                    return Ok(());
+                }
+            }
+
             match action {
-                Action::Quit => break Ok(()),
+                Action::Quit => {
+                    // Quitting doesn't take back a message which was sent
+                    if let Err(err) = state.flush_pending(&sender.tx) {
+                        tracing::error!("Failed to queue the message with error: {err}");
+                    }
+                    break Ok(());
+                }
                 Action::Tick => continue,
+                Action::GoTo(Page::Mailboxes) => {
+                    if let Err(err) = state.open_mailboxes(MailboxesPurpose::Open) {
+                        tracing::error!("Failed to send message to store thread: {err}");
+                        return Ok(());
+                    }
+                    screen = Screen::from(Page::Mailboxes);
+                }
+                Action::GoTo(page @ (Page::Outbox | Page::Scheduled)) => {
+                    state.outbox_state.show(matches!(page, Page::Scheduled));
+                    screen = Screen::from(page);
+                }
+                Action::GoTo(Page::Drafts) => {
+                    state.load_drafts();
+                    screen = Screen::from(Page::Drafts);
+                }
+                Action::GoTo(Page::Compose) => {
+                    let signature = state.settings.signature.as_deref();
+                    screen = Screen::Compose(ComposeWidget::with_signature(signature));
+                }
                 Action::GoTo(new_screen) => screen = Screen::from(new_screen),
             };
         }
@@ -0,0 +1,145 @@
+use ratatui::{
+    layout::Constraint,
+    widgets::{Block, Borders, Cell, Row, StatefulWidget, Table, TableState},
+};
+
+use crate::{
+    smtp::outbox::QueuedMessage,
+    tui::{keymap::KeyAction, theme::Theme, Action, Page},
+};
+
+pub struct OutboxState {
+    pub messages: Vec<QueuedMessage>,
+    pub table: TableState,
+    /// Only list the messages scheduled for later.
+    scheduled_only: bool,
+}
+
+impl OutboxState {
+    pub fn new() -> Self {
+        Self {
+            messages: vec![],
+            table: TableState::default().with_selected(0),
+            scheduled_only: false,
+        }
+    }
+
+    /// Switch between every queued message and only the scheduled ones.
+    pub fn show(&mut self, scheduled_only: bool) {
+        self.scheduled_only = scheduled_only;
+        self.table.select(Some(0));
+    }
+
+    fn visible(&self) -> impl Iterator<Item = &QueuedMessage> {
+        self.messages
+            .iter()
+            .filter(|queued| !self.scheduled_only || queued.scheduled.is_some())
+    }
+
+    pub fn visible_count(&self) -> usize {
+        self.visible().count()
+    }
+
+    pub fn selected(&self) -> Option<&QueuedMessage> {
+        self.table
+            .selected()
+            .and_then(|selected| self.visible().nth(selected))
+    }
+}
+
+pub struct OutboxWidget {
+    theme: Theme,
+}
+
+impl OutboxWidget {
+    pub fn new() -> Self {
+        Self {
+            theme: Theme::default(),
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = *theme;
+    }
+
+    pub fn handle_action(&mut self, action: KeyAction, state: &mut OutboxState) -> Action {
+        match action {
+            KeyAction::Back => Action::GoTo(Page::Inbox),
+            KeyAction::Down => {
+                state.table.select_next();
+                Action::Tick
+            }
+            KeyAction::Up => {
+                state.table.select_previous();
+                Action::Tick
+            }
+            KeyAction::Top => {
+                state.table.select_first();
+                Action::Tick
+            }
+            KeyAction::Bottom => {
+                state.table.select_last();
+                Action::Tick
+            }
+            _ => Action::Tick,
+        }
+    }
+}
+
+fn status(queued: &QueuedMessage) -> String {
+    match queued.next_attempt() {
+        Some(next_attempt) if queued.attempts == 0 && queued.scheduled.is_some() => {
+            format!("Scheduled {}", next_attempt.format("%a %d %b %H:%M"))
+        }
+        Some(_) if queued.attempts == 0 => "Sending".to_string(),
+        Some(next_attempt) => format!("Retry at {}", next_attempt.format("%H:%M:%S")),
+        None => "Failed".to_string(),
+    }
+}
+
+impl StatefulWidget for &mut OutboxWidget {
+    type State = OutboxState;
+
+    fn render(
+        self,
+        area: ratatui::prelude::Rect,
+        buf: &mut ratatui::prelude::Buffer,
+        state: &mut Self::State,
+    ) {
+        let header = Row::new([
+            Cell::from("Status"),
+            Cell::from("To"),
+            Cell::from("Subject"),
+            Cell::from("Error"),
+        ]);
+        let rows = state.visible().map(|queued| {
+            Row::new(vec![
+                Cell::from(status(queued)),
+                Cell::from(queued.to.clone().unwrap_or_default()),
+                Cell::from(queued.subject.clone().unwrap_or_default()),
+                Cell::from(queued.last_error.clone().unwrap_or_default()),
+            ])
+        });
+        let widths = [
+            Constraint::Length(22),
+            Constraint::Fill(2),
+            Constraint::Fill(2),
+            Constraint::Fill(3),
+        ];
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(self.theme.border)
+                    .title(if state.scheduled_only {
+                        "Scheduled"
+                    } else {
+                        "Outbox"
+                    }),
+            )
+            .row_highlight_style(self.theme.selected);
+
+        StatefulWidget::render(table, area, buf, &mut state.table);
+    }
+}
@@ -0,0 +1,108 @@
+use itertools::Itertools;
+use ratatui::{
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+
+use crate::tui::{
+    combo::KeyCombo,
+    keymap::{Context, Keymap},
+    theme::Theme,
+};
+
+/// Every key of a screen, grouped and scrollable, on top of everything.
+pub struct HelpOverlay {
+    title: String,
+    lines: Vec<Line<'static>>,
+    /// The first line shown, brought back within the text when rendered.
+    offset: usize,
+    /// Lines shown at once the last time it was rendered, to scroll by pages.
+    height: usize,
+    theme: Theme,
+}
+
+impl HelpOverlay {
+    pub fn new(keymap: &Keymap, context: Context, theme: &Theme) -> Self {
+        let overview = keymap.overview(context);
+        let width = overview
+            .iter()
+            .flat_map(|(_, entries)| entries)
+            .map(|entry| keys(entry.keys.iter()).chars().count())
+            .max()
+            .unwrap_or_default();
+
+        let mut lines = vec![];
+        for (title, entries) in overview {
+            if !lines.is_empty() {
+                lines.push(Line::default());
+            }
+            lines.push(Line::styled(
+                title,
+                Style::new().add_modifier(Modifier::BOLD),
+            ));
+            for entry in entries {
+                let keys = match entry.keys.as_slice() {
+                    [] => "(not bound)".to_string(),
+                    bound => keys(bound.iter()),
+                };
+                lines.push(Line::from(vec![
+                    Span::raw(format!("  {keys:<width$}  ")),
+                    Span::raw(entry.label),
+                    Span::styled(
+                        format!("  {}", entry.action.name()),
+                        Style::new().add_modifier(Modifier::DIM),
+                    ),
+                ]));
+            }
+        }
+
+        Self {
+            title: format!("Keys in {}, Esc to close", context.name()),
+            lines,
+            offset: 0,
+            height: 0,
+            theme: *theme,
+        }
+    }
+
+    /// Move by `lines`, down when positive.
+    pub fn scroll(&mut self, lines: isize) {
+        self.offset = self.offset.saturating_add_signed(lines);
+    }
+
+    pub fn scroll_page(&mut self, down: bool) {
+        let page = self.height.max(1) as isize;
+        self.scroll(if down { page } else { -page });
+    }
+
+    pub fn scroll_to(&mut self, top: bool) {
+        self.offset = if top { 0 } else { usize::MAX };
+    }
+}
+
+fn keys<'k>(mut keys: impl Iterator<Item = &'k KeyCombo>) -> String {
+    keys.join(", ")
+}
+
+impl Widget for &mut HelpOverlay {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let block = Block::bordered()
+            .title(self.title.as_str())
+            .border_style(self.theme.border);
+        self.height = block.inner(area).height as usize;
+        self.offset = self
+            .offset
+            .min(self.lines.len().saturating_sub(self.height));
+
+        Clear.render(area, buf);
+        Paragraph::new(self.lines.clone())
+            .block(block)
+            .scroll((self.offset as u16, 0))
+            .render(area, buf);
+    }
+}
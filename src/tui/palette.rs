@@ -0,0 +1,183 @@
+use crossterm::event::KeyEvent;
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListState, StatefulWidget, Widget},
+};
+
+use crate::tui::{
+    focus::FocusStyle,
+    keymap::{Context, Entry, KeyAction, Keymap},
+    line::LineWidget,
+    popup::centered,
+    theme::Theme,
+};
+
+/// Runs any action of the screen by name, picked by typing some of it.
+pub struct PaletteWidget<'w> {
+    input: LineWidget<'w>,
+    entries: Vec<Entry>,
+    /// Indices into `entries` matching what's typed, best first.
+    matches: Vec<usize>,
+    selected: usize,
+    theme: Theme,
+}
+
+impl<'w> PaletteWidget<'w> {
+    pub fn new(keymap: &Keymap, context: Context, theme: &Theme) -> Self {
+        let mut input = LineWidget::new("Run a command");
+        input.focused(theme);
+        let entries = keymap
+            .overview(context)
+            .into_iter()
+            .flat_map(|(_, entries)| entries)
+            .filter(|entry| entry.action != KeyAction::Palette)
+            .collect::<Vec<_>>();
+        Self {
+            input,
+            matches: (0..entries.len()).collect(),
+            entries,
+            selected: 0,
+            theme: *theme,
+        }
+    }
+
+    pub fn input(&mut self, event: KeyEvent) {
+        if !self.input.input(event) {
+            return;
+        }
+        let query = self.input.as_ref().lines().join("");
+        let mut scored = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| {
+                let score = fuzzy_score(&query, entry.label)
+                    .max(fuzzy_score(&query, entry.action.name()))?;
+                Some((i, score))
+            })
+            .collect::<Vec<_>>();
+        // Stable, ties keep the order of the help
+        scored.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        self.matches = scored.into_iter().map(|(i, _)| i).collect();
+        self.selected = 0;
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + 1) % self.matches.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + self.matches.len() - 1) % self.matches.len();
+        }
+    }
+
+    /// The action to run, if anything matches.
+    pub fn selected(&self) -> Option<KeyAction> {
+        let i = *self.matches.get(self.selected)?;
+        Some(self.entries[i].action)
+    }
+}
+
+/// How well `query` matches `candidate`, if all of its characters are found there in the
+/// same order. Characters next to each other and at the start of words count for more.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<u32> {
+    let candidate = candidate.chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous = None;
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let found = (next..candidate.len())
+            .find(|&i| candidate[i].to_lowercase().eq(wanted.to_lowercase()))?;
+        score += 1;
+        if found == 0 || matches!(candidate[found - 1], ' ' | '_' | '-') {
+            score += 3;
+        }
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 2;
+        }
+        previous = Some(found);
+        next = found + 1;
+    }
+    Some(score)
+}
+
+impl<'w> Widget for &PaletteWidget<'w> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let popup_area = centered(area, 64, 3 + 2 + self.matches.len().max(1) as u16);
+        Clear.render(popup_area, buf);
+        let [input_area, list_area] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(popup_area);
+        self.input.render(input_area, buf);
+
+        let items = self.matches.iter().map(|&i| {
+            let entry = &self.entries[i];
+            let mut spans = vec![Span::raw(entry.label)];
+            if let Some(key) = entry.keys.first() {
+                spans.push(Span::styled(
+                    format!("  {key}"),
+                    Style::new().add_modifier(Modifier::DIM),
+                ));
+            }
+            Line::from(spans)
+        });
+        let block = Block::bordered().border_style(self.theme.border);
+        let block = if self.matches.is_empty() {
+            block.title("No match")
+        } else {
+            block
+        };
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(self.theme.selected);
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        StatefulWidget::render(list, list_area, buf, &mut state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::{KeyCode, KeyEvent};
+
+    use super::{fuzzy_score, PaletteWidget};
+    use crate::tui::{
+        keymap::{Context, KeyAction, Keymap},
+        theme::Theme,
+    };
+
+    #[test]
+    fn commands_are_found() {
+        assert_eq!(fuzzy_score("sl", "Send later"), Some(8));
+        assert!(fuzzy_score("next", "Next match") > fuzzy_score("next", "New text"));
+        assert_eq!(fuzzy_score("xyz", "Send"), None);
+        assert_eq!(fuzzy_score("", "Send"), Some(0));
+
+        let mut palette =
+            PaletteWidget::new(&Keymap::default(), Context::Compose, &Theme::default());
+        assert_eq!(palette.selected(), Some(KeyAction::Send));
+        for c in "later".chars() {
+            palette.input(KeyEvent::from(KeyCode::Char(c)));
+        }
+        assert_eq!(palette.selected(), Some(KeyAction::SendLater));
+        for c in "undo".chars() {
+            palette.input(KeyEvent::from(KeyCode::Char(c)));
+        }
+        assert_eq!(palette.selected(), None);
+
+        let palette = PaletteWidget::new(&Keymap::default(), Context::Inbox, &Theme::default());
+        assert!(
+            !palette
+                .entries
+                .iter()
+                .any(|entry| entry.action == KeyAction::Palette),
+            "the palette doesn't open itself"
+        );
+    }
+}
@@ -1,23 +1,38 @@
 use ratatui::{
     layout::Rect,
-    style::{Style, Stylize},
     text::Line,
     widgets::{Block, Borders, Clear, Paragraph, Widget, Wrap},
 };
 
+use crate::tui::theme::Theme;
+
 const DISMISS_MESSAGE: &str = "Press Enter to dismiss";
 
+/// Where something drawn on top of `area` goes: centered horizontally, a third of the way
+/// down, and cut to fit.
+pub fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + area.height / 3,
+        width,
+        height: height.min(area.height - area.height / 3),
+    }
+}
+
 #[derive(Debug)]
 pub struct Popup {
     message: String,
     dismissable: bool,
+    theme: Theme,
 }
 
 impl Popup {
-    pub const fn new(message: String, dismissable: bool) -> Self {
+    pub const fn new(message: String, dismissable: bool, theme: Theme) -> Self {
         Self {
             message,
             dismissable,
+            theme,
         }
     }
 }
@@ -32,13 +47,12 @@ impl Widget for Popup {
         } else {
             (self.message.len() + 2) as u16
         };
+        // Long messages wrap instead of running off the screen
+        let width = message_with_padding.min(area.width);
+        let lines = (self.message.len() as u16).div_ceil(width.saturating_sub(2).max(1));
+        let height = lines + 2 + u16::from(self.dismissable);
 
-        let popup_area = Rect {
-            x: area.width / 2 - (message_with_padding / 2),
-            y: area.height / 3,
-            width: message_with_padding,
-            height: if self.dismissable { 4 } else { 3 },
-        };
+        let popup_area = centered(area, width, height);
         Clear.render(popup_area, buf);
 
         let text = if self.dismissable {
@@ -49,12 +63,52 @@ impl Widget for Popup {
 
         Paragraph::new(text)
             .wrap(Wrap { trim: true })
-            .style(Style::new().yellow())
+            .style(self.theme.popup)
             .block(
                 Block::new()
                     .borders(Borders::ALL)
-                    .border_style(Style::new().red()),
+                    .border_style(self.theme.popup_border),
             )
             .render(popup_area, buf);
     }
 }
+
+/// A message in the bottom right corner which doesn't get in the way.
+#[derive(Debug)]
+pub struct Notice {
+    message: String,
+    theme: Theme,
+}
+
+impl Notice {
+    pub const fn new(message: String, theme: Theme) -> Self {
+        Self { message, theme }
+    }
+}
+
+impl Widget for Notice {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let width = ((self.message.len() + 2) as u16).min(area.width);
+        let height = 3.min(area.height);
+        let notice_area = Rect {
+            x: area.x + area.width - width,
+            // Right above the help line
+            y: (area.y + area.height).saturating_sub(height + 1),
+            width,
+            height,
+        };
+        Clear.render(notice_area, buf);
+
+        Paragraph::new(self.message)
+            .style(self.theme.notice)
+            .block(
+                Block::new()
+                    .borders(Borders::ALL)
+                    .border_style(self.theme.border),
+            )
+            .render(notice_area, buf);
+    }
+}
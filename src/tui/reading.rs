@@ -1,17 +1,14 @@
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::{
     layout::{Constraint, Direction, Layout},
     widgets::Widget,
 };
+use tui_textarea::{CursorMove, TextArea};
 
 use crate::{
-    imap::ParsedEmail,
+    store::ParsedEmail,
     tui::{
-        body::BodyWidget,
-        combo::KeyCombo,
-        focus::FocusStyle,
-        help::{HasHelp, HelpWidget},
-        line::LineWidget,
+        body::BodyWidget, focus::FocusStyle, keymap::KeyAction, line::LineWidget, theme::Theme,
         Action, Page,
     },
 };
@@ -61,6 +58,7 @@ impl Focus {
 }
 
 pub struct ReadingWidget<'w> {
+    uid: u32,
     focused: Focus,
 
     to: LineWidget<'w>,
@@ -68,11 +66,12 @@ pub struct ReadingWidget<'w> {
     bcc: LineWidget<'w>,
     subject: LineWidget<'w>,
     body: BodyWidget<'w>,
-    help: HelpWidget<'w>,
+    theme: Theme,
 }
 
 impl ReadingWidget<'_> {
     pub fn new(
+        uid: u32,
         from: String,
         cc: Vec<String>,
         bcc: Vec<String>,
@@ -80,6 +79,7 @@ impl ReadingWidget<'_> {
         body: String,
     ) -> Self {
         Self {
+            uid,
             to: LineWidget::with_contents("From", vec![from]),
             cc: LineWidget::with_contents("Cc", cc),
             bcc: LineWidget::with_contents("Bcc", bcc),
@@ -90,82 +90,93 @@ impl ReadingWidget<'_> {
                     .map(ToString::to_string)
                     .collect(),
             ),
-            help: Self::help(),
             focused: Focus::From,
+            theme: Theme::default(),
         }
     }
-}
 
-impl From<ParsedEmail> for ReadingWidget<'_> {
-    fn from(value: ParsedEmail) -> Self {
-        Self::new(value.from, value.cc, value.bcc, value.subject, value.body)
+    pub fn uid(&self) -> u32 {
+        self.uid
     }
 }
 
-impl<'w> HasHelp for ReadingWidget<'w> {
-    fn help<'h>() -> super::help::HelpWidget<'h> {
-        HelpWidget::new(vec![
-            (KeyCombo::new().with_code(KeyCode::Tab), "Next"),
-            (
-                KeyCombo::new()
-                    .with_code(KeyCode::Tab)
-                    .with_modifier(KeyModifiers::SHIFT),
-                "Prev",
-            ),
-            (KeyCombo::new().with_code(KeyCode::Esc), "Cancel"),
-        ])
+impl From<ParsedEmail> for ReadingWidget<'_> {
+    fn from(value: ParsedEmail) -> Self {
+        Self::new(
+            value.uid,
+            value.from,
+            value.cc,
+            value.bcc,
+            value.subject,
+            value.body,
+        )
     }
 }
 
 impl<'w> ReadingWidget<'w> {
-    pub fn handle_event(&mut self, event: Event) -> Action {
-        match event {
-            Event::Key(key_event) => self.handle_key_event(key_event),
-            _ => Action::Tick,
-        }
-    }
-
-    fn handle_key_event(
-        &mut self,
-        event @ KeyEvent {
-            code, modifiers, ..
-        }: KeyEvent,
-    ) -> Action {
+    /// Handle a key press, `action` being what the keymap binds it to.
+    pub fn handle_key(&mut self, event: KeyEvent, action: Option<KeyAction>) -> Action {
         tracing::debug!("{:?}", self.cc.as_ref());
 
         let cc_is_empty = self.cc.as_ref().lines().is_empty() || self.cc.as_ref().is_empty();
         let bcc_is_empty = self.bcc.as_ref().lines().is_empty() || self.cc.as_ref().is_empty();
 
-        match (code, modifiers) {
-            (crossterm::event::KeyCode::Esc, _) => return Action::GoTo(Page::Inbox),
-            (crossterm::event::KeyCode::Tab, _) => {
+        let motion = match action {
+            Some(KeyAction::Back) => return Action::GoTo(Page::Inbox),
+            Some(KeyAction::NextField) => {
                 self.focused.next(cc_is_empty, bcc_is_empty);
                 self.update_focused();
+                return Action::Tick;
             }
-            (crossterm::event::KeyCode::BackTab, _) => {
+            Some(KeyAction::PreviousField) => {
                 self.focused.previous(cc_is_empty, bcc_is_empty);
                 self.update_focused();
+                return Action::Tick;
             }
-            (crossterm::event::KeyCode::Char(_), _)
-            | (crossterm::event::KeyCode::Backspace, _)
-            | (crossterm::event::KeyCode::Delete, _) => {
-                // Ignore editing inputs
-                // this could be placed in the underlying component too but
-                // the logic there would become more complicated, here is good enough
-            }
+            Some(KeyAction::Down) => CursorMove::Down,
+            Some(KeyAction::Up) => CursorMove::Up,
+            Some(KeyAction::Top) => CursorMove::Top,
+            Some(KeyAction::Bottom) => CursorMove::Bottom,
             _ => {
-                match self.focused {
-                    Focus::From => self.to.input(event),
-                    Focus::Cc => self.cc.input(event),
-                    Focus::Bcc => self.bcc.input(event),
-                    Focus::Subject => self.subject.input(event),
-                    Focus::Body => self.body.as_mut().input(event),
-                };
+                match event.code {
+                    KeyCode::Char(_) | KeyCode::Backspace | KeyCode::Delete => {
+                        // Ignore editing inputs
+                        // this could be placed in the underlying component too but
+                        // the logic there would become more complicated, here is good enough
+                    }
+                    _ => {
+                        match self.focused {
+                            Focus::From => self.to.input(event),
+                            Focus::Cc => self.cc.input(event),
+                            Focus::Bcc => self.bcc.input(event),
+                            Focus::Subject => self.subject.input(event),
+                            Focus::Body => self.body.as_mut().input(event),
+                        };
+                    }
+                }
+                return Action::Tick;
             }
-        }
+        };
+        self.focused_mut().move_cursor(motion);
         Action::Tick
     }
 
+    fn focused_mut(&mut self) -> &mut TextArea<'w> {
+        match self.focused {
+            Focus::From => self.to.as_mut(),
+            Focus::Cc => self.cc.as_mut(),
+            Focus::Bcc => self.bcc.as_mut(),
+            Focus::Subject => self.subject.as_mut(),
+            Focus::Body => self.body.as_mut(),
+        }
+    }
+
+    /// Style the fields with `theme`, the focused one stands out.
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = *theme;
+        self.update_focused();
+    }
+
     fn update_focused(&mut self) {
         let parts: [(Focus, &mut dyn FocusStyle); 5] = [
             (Focus::From, &mut self.to),
@@ -176,9 +187,9 @@ impl<'w> ReadingWidget<'w> {
         ];
         for (focus, focusable) in parts.into_iter() {
             if self.focused == focus {
-                focusable.focused();
+                focusable.focused(&self.theme);
             } else {
-                focusable.unfocused();
+                focusable.unfocused(&self.theme);
             }
         }
     }
@@ -202,7 +213,6 @@ impl<'w> Widget for &ReadingWidget<'w> {
                     Constraint::Length(3),
                     Constraint::Length(3),
                     Constraint::Min(5),
-                    Constraint::Length(1),
                 ]);
                 let chunks = layout.constraints(constraints).split(area);
                 self.to.render(chunks[0], buf);
@@ -210,47 +220,37 @@ impl<'w> Widget for &ReadingWidget<'w> {
                 self.bcc.render(chunks[2], buf);
                 self.subject.render(chunks[3], buf);
                 self.body.render(chunks[4], buf);
-                self.help.render(chunks[5], buf);
             }
             (false, true) => {
                 constraints.extend_from_slice(&[
                     Constraint::Length(3),
                     Constraint::Length(3),
                     Constraint::Min(5),
-                    Constraint::Length(1),
                 ]);
                 let chunks = layout.constraints(constraints).split(area);
                 self.to.render(chunks[0], buf);
                 self.cc.render(chunks[1], buf);
                 self.subject.render(chunks[2], buf);
                 self.body.render(chunks[3], buf);
-                self.help.render(chunks[4], buf);
             }
             (true, false) => {
                 constraints.extend_from_slice(&[
                     Constraint::Length(3),
                     Constraint::Length(3),
                     Constraint::Min(5),
-                    Constraint::Length(1),
                 ]);
                 let chunks = layout.constraints(constraints).split(area);
                 self.to.render(chunks[0], buf);
                 self.bcc.render(chunks[1], buf);
                 self.subject.render(chunks[2], buf);
                 self.body.render(chunks[3], buf);
-                self.help.render(chunks[4], buf);
             }
             (true, true) => {
-                constraints.extend_from_slice(&[
-                    Constraint::Length(3),
-                    Constraint::Min(5),
-                    Constraint::Length(1),
-                ]);
+                constraints.extend_from_slice(&[Constraint::Length(3), Constraint::Min(5)]);
                 let chunks = layout.constraints(constraints).split(area);
                 self.to.render(chunks[0], buf);
                 self.subject.render(chunks[1], buf);
                 self.body.render(chunks[2], buf);
-                self.help.render(chunks[3], buf);
             }
         }
     }
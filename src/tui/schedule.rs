@@ -0,0 +1,206 @@
+use chrono::{DateTime, Datelike, Days, Duration, Local, NaiveDate, NaiveTime, TimeZone, Weekday};
+use crossterm::event::KeyEvent;
+use ratatui::{
+    layout::Rect,
+    widgets::{Clear, Widget},
+};
+
+use crate::tui::{focus::FocusStyle, line::LineWidget, popup::centered, theme::Theme};
+
+/// Time used when only a day is given, the start of a working day.
+const DEFAULT_TIME: NaiveTime = match NaiveTime::from_hms_opt(9, 0, 0) {
+    Some(time) => time,
+    None => unreachable!(),
+};
+
+/// When to send a message, absolute (`2024-05-02 14:30`) or relative (`in 2h`,
+/// `tomorrow 9:00`, `friday`, `17:00`). Only times after `now` are accepted.
+pub fn parse_when(input: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let input = input.trim().to_lowercase();
+    let words = input.split_whitespace().collect::<Vec<_>>();
+    let when = match words.as_slice() {
+        ["in", amount, unit] => now + relative(amount, unit)?,
+        ["in", amount] => {
+            let split = amount.find(|c: char| !c.is_ascii_digit())?;
+            now + relative(&amount[..split], &amount[split..])?
+        }
+        [word] => match time(word) {
+            // A bare time is its next occurrence
+            Some(time) => {
+                let today = at(now.date_naive(), time)?;
+                if today > now {
+                    today
+                } else {
+                    at(now.date_naive().checked_add_days(Days::new(1))?, time)?
+                }
+            }
+            None => at(day(word, now.date_naive())?, DEFAULT_TIME)?,
+        },
+        [day_word, time_word] => at(day(day_word, now.date_naive())?, time(time_word)?)?,
+        _ => return None,
+    };
+    (when > now).then_some(when)
+}
+
+fn relative(amount: &str, unit: &str) -> Option<Duration> {
+    let amount = amount.parse::<i64>().ok()?;
+    match unit {
+        "m" | "min" | "mins" | "minute" | "minutes" => Duration::try_minutes(amount),
+        "h" | "hour" | "hours" => Duration::try_hours(amount),
+        "d" | "day" | "days" => Duration::try_days(amount),
+        _ => None,
+    }
+}
+
+fn day(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match word {
+        "today" => Some(today),
+        "tomorrow" => today.checked_add_days(Days::new(1)),
+        _ => match word.parse::<Weekday>() {
+            // Always in the future, "monday" on a Monday is the next one
+            Ok(weekday) => {
+                let ahead = (weekday.num_days_from_monday() + 7
+                    - today.weekday().num_days_from_monday()
+                    - 1)
+                    % 7
+                    + 1;
+                today.checked_add_days(Days::new(ahead.into()))
+            }
+            Err(_) => NaiveDate::parse_from_str(word, "%Y-%m-%d").ok(),
+        },
+    }
+}
+
+fn time(word: &str) -> Option<NaiveTime> {
+    let (word, offset) = if let Some(word) = word.strip_suffix("am") {
+        (word, Some(0))
+    } else if let Some(word) = word.strip_suffix("pm") {
+        (word, Some(12))
+    } else {
+        (word, None)
+    };
+    let (hour, minute) = match word.split_once(':') {
+        Some((hour, minute)) => (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?),
+        // Without a colon, only "9am" and the like make sense
+        None if offset.is_some() => (word.parse::<u32>().ok()?, 0),
+        None => return None,
+    };
+    let hour = match offset {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(offset) => hour % 12 + offset,
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+fn at(date: NaiveDate, time: NaiveTime) -> Option<DateTime<Local>> {
+    Local.from_local_datetime(&date.and_time(time)).earliest()
+}
+
+/// What the time asked for is used for.
+pub enum SchedulePurpose {
+    /// Send the message being composed later.
+    Compose,
+    /// Move a queued message to a new time.
+    Reschedule { id: String },
+}
+
+/// Asks for a time, on top of the current screen.
+pub struct ScheduleWidget<'w> {
+    pub purpose: SchedulePurpose,
+    input: LineWidget<'w>,
+}
+
+impl<'w> ScheduleWidget<'w> {
+    pub fn new(purpose: SchedulePurpose, theme: &Theme) -> Self {
+        let mut input = LineWidget::new("Send at (e.g. tomorrow 9:00, in 2h, 2024-05-02 14:30)");
+        input.focused(theme);
+        Self { purpose, input }
+    }
+
+    pub fn input(&mut self, event: KeyEvent) {
+        self.input.input(event);
+    }
+
+    pub fn when(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        parse_when(&self.value(), now)
+    }
+
+    pub fn value(&self) -> String {
+        self.input.as_ref().lines().join("")
+    }
+}
+
+impl<'w> Widget for &ScheduleWidget<'w> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let popup_area = centered(area, 64, 3);
+        Clear.render(popup_area, buf);
+        self.input.render(popup_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, Local, NaiveDate, TimeZone};
+
+    use super::parse_when;
+
+    fn local(date: (i32, u32, u32), time: (u32, u32)) -> DateTime<Local> {
+        let naive = NaiveDate::from_ymd_opt(date.0, date.1, date.2)
+            .unwrap()
+            .and_hms_opt(time.0, time.1, 0)
+            .unwrap();
+        Local.from_local_datetime(&naive).earliest().unwrap()
+    }
+
+    #[test]
+    fn times_are_understood() {
+        // A Wednesday evening
+        let now = local((2024, 5, 1), (20, 15));
+
+        assert_eq!(
+            parse_when("tomorrow 9:00", now),
+            Some(local((2024, 5, 2), (9, 0)))
+        );
+        assert_eq!(
+            parse_when("Tomorrow", now),
+            Some(local((2024, 5, 2), (9, 0)))
+        );
+        assert_eq!(parse_when("9am", now), Some(local((2024, 5, 2), (9, 0))));
+        assert_eq!(
+            parse_when("21:30", now),
+            Some(local((2024, 5, 1), (21, 30)))
+        );
+        assert_eq!(parse_when("today 5:30pm", now), None, "already in the past");
+        assert_eq!(parse_when("friday", now), Some(local((2024, 5, 3), (9, 0))));
+        assert_eq!(
+            parse_when("wednesday 8:00", now),
+            Some(local((2024, 5, 8), (8, 0)))
+        );
+        assert_eq!(
+            parse_when("2024-06-10 14:30", now),
+            Some(local((2024, 6, 10), (14, 30)))
+        );
+        assert_eq!(
+            parse_when("in 2h", now),
+            Some(local((2024, 5, 1), (22, 15)))
+        );
+        assert_eq!(
+            parse_when("in 30 minutes", now),
+            Some(local((2024, 5, 1), (20, 45)))
+        );
+        assert_eq!(
+            parse_when("in 1 day", now),
+            Some(local((2024, 5, 2), (20, 15)))
+        );
+
+        assert_eq!(parse_when("", now), None);
+        assert_eq!(parse_when("whenever", now), None);
+        assert_eq!(parse_when("13pm", now), None);
+        assert_eq!(parse_when("25:00", now), None);
+        assert_eq!(parse_when("in -2h", now), None);
+    }
+}
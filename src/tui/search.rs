@@ -0,0 +1,39 @@
+use crossterm::event::KeyEvent;
+use ratatui::{
+    layout::Rect,
+    widgets::{Clear, Widget},
+};
+
+use crate::tui::{focus::FocusStyle, line::LineWidget, popup::centered, theme::Theme};
+
+/// Asks what to look for in the inbox, on top of it.
+pub struct SearchWidget<'w> {
+    input: LineWidget<'w>,
+}
+
+impl<'w> SearchWidget<'w> {
+    pub fn new(theme: &Theme) -> Self {
+        let mut input = LineWidget::new("Search (author or subject, empty for the last search)");
+        input.focused(theme);
+        Self { input }
+    }
+
+    pub fn input(&mut self, event: KeyEvent) {
+        self.input.input(event);
+    }
+
+    pub fn value(&self) -> String {
+        self.input.as_ref().lines().join("")
+    }
+}
+
+impl<'w> Widget for &SearchWidget<'w> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let popup_area = centered(area, 64, 3);
+        Clear.render(popup_area, buf);
+        self.input.render(popup_area, buf);
+    }
+}
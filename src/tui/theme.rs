@@ -0,0 +1,403 @@
+//! How the TUI is colored: a built-in theme, possibly adjusted in the configuration, brought down
+//! to the colors the terminal can show.
+
+use std::{env, fmt};
+
+use ratatui::style::{Color, Modifier, Style};
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+/// The styles of everything the TUI draws.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// Borders of the field being typed in, its title is also bold and its border thicker.
+    pub focused: Style,
+    /// Every other border.
+    pub border: Style,
+    /// The selected row of a list.
+    pub selected: Style,
+    /// Messages which weren't read yet.
+    pub unread: Style,
+    /// The help line at the bottom.
+    pub help: Style,
+    pub popup: Style,
+    pub popup_border: Style,
+    pub notice: Style,
+}
+
+/// The themes to start from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuiltinTheme {
+    #[default]
+    Dark,
+    Light,
+    /// No colors at all, only bold and reversed text, readable on any background.
+    HighContrast,
+}
+
+impl BuiltinTheme {
+    pub fn theme(self) -> Theme {
+        let plain = Style::new();
+        match self {
+            BuiltinTheme::Dark => Theme {
+                focused: plain.fg(Color::Blue),
+                border: plain,
+                selected: plain.bg(Color::Blue).fg(Color::White),
+                unread: plain.add_modifier(Modifier::BOLD),
+                help: plain.fg(Color::White).bg(Color::DarkGray),
+                popup: plain.fg(Color::Yellow),
+                popup_border: plain.fg(Color::Red),
+                notice: plain.fg(Color::Yellow),
+            },
+            // Yellow and white text get lost on a light background
+            BuiltinTheme::Light => Theme {
+                focused: plain.fg(Color::Blue),
+                border: plain,
+                selected: plain.bg(Color::Blue).fg(Color::White),
+                unread: plain.add_modifier(Modifier::BOLD),
+                help: plain.fg(Color::Black).bg(Color::Gray),
+                popup: plain.fg(Color::Black),
+                popup_border: plain.fg(Color::Red),
+                notice: plain.fg(Color::Magenta),
+            },
+            BuiltinTheme::HighContrast => Theme {
+                focused: plain.add_modifier(Modifier::BOLD),
+                border: plain,
+                selected: plain.add_modifier(Modifier::REVERSED | Modifier::BOLD),
+                unread: plain.add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                help: plain.add_modifier(Modifier::REVERSED),
+                popup: plain.add_modifier(Modifier::BOLD),
+                popup_border: plain.add_modifier(Modifier::BOLD),
+                notice: plain.add_modifier(Modifier::BOLD),
+            },
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        BuiltinTheme::default().theme()
+    }
+}
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ColorDepth {
+    #[serde(rename = "16")]
+    Ansi16,
+    #[serde(rename = "256")]
+    Ansi256,
+    #[serde(rename = "truecolor")]
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Guessed from `COLORTERM` and `TERM`, the way most terminal programs do.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if matches!(colorterm.as_str(), "truecolor" | "24bit") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+}
+
+/// xterm's default values for the 16 basic colors.
+const ANSI: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// The levels of every channel in the 6x6x6 cube of the 256 colors.
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    [(r1, r2), (g1, g2), (b1, b2)]
+        .into_iter()
+        .map(|(a, b)| u32::from(a.abs_diff(b)).pow(2))
+        .sum()
+}
+
+/// What the 256 color `index` looks like.
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI[usize::from(index)].1,
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE[usize::from(index / 36)],
+                CUBE[usize::from(index / 6 % 6)],
+                CUBE[usize::from(index % 6)],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (index - 232);
+            (level, level, level)
+        }
+    }
+}
+
+/// The closest of the 256 colors, from the cube or the grays.
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |value: u8| {
+        (0..CUBE.len())
+            .min_by_key(|&i| CUBE[i].abs_diff(value))
+            .expect("the cube has levels") as u8
+    };
+    let cube = 16 + 36 * level(rgb.0) + 6 * level(rgb.1) + level(rgb.2);
+    let average = ((u16::from(rgb.0) + u16::from(rgb.1) + u16::from(rgb.2)) / 3) as u8;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23);
+    if distance(indexed_rgb(gray), rgb) < distance(indexed_rgb(cube), rgb) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn nearest_16(rgb: (u8, u8, u8)) -> Color {
+    ANSI.iter()
+        .min_by_key(|(_, ansi)| distance(*ansi, rgb))
+        .map(|(color, _)| *color)
+        .expect("there are 16 colors")
+}
+
+fn adapt_color(color: Color, depth: ColorDepth) -> Color {
+    match (depth, color) {
+        (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(nearest_256((r, g, b))),
+        (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => nearest_16((r, g, b)),
+        (ColorDepth::Ansi16, Color::Indexed(index)) => nearest_16(indexed_rgb(index)),
+        _ => color,
+    }
+}
+
+fn adapt_style(style: Style, depth: ColorDepth) -> Style {
+    Style {
+        fg: style.fg.map(|color| adapt_color(color, depth)),
+        bg: style.bg.map(|color| adapt_color(color, depth)),
+        ..style
+    }
+}
+
+impl Theme {
+    /// The same theme with only the colors `depth` allows, the closest ones to those asked for.
+    pub fn adapt(self, depth: ColorDepth) -> Self {
+        let adapt = |style| adapt_style(style, depth);
+        Self {
+            focused: adapt(self.focused),
+            border: adapt(self.border),
+            selected: adapt(self.selected),
+            unread: adapt(self.unread),
+            help: adapt(self.help),
+            popup: adapt(self.popup),
+            popup_border: adapt(self.popup_border),
+            notice: adapt(self.notice),
+        }
+    }
+}
+
+/// A color written like `blue`, `light-red`, `#005f87` or `24` for one of the 256 colors.
+struct ThemeColor(Color);
+
+impl<'de> Deserialize<'de> for ThemeColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let color = String::deserialize(deserializer)?;
+        color.parse().map(ThemeColor).map_err(|_| {
+            de::Error::custom(format!(
+                "unknown color `{color}`, expected a name like `blue`, `#rrggbb` or 0 to 255"
+            ))
+        })
+    }
+}
+
+/// Changes to one of a theme's styles, what's left out stays as it was.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleConfig {
+    fg: Option<ThemeColor>,
+    bg: Option<ThemeColor>,
+    bold: Option<bool>,
+    italic: Option<bool>,
+    underlined: Option<bool>,
+    reversed: Option<bool>,
+}
+
+impl StyleConfig {
+    fn apply(self, style: &mut Style) {
+        if let Some(ThemeColor(fg)) = self.fg {
+            *style = style.fg(fg);
+        }
+        if let Some(ThemeColor(bg)) = self.bg {
+            *style = style.bg(bg);
+        }
+        for (enabled, modifier) in [
+            (self.bold, Modifier::BOLD),
+            (self.italic, Modifier::ITALIC),
+            (self.underlined, Modifier::UNDERLINED),
+            (self.reversed, Modifier::REVERSED),
+        ] {
+            match enabled {
+                Some(true) => *style = style.add_modifier(modifier),
+                Some(false) => *style = style.remove_modifier(modifier),
+                None => {}
+            }
+        }
+    }
+}
+
+/// A theme of our own, e.g. `{ "base": "light", "selected": { "bg": "#005f87" } }`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeConfig {
+    #[serde(default)]
+    base: BuiltinTheme,
+    focused: Option<StyleConfig>,
+    border: Option<StyleConfig>,
+    selected: Option<StyleConfig>,
+    unread: Option<StyleConfig>,
+    help: Option<StyleConfig>,
+    popup: Option<StyleConfig>,
+    popup_border: Option<StyleConfig>,
+    notice: Option<StyleConfig>,
+}
+
+impl From<ThemeConfig> for Theme {
+    fn from(config: ThemeConfig) -> Self {
+        let mut theme = config.base.theme();
+        for (changes, style) in [
+            (config.focused, &mut theme.focused),
+            (config.border, &mut theme.border),
+            (config.selected, &mut theme.selected),
+            (config.unread, &mut theme.unread),
+            (config.help, &mut theme.help),
+            (config.popup, &mut theme.popup),
+            (config.popup_border, &mut theme.popup_border),
+            (config.notice, &mut theme.notice),
+        ] {
+            if let Some(changes) = changes {
+                changes.apply(style);
+            }
+        }
+        theme
+    }
+}
+
+/// The name of a built-in theme, or one of our own.
+impl<'de> Deserialize<'de> for Theme {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ThemeVisitor;
+
+        impl<'de> Visitor<'de> for ThemeVisitor {
+            type Value = Theme;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("the name of a built-in theme or a theme")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Theme, E> {
+                BuiltinTheme::deserialize(de::value::StrDeserializer::<E>::new(value))
+                    .map(BuiltinTheme::theme)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Theme, A::Error> {
+                ThemeConfig::deserialize(MapAccessDeserializer::new(map)).map(Theme::from)
+            }
+        }
+
+        deserializer.deserialize_any(ThemeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ratatui::style::{Color, Modifier, Style};
+    use serde_json::json;
+
+    use super::{nearest_16, nearest_256, BuiltinTheme, ColorDepth, Theme};
+
+    #[test]
+    fn themes_are_configured() {
+        let light: Theme = serde_json::from_value(json!("light")).unwrap();
+        assert_eq!(light, BuiltinTheme::Light.theme());
+
+        let custom: Theme = serde_json::from_value(json!({
+            "base": "high-contrast",
+            "selected": { "fg": "#ffffff", "bg": "#005f87", "reversed": false },
+        }))
+        .unwrap();
+        assert_eq!(
+            custom.selected,
+            Style::new()
+                .fg(Color::Rgb(255, 255, 255))
+                .bg(Color::Rgb(0, 95, 135))
+                .add_modifier(Modifier::BOLD)
+                .remove_modifier(Modifier::REVERSED)
+        );
+        assert_eq!(custom.help, BuiltinTheme::HighContrast.theme().help);
+
+        let error = serde_json::from_value::<Theme>(json!("ligth")).unwrap_err();
+        assert!(error.to_string().starts_with("unknown variant `ligth`"));
+        let error = serde_json::from_value::<Theme>(json!({ "help": { "fg": "bleu" } }));
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .starts_with("unknown color `bleu`"));
+        // Typos would otherwise be dropped without a word
+        let error = serde_json::from_value::<Theme>(json!({ "selcted": { "fg": "red" } }));
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .starts_with("unknown field `selcted`"));
+        let error =
+            serde_json::from_value::<Theme>(json!({ "help": { "bold": true, "ital": true } }));
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .starts_with("unknown field `ital`"));
+    }
+
+    #[test]
+    fn colors_are_brought_down() {
+        assert_eq!(nearest_256((0, 95, 135)), 24);
+        assert_eq!(nearest_256((128, 128, 128)), 244);
+        assert_eq!(nearest_16((0, 0, 175)), Color::Blue);
+        assert_eq!(nearest_16((250, 250, 250)), Color::White);
+
+        let theme = Theme {
+            selected: Style::new()
+                .bg(Color::Rgb(0, 0, 175))
+                .fg(Color::Indexed(231)),
+            ..Theme::default()
+        };
+        let selected = |depth| theme.adapt(depth).selected;
+        assert_eq!(selected(ColorDepth::TrueColor), theme.selected);
+        assert_eq!(
+            selected(ColorDepth::Ansi256),
+            Style::new().bg(Color::Indexed(19)).fg(Color::Indexed(231))
+        );
+        assert_eq!(
+            selected(ColorDepth::Ansi16),
+            Style::new().bg(Color::Blue).fg(Color::White)
+        );
+    }
+}
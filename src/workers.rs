@@ -0,0 +1,186 @@
+//! The threads talking to the backends, started at launch and again when their configuration
+//! changes while the TUI runs.
+
+use std::{
+    path::Path,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::JoinHandle,
+};
+
+use crate::{
+    file::{config::FileConfig, FileTransport},
+    imap::{config::ReadBackend, imap_thread},
+    jmap::{jmap_thread, JmapTransport},
+    maildir::MaildirStore,
+    mbox::MboxStore,
+    pop3::Pop3Store,
+    sendmail::Sendmail,
+    smtp::{self, config::SendBackend, outbox::Outbox},
+    store,
+};
+
+/// What a worker thread runs, built before the thread starts so a bad configuration is caught
+/// without stopping the worker it would replace.
+pub type Body<C, R> = Box<dyn FnOnce(Receiver<C>, Sender<R>) -> Result<(), crate::Error> + Send>;
+
+/// A backend thread and the channels to talk to it.
+pub struct Worker<C, R> {
+    pub tx: Sender<C>,
+    pub rx: Receiver<R>,
+    thread: Option<JoinHandle<Result<(), crate::Error>>>,
+}
+
+pub type StoreWorker = Worker<store::Command, store::Response>;
+pub type SendWorker = Worker<smtp::Command, smtp::Response>;
+
+impl<C, R> Worker<C, R>
+where
+    C: Send + 'static,
+    R: Send + 'static,
+{
+    pub fn spawn(body: Body<C, R>) -> Self {
+        let (tx, thread_rx) = channel();
+        let (thread_tx, rx) = channel();
+        let thread = std::thread::spawn(move || body(thread_rx, thread_tx));
+        Self {
+            tx,
+            rx,
+            thread: Some(thread),
+        }
+    }
+
+    /// Replace the thread with one running `body`, without waiting for the old one. With
+    /// `after_old`, `body` only runs once the old thread is done, e.g. when both would use the
+    /// same files. Commands sent in the meantime wait in the channel.
+    pub fn restart(&mut self, body: Body<C, R>, after_old: bool) {
+        // Dropping the channels tells the old thread to stop
+        let Self { thread, .. } = std::mem::replace(self, Self::idle());
+        let body = match thread.filter(|_| after_old) {
+            Some(old) => Box::new(move |rx, tx| {
+                // Its failure was the old configuration's, the new one may well work
+                let _ = join_thread(old);
+                body(rx, tx)
+            }),
+            None => body,
+        };
+        *self = Self::spawn(body);
+    }
+
+    /// A placeholder without a thread, only around while the worker is being replaced.
+    fn idle() -> Self {
+        let (tx, _) = channel();
+        let (_, rx) = channel();
+        Self {
+            tx,
+            rx,
+            thread: None,
+        }
+    }
+
+    /// Close the channels and wait for the thread to finish, failing if it panicked.
+    pub fn join(self) -> Result<(), crate::Error> {
+        let Self { tx, rx, thread } = self;
+        drop((tx, rx));
+        thread.map_or(Ok(()), join_thread)
+    }
+}
+
+/// Wait for a worker thread, failing if it panicked.
+fn join_thread(thread: JoinHandle<Result<(), crate::Error>>) -> Result<(), crate::Error> {
+    let Err(err) = thread.join() else {
+        return Ok(());
+    };
+    if err.is::<Box<dyn std::error::Error>>() {
+        tracing::error!(
+            "Thread panicked with error: {}",
+            err.downcast::<Box<dyn std::error::Error>>()
+                .expect("`.is` failed us")
+        );
+    } else {
+        tracing::error!("Thread panicked with error: {:?}", err);
+    }
+    Err(std::io::Error::other("Thread panic"))?
+}
+
+/// The thread serving the store `read` configures.
+pub fn store(read: ReadBackend) -> Result<Body<store::Command, store::Response>, crate::Error> {
+    Ok(match read {
+        ReadBackend::Imap(imap_config) => Box::new(|rx, tx| {
+            tracing::debug!("Launching IMAP thread");
+            imap_thread(imap_config, rx, tx)
+        }),
+        ReadBackend::Maildir(maildir_config) => {
+            let store = MaildirStore::new(maildir_config)?;
+            Box::new(|rx, tx| {
+                tracing::debug!("Launching Maildir thread");
+                store::run(store, rx, tx)
+            })
+        }
+        ReadBackend::Mbox(mbox_config) => {
+            let store = MboxStore::new(mbox_config)?;
+            Box::new(|rx, tx| {
+                tracing::debug!("Launching mbox thread");
+                store::run(store, rx, tx)
+            })
+        }
+        ReadBackend::Jmap(jmap_config) => Box::new(|rx, tx| {
+            tracing::debug!("Launching JMAP thread");
+            jmap_thread(jmap_config, rx, tx)
+        }),
+        ReadBackend::Pop3(pop3_config) => {
+            let store = Pop3Store::new(pop3_config)?;
+            Box::new(|rx, tx| {
+                tracing::debug!("Launching POP3 thread");
+                store::run(store, rx, tx)
+            })
+        }
+    })
+}
+
+/// The thread sending mail through `send`, or writing it to `dry_run` instead.
+pub fn sender(
+    send: SendBackend,
+    dry_run: Option<&Path>,
+) -> Result<Body<smtp::Command, smtp::Response>, crate::Error> {
+    // Dry runs get their own outbox, queued messages shouldn't end up on disk instead of sent
+    let (send, outbox) = match dry_run {
+        Some(dir) => (
+            SendBackend::File(FileConfig {
+                dir: dir.to_path_buf(),
+                from: send.sender().to_string(),
+            }),
+            Outbox::new(dir.join("outbox")),
+        ),
+        None => (send, Outbox::new(Outbox::default_dir())),
+    };
+    Ok(match send {
+        SendBackend::Smtp(smtp_config) => {
+            let client = smtp::Client::new(smtp_config)?;
+            Box::new(|rx, tx| {
+                tracing::debug!("Launching SMTP thread");
+                smtp::run(client, outbox, rx, tx)
+            })
+        }
+        SendBackend::Jmap(jmap_config) => {
+            let transport = JmapTransport::new(jmap_config);
+            Box::new(|rx, tx| {
+                tracing::debug!("Launching JMAP submission thread");
+                smtp::run(transport, outbox, rx, tx)
+            })
+        }
+        SendBackend::Sendmail(sendmail_config) => {
+            let transport = Sendmail::new(sendmail_config);
+            Box::new(|rx, tx| {
+                tracing::debug!("Launching sendmail thread");
+                smtp::run(transport, outbox, rx, tx)
+            })
+        }
+        SendBackend::File(file_config) => {
+            let transport = FileTransport::new(file_config);
+            Box::new(|rx, tx| {
+                tracing::debug!("Launching file thread");
+                smtp::run(transport, outbox, rx, tx)
+            })
+        }
+    })
+}